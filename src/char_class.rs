@@ -0,0 +1,348 @@
+//! Character classes used as single-char grammar elements.
+//!
+//! A class is written like `[0-9]`, `[a-zA-Z_]` or `[^"\n]`, and `.` matches any
+//! char. Classes are normalized into sorted, non-overlapping inclusive ranges so
+//! they can be matched quickly and turned into range-aware set members for the
+//! FIRST/FOLLOW/steuer machinery.
+
+use std::fmt;
+
+use crate::errors::GrammarError::UnexpectedElementError;
+use crate::errors::ParserError;
+use crate::errors::ParserError::EndOfCharsError;
+use crate::parse_funcs::parse_escape;
+use crate::peekables::{ParseProcess, PeekableWrapper, TPeekable};
+use crate::sets::{SetMember, SetMemberWithEmpty};
+
+/// Source text of the class that matches any char.
+pub const ANY_CHAR: &str = ".";
+
+/// Chars that stand for themselves when escaped inside brackets, on top of the usual escapes.
+const CLASS_ESCAPES: [char; 4] = ['[', ']', '-', '^'];
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct CharClass {
+    ranges: Vec<(char, char)>,
+}
+
+impl CharClass {
+    /// Builds a class from arbitrary inclusive ranges. Overlapping and adjacent ranges are merged.
+    #[must_use]
+    pub fn new(mut ranges: Vec<(char, char)>, negated: bool) -> CharClass {
+        ranges.sort_unstable();
+        let mut merged: Vec<(char, char)> = vec![];
+        for (lo, hi) in ranges {
+            match merged.last_mut() {
+                Some((_, last_hi)) if char_after(*last_hi).is_none_or(|next| lo <= next) => {
+                    *last_hi = (*last_hi).max(hi);
+                }
+                _ => merged.push((lo, hi)),
+            }
+        }
+        let class = CharClass { ranges: merged };
+        if negated { class.complement() } else { class }
+    }
+
+    /// The class behind `.`, matching every char.
+    #[must_use]
+    pub fn any() -> CharClass {
+        CharClass {
+            ranges: vec![(char::MIN, char::MAX)],
+        }
+    }
+
+    /// Parses the source text of a class, e.g. `[0-9]` or `.`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ParserError`] if `source` is not a well-formed class.
+    pub fn from_source(source: &str) -> Result<CharClass, ParserError> {
+        let mut peekable = PeekableWrapper::from_str(source);
+        let mut to_parse = ParseProcess::new(&mut peekable, None, None);
+        let (_, class) = parse_char_class(&mut to_parse)?;
+        Ok(class)
+    }
+
+    #[must_use]
+    pub fn contains(&self, chr: char) -> bool {
+        self.ranges
+            .binary_search_by(|(lo, hi)| {
+                if *hi < chr {
+                    std::cmp::Ordering::Less
+                } else if *lo > chr {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+
+    #[must_use]
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    /// The set members a class contributes to FIRST sets: one per range.
+    #[must_use]
+    pub fn set_members(&self) -> Vec<SetMember> {
+        self.ranges
+            .iter()
+            .map(|&(lo, hi)| SetMember::from_range(lo, hi))
+            .collect()
+    }
+
+    #[must_use]
+    pub fn set_members_with_empty(&self) -> Vec<SetMemberWithEmpty> {
+        self.ranges
+            .iter()
+            .map(|&(lo, hi)| SetMemberWithEmpty::from_range(lo, hi))
+            .collect()
+    }
+
+    fn complement(&self) -> CharClass {
+        let mut ranges = vec![];
+        let mut next_start = Some(char::MIN);
+        for &(lo, hi) in &self.ranges {
+            if let Some(start) = next_start
+                && start < lo
+                && let Some(end) = char_before(lo)
+            {
+                ranges.push((start, end));
+            }
+            next_start = char_after(hi);
+        }
+        if let Some(start) = next_start {
+            ranges.push((start, char::MAX));
+        }
+        CharClass { ranges }
+    }
+}
+
+impl fmt::Display for CharClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        for (lo, hi) in &self.ranges {
+            if lo == hi {
+                write!(f, "{}", lo.escape_debug())?;
+            } else {
+                write!(f, "{}-{}", lo.escape_debug(), hi.escape_debug())?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+/// The next char in code point order, skipping the surrogate gap.
+#[must_use]
+pub fn char_after(chr: char) -> Option<char> {
+    match chr {
+        '\u{D7FF}' => Some('\u{E000}'),
+        char::MAX => None,
+        _ => char::from_u32(chr as u32 + 1),
+    }
+}
+
+/// The previous char in code point order, skipping the surrogate gap.
+#[must_use]
+pub fn char_before(chr: char) -> Option<char> {
+    match chr {
+        '\u{E000}' => Some('\u{D7FF}'),
+        char::MIN => None,
+        _ => char::from_u32(chr as u32 - 1),
+    }
+}
+
+/// Parses a class element (`[...]` or `.`) and returns its source text together with the class.
+///
+/// Inside brackets the escapes of terminals work as well, and `\[`, `\]`, `\-` and `\^` stand
+/// for themselves.
+///
+/// # Errors
+///
+/// Returns a [`ParserError`] if the input does not start with a class, a range is reversed, the
+/// class is empty or the closing bracket is missing.
+pub fn parse_char_class<T>(
+    to_parse: &mut ParseProcess<T>,
+) -> Result<(String, CharClass), ParserError>
+where
+    T: TPeekable<Item = char>,
+{
+    let pos = to_parse.cur_pos();
+    match to_parse.next() {
+        Some('.') => return Ok((String::from(ANY_CHAR), CharClass::any())),
+        Some('[') => {}
+        Some(chr) => {
+            return Err(ParserError::UnexpectedCharError {
+                chr,
+                pos,
+                expected: String::from("[ or . for a character class"),
+                rules: vec![],
+                repairs: vec![],
+            });
+        }
+        None => {
+            return Err(EndOfCharsError {
+                pos,
+                rules: vec![],
+                repairs: vec![],
+            });
+        }
+    }
+
+    let mut source = String::from("[");
+    let negated = to_parse.next_if_eq(&'^').is_some();
+    if negated {
+        source.push('^');
+    }
+
+    let mut ranges = vec![];
+    loop {
+        let pos = to_parse.cur_pos();
+        if to_parse.next_if_eq(&']').is_some() {
+            source.push(']');
+            break;
+        }
+        let lo = parse_class_char(to_parse, &mut source)?;
+        let is_range = to_parse.peek() == Some(&'-');
+        if !is_range {
+            ranges.push((lo, lo));
+            continue;
+        }
+        to_parse.next();
+        source.push('-');
+        if to_parse.peek() == Some(&']') {
+            // a trailing '-' is a literal dash
+            ranges.push((lo, lo));
+            ranges.push(('-', '-'));
+            continue;
+        }
+        let hi = parse_class_char(to_parse, &mut source)?;
+        if hi < lo {
+            return Err(ParserError::GramError {
+                err: UnexpectedElementError {
+                    reason: format!("character range {lo:?}-{hi:?} is reversed"),
+                    pos,
+                },
+            });
+        }
+        ranges.push((lo, hi));
+    }
+
+    if ranges.is_empty() {
+        return Err(ParserError::GramError {
+            err: UnexpectedElementError {
+                reason: String::from("character class is empty"),
+                pos,
+            },
+        });
+    }
+    Ok((source, CharClass::new(ranges, negated)))
+}
+
+fn parse_class_char<T>(
+    to_parse: &mut ParseProcess<T>,
+    source: &mut String,
+) -> Result<char, ParserError>
+where
+    T: TPeekable<Item = char>,
+{
+    let pos = to_parse.cur_pos();
+    let chr = to_parse.next().ok_or(EndOfCharsError {
+        pos,
+        rules: vec![],
+        repairs: vec![],
+    })?;
+    if chr != '\\' {
+        source.push(chr);
+        return Ok(chr);
+    }
+    let chr = parse_escape(to_parse, pos, &CLASS_ESCAPES)?;
+    match chr {
+        '\n' => source.push_str("\\n"),
+        '\t' => source.push_str("\\t"),
+        '\r' => source.push_str("\\r"),
+        '\\' | '[' | ']' | '-' | '^' => {
+            source.push('\\');
+            source.push(chr);
+        }
+        _ => source.push(chr),
+    }
+    Ok(chr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        let class = CharClass::from_source("[0-9]").unwrap();
+        assert_eq!(&[('0', '9')], class.ranges());
+        assert!(class.contains('5'));
+        assert!(!class.contains('a'));
+    }
+
+    #[test]
+    fn test_parse_multiple_ranges_are_merged() {
+        let class = CharClass::from_source("[a-zA-Z_b-d]").unwrap();
+        assert_eq!(&[('A', 'Z'), ('_', '_'), ('a', 'z')], class.ranges());
+    }
+
+    #[test]
+    fn test_parse_negated() {
+        let class = CharClass::from_source("[^\"\\n]").unwrap();
+        assert!(class.contains('a'));
+        assert!(class.contains('\u{10FFFF}'));
+        assert!(!class.contains('"'));
+        assert!(!class.contains('\n'));
+    }
+
+    #[test]
+    fn test_parse_any() {
+        let class = CharClass::from_source(".").unwrap();
+        assert!(class.contains('\0'));
+        assert!(class.contains('ß'));
+    }
+
+    #[test]
+    fn test_parse_trailing_dash_is_literal() {
+        let class = CharClass::from_source("[+-]").unwrap();
+        assert!(class.contains('+'));
+        assert!(class.contains('-'));
+        assert!(!class.contains(','));
+    }
+
+    #[test]
+    fn test_parse_reversed_range_fails() {
+        assert!(CharClass::from_source("[9-0]").is_err());
+    }
+
+    #[test]
+    fn test_parse_empty_class_fails() {
+        assert!(CharClass::from_source("[]").is_err());
+    }
+
+    #[test]
+    fn test_complement_skips_surrogates() {
+        let class = CharClass::new(vec![('\u{E000}', char::MAX)], true);
+        assert_eq!(&[(char::MIN, '\u{D7FF}')], class.ranges());
+    }
+
+    #[test]
+    fn test_parse_escapes_keep_source_parseable() {
+        let mut peekable = PeekableWrapper::from_str("[\\]\\u{41}\\n]");
+        let mut to_parse = ParseProcess::new(&mut peekable, None, None);
+        let (source, class) = parse_char_class(&mut to_parse).unwrap();
+        assert_eq!("[\\]A\\n]", source);
+        assert_eq!(class, CharClass::from_source(&source).unwrap());
+        assert!(class.contains(']'));
+        assert!(class.contains('A'));
+        assert!(class.contains('\n'));
+    }
+
+    #[test]
+    fn test_parse_unknown_escape_fails() {
+        assert!(CharClass::from_source("[\\q]").is_err());
+    }
+}
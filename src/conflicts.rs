@@ -0,0 +1,409 @@
+//! Explain why a grammar is not LL(1).
+//!
+//! Building the steuer maps stops at the first clash of a rule. [`find_conflicts`] instead looks at
+//! every pair of alternatives of every rule and reports the lookaheads they share, whether both
+//! alternatives can start with them (FIRST/FIRST) or one of them is chosen by what follows the
+//! rule (FIRST/FOLLOW), and a shortest input that runs into the conflict.
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::errors::GrammarError;
+use crate::first_sets::first_set_of_partial;
+use crate::parser_data::{ElementIndex, ElementType, ParserData, Production};
+use crate::sets::{NamedSets, NamedSetsNoEmpty, SetMember, SetMemberWithEmpty};
+use crate::steuer_map::{leading_terminal, steuer_keys_of_production, terminals_clash};
+use crate::steuer_sets::get_steuer_sets;
+use crate::vms::VM;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// Both alternatives can start with the shared lookahead.
+    FirstFirst,
+    /// One alternative can derive the empty string and the shared lookahead can follow the rule.
+    FirstFollow,
+}
+
+impl fmt::Display for ConflictKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConflictKind::FirstFirst => write!(f, "FIRST/FIRST"),
+            ConflictKind::FirstFollow => write!(f, "FIRST/FOLLOW"),
+        }
+    }
+}
+
+/// Two alternatives of a rule that one char of lookahead can't tell apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub rule: ElementIndex,
+    pub rule_name: String,
+    /// The competing alternatives in grammar notation, in the order they were written.
+    pub alternatives: [String; 2],
+    /// The lookaheads both alternatives are chosen by, sorted.
+    pub shared: Vec<SetMember>,
+    pub kind: ConflictKind,
+    /// A shortest input that reaches the rule and continues with a shared lookahead. `None` if
+    /// the rule can't be reached from an entry point.
+    pub example: Option<String>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let shared: Vec<String> = self.shared.iter().map(SetMember::to_string).collect();
+        write!(
+            f,
+            "{} conflict between {} and {} on {}",
+            self.kind,
+            self.alternatives[0],
+            self.alternatives[1],
+            shared.join(", ")
+        )?;
+        if let Some(example) = &self.example {
+            write!(f, ", e.g. input {example:?}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Finds every pair of alternatives that conflict, sorted by rule and alternative. Alternatives
+/// that start with different terminals sharing their first char don't conflict, the parser picks
+/// the longest matching terminal for them, unless that terminal can also be read by the other
+/// alternative (see [`terminals_clash`]).
+///
+/// # Errors
+///
+/// Returns a [`GrammarError`] if a rule, element or FIRST/FOLLOW set the search needs is
+/// missing from `parser_data`, `first_sets` or `follow_sets`.
+pub fn find_conflicts<T>(
+    first_sets: &NamedSets,
+    follow_sets: &NamedSetsNoEmpty,
+    parser_data: &ParserData<T>,
+    entry_points: &[ElementIndex],
+) -> Result<Vec<Conflict>, GrammarError>
+where
+    T: VM,
+{
+    let steuer_sets = get_steuer_sets(first_sets, follow_sets)?;
+    let shortest = shortest_inputs(parser_data);
+    let prefixes = shortest_prefixes(parser_data, entry_points, &shortest);
+    let mut rules: Vec<ElementIndex> = parser_data.parse_rules.rules.keys().copied().collect();
+    rules.sort_unstable();
+
+    let mut conflicts = vec![];
+    for rule in rules {
+        let productions = &parser_data
+            .get_rule_by_key(rule)
+            .ok_or(GrammarError::MissingProduction { index: rule })?
+            .possible_productions;
+        let mut keys = vec![];
+        let mut firsts = vec![];
+        for production in productions {
+            let (prod_keys, _) = steuer_keys_of_production(
+                &steuer_sets,
+                follow_sets,
+                production,
+                rule,
+                parser_data,
+            )?;
+            keys.push(prod_keys);
+            firsts.push(first_of_production(production, first_sets, parser_data)?);
+        }
+        let leading: Vec<Option<String>> = productions
+            .iter()
+            .map(|production| leading_terminal(production, parser_data))
+            .collect();
+
+        for first in 0..productions.len() {
+            for second in first + 1..productions.len() {
+                let clash = match (&leading[first], &leading[second]) {
+                    (Some(first_terminal), Some(second_terminal)) => terminals_clash(
+                        (first_terminal, &productions[first]),
+                        (second_terminal, &productions[second]),
+                        rule,
+                        first_sets,
+                        follow_sets,
+                        parser_data,
+                    )?,
+                    _ => true,
+                };
+                let mut shared: Vec<SetMember> = keys[first]
+                    .iter()
+                    .flat_map(|key| {
+                        keys[second]
+                            .iter()
+                            .filter_map(|other| key.intersection(other))
+                    })
+                    .filter(|&member| clash || !split_by_terminals(member, &keys, &leading))
+                    .collect();
+                if shared.is_empty() {
+                    continue;
+                }
+                shared.sort_unstable();
+                shared.dedup();
+                let kind = if shared.iter().any(|&member| {
+                    overlaps_any(member, &firsts[first]) && overlaps_any(member, &firsts[second])
+                }) {
+                    ConflictKind::FirstFirst
+                } else {
+                    ConflictKind::FirstFollow
+                };
+                let example = prefixes.get(&rule).map(|prefix| {
+                    let mut example = prefix.clone();
+                    example.extend(shared.iter().copied().find_map(example_char));
+                    example
+                });
+                conflicts.push(Conflict {
+                    rule,
+                    rule_name: parser_data
+                        .get_element_data(rule)
+                        .map_or_else(|| rule.to_string(), |data| data.name.clone()),
+                    alternatives: [
+                        parser_data.production_notation(rule, &productions[first]),
+                        parser_data.production_notation(rule, &productions[second]),
+                    ],
+                    shared,
+                    kind,
+                    example,
+                });
+            }
+        }
+    }
+    Ok(conflicts)
+}
+
+fn first_of_production<T>(
+    production: &Production,
+    first_sets: &NamedSets,
+    parser_data: &ParserData<T>,
+) -> Result<HashSet<SetMember>, GrammarError>
+where
+    T: VM,
+{
+    let Production::NotEmpty(el_ixs) = production else {
+        return Ok(HashSet::new());
+    };
+    Ok(first_set_of_partial(el_ixs, first_sets, parser_data)?
+        .into_iter()
+        .filter_map(|member| match member {
+            SetMemberWithEmpty::Char(chr) => Some(SetMember::Char(chr)),
+            SetMemberWithEmpty::Range(lo, hi) => Some(SetMember::Range(lo, hi)),
+            SetMemberWithEmpty::Empty | SetMemberWithEmpty::Terminate => None,
+        })
+        .collect())
+}
+
+fn overlaps_any(member: SetMember, set: &HashSet<SetMember>) -> bool {
+    set.iter().any(|other| other.overlaps(&member))
+}
+
+/// True if the parser chooses by whole terminals for this char: every alternative that can start
+/// with it starts with a terminal.
+fn split_by_terminals(
+    member: SetMember,
+    keys: &[Vec<SetMember>],
+    leading: &[Option<String>],
+) -> bool {
+    if !matches!(member, SetMember::Char(_)) {
+        return false;
+    }
+    keys.iter()
+        .zip(leading)
+        .filter(|(prod_keys, _)| prod_keys.iter().any(|key| key.overlaps(&member)))
+        .all(|(_, terminal)| terminal.is_some())
+}
+
+/// A char matched by the member, preferring visible ASCII. `None` for Terminate.
+fn example_char(member: SetMember) -> Option<char> {
+    let (lo, hi) = member.bounds()?;
+    if lo <= '~' && hi >= '!' {
+        Some(lo.max('!'))
+    } else {
+        Some(lo)
+    }
+}
+
+/// The shortest input every productive non-terminal can derive.
+fn shortest_inputs<T>(parser_data: &ParserData<T>) -> HashMap<ElementIndex, String>
+where
+    T: VM,
+{
+    let mut shortest: HashMap<ElementIndex, String> = HashMap::new();
+    let mut changes = true;
+    while changes {
+        changes = false;
+        for (&rule, nt_rules) in &parser_data.parse_rules.rules {
+            for production in &nt_rules.possible_productions {
+                let input = match &**production {
+                    Production::Empty => Some(String::new()),
+                    Production::NotEmpty(el_ixs) => {
+                        shortest_of_elements(el_ixs, &shortest, parser_data)
+                    }
+                };
+                if let Some(input) = input
+                    && shortest
+                        .get(&rule)
+                        .is_none_or(|known| input.chars().count() < known.chars().count())
+                {
+                    shortest.insert(rule, input);
+                    changes = true;
+                }
+            }
+        }
+    }
+    shortest
+}
+
+fn shortest_of_elements<T>(
+    el_ixs: &[ElementIndex],
+    shortest: &HashMap<ElementIndex, String>,
+    parser_data: &ParserData<T>,
+) -> Option<String>
+where
+    T: VM,
+{
+    let mut input = String::new();
+    for &el_ix in el_ixs {
+        match parser_data.element_types[el_ix] {
+            ElementType::Terminal => input.push_str(&parser_data.get_element_data(el_ix)?.name),
+            ElementType::CharClass => input.push(
+                parser_data
+                    .get_char_class(el_ix)?
+                    .set_members()
+                    .into_iter()
+                    .find_map(example_char)?,
+            ),
+            ElementType::NonTerminal => input.push_str(shortest.get(&el_ix)?),
+        }
+    }
+    Some(input)
+}
+
+/// The shortest input that leads from an entry point to the start of each reachable rule.
+fn shortest_prefixes<T>(
+    parser_data: &ParserData<T>,
+    entry_points: &[ElementIndex],
+    shortest: &HashMap<ElementIndex, String>,
+) -> HashMap<ElementIndex, String>
+where
+    T: VM,
+{
+    let mut prefixes: HashMap<ElementIndex, String> = entry_points
+        .iter()
+        .map(|&entry_point| (entry_point, String::new()))
+        .collect();
+    let mut changes = true;
+    while changes {
+        changes = false;
+        for (&rule, nt_rules) in &parser_data.parse_rules.rules {
+            let Some(prefix) = prefixes.get(&rule).cloned() else {
+                continue;
+            };
+            for production in &nt_rules.possible_productions {
+                let Production::NotEmpty(el_ixs) = &**production else {
+                    continue;
+                };
+                for (i, &el_ix) in el_ixs.iter().enumerate() {
+                    if parser_data.element_types[el_ix] != ElementType::NonTerminal {
+                        continue;
+                    }
+                    let Some(before) = shortest_of_elements(&el_ixs[..i], shortest, parser_data)
+                    else {
+                        break;
+                    };
+                    let candidate = format!("{prefix}{before}");
+                    if prefixes
+                        .get(&el_ix)
+                        .is_none_or(|known| candidate.chars().count() < known.chars().count())
+                    {
+                        prefixes.insert(el_ix, candidate);
+                        changes = true;
+                    }
+                }
+            }
+        }
+    }
+    prefixes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::first_sets::get_first_sets;
+    use crate::follow_sets::get_follow_sets_for_entry_points;
+    use crate::peekables::PeekableWrapper;
+    use crate::rule_parsing::RuleParser;
+    use crate::vms::NullVm;
+
+    fn conflicts_of(rules: &str) -> Vec<Conflict> {
+        let mut peekable = PeekableWrapper::from_str(rules);
+        let vm = NullVm::new();
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+        let parser_data = rule_parser.parser_data;
+        let entry_points = parser_data.entry_points();
+        let first = get_first_sets(&parser_data).unwrap();
+        let follow = get_follow_sets_for_entry_points(&entry_points, &first, &parser_data).unwrap();
+        find_conflicts(&first, &follow, &parser_data, &entry_points).unwrap()
+    }
+
+    #[test]
+    fn test_first_first_conflict() {
+        let conflicts = conflicts_of(
+            "start -> \"(\" value \")\";\
+            value -> [0-9] | \"5\" \"!\" | \"x\";",
+        );
+        assert_eq!(1, conflicts.len());
+        let conflict = &conflicts[0];
+        assert_eq!("value", conflict.rule_name);
+        assert_eq!(ConflictKind::FirstFirst, conflict.kind);
+        assert_eq!(vec![SetMember::Char('5')], conflict.shared);
+        assert_eq!(Some(String::from("(5")), conflict.example);
+        assert_eq!(
+            "FIRST/FIRST conflict between [0-9] and \"5\" \"!\" on '5', e.g. input \"(5\"",
+            conflict.to_string()
+        );
+    }
+
+    #[test]
+    fn test_first_follow_conflict() {
+        let conflicts = conflicts_of(
+            "start -> \"x\" opt \"a\";\
+            opt -> \"a\" \"b\" | #;",
+        );
+        assert_eq!(1, conflicts.len());
+        assert_eq!(ConflictKind::FirstFollow, conflicts[0].kind);
+        assert_eq!(["\"a\" \"b\"", "#"], conflicts[0].alternatives);
+        assert_eq!(Some(String::from("xa")), conflicts[0].example);
+    }
+
+    #[test]
+    fn test_every_pair_is_reported() {
+        let conflicts = conflicts_of("start -> \"pop\" | \"print\" | \"pop\" \"1\" | [a-z];");
+        let pairs: Vec<[String; 2]> = conflicts
+            .into_iter()
+            .map(|conflict| conflict.alternatives)
+            .collect();
+        assert_eq!(
+            vec![
+                [String::from("\"pop\""), String::from("\"print\"")],
+                [String::from("\"pop\""), String::from("\"pop\" \"1\"")],
+                [String::from("\"pop\""), String::from("[a-z]")],
+                [String::from("\"print\""), String::from("\"pop\" \"1\"")],
+                [String::from("\"print\""), String::from("[a-z]")],
+                [String::from("\"pop\" \"1\""), String::from("[a-z]")],
+            ],
+            pairs
+        );
+    }
+
+    #[test]
+    fn test_terminals_sharing_a_char_do_not_conflict() {
+        assert!(conflicts_of("start -> \"print\" | \"pop\";").is_empty());
+        let conflicts = conflicts_of("start -> \"pop\" \"1\" | \"print\" | \"pop\" \"2\";");
+        assert_eq!(1, conflicts.len());
+        assert_eq!(
+            ["\"pop\" \"1\"", "\"pop\" \"2\""],
+            conflicts[0].alternatives
+        );
+    }
+}
@@ -0,0 +1,545 @@
+//! Collect every problem of a grammar instead of stopping at the first one.
+//!
+//! The checks run in stages. Syntax errors and the findings of [`validate`] (undefined
+//! non-terminals, unreachable and unproductive rules, duplicate alternatives) are collected
+//! first; the left recursion check and the LL(k) analysis need a complete grammar and only run
+//! if those stages found nothing. Every
+//! diagnostic names its rule and, for grammars read from text, its line and column.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::errors::GrammarError::{
+    LookaheadNotDistinct, SteuerSetsNotDistinct, UnexpectedElementError,
+};
+use crate::conflicts::Conflict;
+use crate::errors::{GrammarDiagnostic, GrammarError, ParserError};
+use crate::left_recursion::{cycle_names, find_left_recursion};
+use crate::parse_funcs::SourcePosition;
+use crate::parser_data::{ElementIndex, ElementType, ParserData, Production, is_generated_name};
+use crate::vms::VM;
+
+/// Turns the errors of [`RuleParser::parse_rules_recovering`](crate::rule_parsing::RuleParser::parse_rules_recovering)
+/// into diagnostics.
+#[must_use]
+pub fn syntax_diagnostics(
+    text: &str,
+    errors: Vec<(Option<String>, ParserError)>,
+) -> Vec<GrammarDiagnostic> {
+    errors
+        .into_iter()
+        .map(|(rule, err)| GrammarDiagnostic {
+            location: err.pos().map(|pos| SourcePosition::of(text, pos)),
+            message: describe_syntax_error(err),
+            rule,
+        })
+        .collect()
+}
+
+fn describe_syntax_error(err: ParserError) -> String {
+    match err {
+        ParserError::UnexpectedCharError { chr, expected, .. } => {
+            format!("unexpected {chr:?}, expected {expected}")
+        }
+        ParserError::EndOfCharsError { .. } => String::from("unexpected end of the grammar text"),
+        ParserError::UnknownSpecialOperation { operation, .. } => {
+            format!("unknown directive ${operation}")
+        }
+        ParserError::GramError {
+            err: UnexpectedElementError { reason, .. },
+        } => reason,
+        other => other.to_string(),
+    }
+}
+
+/// Reports every non-terminal that is used but has no rule.
+///
+/// Rules listed in `broken` had a syntax error in their definition and are not reported again.
+#[must_use]
+pub fn undefined_non_terminals<T>(
+    parser_data: &ParserData<T>,
+    text: Option<&str>,
+    broken: &[String],
+) -> Vec<GrammarDiagnostic>
+where
+    T: VM,
+{
+    let mut diagnostics = vec![];
+    for (ix, data) in parser_data.element_data.iter().enumerate() {
+        if parser_data.element_types[ix] != ElementType::NonTerminal
+            || parser_data.parse_rules.rules.contains_key(&ix)
+            || broken.contains(&data.name)
+        {
+            continue;
+        }
+        let reference = data.first_reference;
+        diagnostics.push(GrammarDiagnostic {
+            message: format!("non-terminal \"{}\" is used but never defined", data.name),
+            rule: reference
+                .and_then(|reference| reference.rule)
+                .map(|rule| rule_name(parser_data, rule)),
+            location: text
+                .zip(reference)
+                .map(|(text, reference)| SourcePosition::of(text, reference.pos)),
+        });
+    }
+    diagnostics
+}
+
+/// The diagnostic for a grammar without any rule to start parsing from.
+#[must_use]
+pub fn missing_entry_point() -> GrammarDiagnostic {
+    GrammarDiagnostic {
+        message: String::from(
+            "there is no entry point, declare one with $START or define a rule named start",
+        ),
+        rule: None,
+        location: None,
+    }
+}
+
+/// How the findings of a check of [`validate`] are reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The check does not run.
+    Allow,
+    /// Findings are reported, but the parser is still built.
+    Warning,
+    /// Findings keep the parser from being built.
+    Error,
+}
+
+/// The severity of each check of [`validate`].
+///
+/// A parser can't be built with undefined non-terminals, lowering their severity only helps
+/// when validating incomplete grammars.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidationLevels {
+    /// Non-terminals that are used but have no rule.
+    pub undefined: Severity,
+    /// Rules that can't be reached from an entry point.
+    pub unreachable: Severity,
+    /// Rules that can never derive a string of terminals, like `a -> "x" a;`.
+    pub unproductive: Severity,
+    /// Alternatives written twice in the same rule.
+    pub duplicate_alternatives: Severity,
+}
+
+impl Default for ValidationLevels {
+    fn default() -> Self {
+        ValidationLevels {
+            undefined: Severity::Error,
+            unreachable: Severity::Warning,
+            unproductive: Severity::Error,
+            duplicate_alternatives: Severity::Warning,
+        }
+    }
+}
+
+/// The findings of [`validate`], sorted by their severity.
+#[derive(Debug, Default)]
+pub struct Validation {
+    pub errors: Vec<GrammarDiagnostic>,
+    pub warnings: Vec<GrammarDiagnostic>,
+}
+
+impl Validation {
+    fn add(&mut self, severity: Severity, diagnostics: Vec<GrammarDiagnostic>) {
+        match severity {
+            Severity::Allow => {}
+            Severity::Warning => self.warnings.extend(diagnostics),
+            Severity::Error => self.errors.extend(diagnostics),
+        }
+    }
+}
+
+/// Checks a grammar for undefined non-terminals, unreachable and unproductive rules and
+/// duplicate alternatives. Rules listed in `broken` had a syntax error and are not reported as
+/// undefined.
+#[must_use]
+pub fn validate<T>(
+    parser_data: &ParserData<T>,
+    text: Option<&str>,
+    broken: &[String],
+    levels: ValidationLevels,
+) -> Validation
+where
+    T: VM,
+{
+    let mut validation = Validation::default();
+    if levels.undefined != Severity::Allow {
+        validation.add(
+            levels.undefined,
+            undefined_non_terminals(parser_data, text, broken),
+        );
+    }
+    if levels.unreachable != Severity::Allow {
+        validation.add(levels.unreachable, unreachable_rules(parser_data, text));
+    }
+    if levels.unproductive != Severity::Allow {
+        validation.add(levels.unproductive, unproductive_rules(parser_data, text));
+    }
+    if levels.duplicate_alternatives != Severity::Allow {
+        validation.add(
+            levels.duplicate_alternatives,
+            duplicate_alternatives(parser_data, text),
+        );
+    }
+    validation
+}
+
+/// Reports the rules written in the grammar that no entry point leads to. Generated rules are
+/// left out, they are unreachable exactly if the rule they were written in is. Without entry
+/// points nothing is reported, [`missing_entry_point`] covers that.
+#[must_use]
+pub fn unreachable_rules<T>(parser_data: &ParserData<T>, text: Option<&str>) -> Vec<GrammarDiagnostic>
+where
+    T: VM,
+{
+    let entry_points = parser_data.entry_points();
+    if entry_points.is_empty() {
+        return vec![];
+    }
+    let reached = parser_data.rules_reached_from(&entry_points);
+    sorted_rules(parser_data)
+        .into_iter()
+        .filter(|rule| !reached.contains(rule) && !is_generated_name(&rule_name(parser_data, *rule)))
+        .map(|rule| {
+            rule_diagnostic(
+                parser_data,
+                rule,
+                text,
+                String::from("rule can't be reached from an entry point"),
+            )
+        })
+        .collect()
+}
+
+/// Reports the rules that can never derive a string of terminals, because every alternative
+/// leads back into such a rule. Undefined non-terminals count as productive here, they are
+/// reported by [`undefined_non_terminals`].
+#[must_use]
+pub fn unproductive_rules<T>(parser_data: &ParserData<T>, text: Option<&str>) -> Vec<GrammarDiagnostic>
+where
+    T: VM,
+{
+    let rules = &parser_data.parse_rules.rules;
+    let mut productive: HashSet<ElementIndex> = HashSet::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (&rule, nt_rules) in rules {
+            if productive.contains(&rule) {
+                continue;
+            }
+            let derives_terminals = nt_rules
+                .possible_productions
+                .iter()
+                .any(|production| match &**production {
+                    Production::Empty => true,
+                    Production::NotEmpty(el_ixs) => el_ixs.iter().all(|ix| {
+                        parser_data.element_types[*ix] != ElementType::NonTerminal
+                            || !rules.contains_key(ix)
+                            || productive.contains(ix)
+                    }),
+                });
+            if derives_terminals {
+                productive.insert(rule);
+                changed = true;
+            }
+        }
+    }
+    sorted_rules(parser_data)
+        .into_iter()
+        .filter(|rule| !productive.contains(rule))
+        .map(|rule| {
+            rule_diagnostic(
+                parser_data,
+                rule,
+                text,
+                String::from("rule can never derive a string of terminals"),
+            )
+        })
+        .collect()
+}
+
+/// Reports alternatives that appear more than once in the same rule, once per repeated
+/// alternative.
+#[must_use]
+pub fn duplicate_alternatives<T>(
+    parser_data: &ParserData<T>,
+    text: Option<&str>,
+) -> Vec<GrammarDiagnostic>
+where
+    T: VM,
+{
+    let mut diagnostics = vec![];
+    for rule in sorted_rules(parser_data) {
+        let productions = &parser_data.parse_rules.rules[&rule].possible_productions;
+        let mut reported: Vec<&Production> = vec![];
+        for (ix, production) in productions.iter().enumerate() {
+            let repeated = productions[..ix]
+                .iter()
+                .any(|earlier| same_production(earlier, production));
+            if repeated && !reported.iter().any(|seen| same_production(seen, production)) {
+                reported.push(production);
+                let message = format!(
+                    "alternative {} is written more than once",
+                    parser_data.production_notation(rule, production)
+                );
+                diagnostics.push(rule_diagnostic(parser_data, rule, text, message));
+            }
+        }
+    }
+    diagnostics
+}
+
+fn same_production(first: &Production, second: &Production) -> bool {
+    match (first, second) {
+        (Production::Empty, Production::Empty) => true,
+        (Production::NotEmpty(first), Production::NotEmpty(second)) => first == second,
+        _ => false,
+    }
+}
+
+fn sorted_rules<T>(parser_data: &ParserData<T>) -> Vec<ElementIndex>
+where
+    T: VM,
+{
+    let mut rules: Vec<ElementIndex> = parser_data.parse_rules.rules.keys().copied().collect();
+    rules.sort_unstable();
+    rules
+}
+
+/// A diagnostic for a problem of `rule`, located at the rule's definition.
+#[must_use]
+pub fn rule_diagnostic<T>(
+    parser_data: &ParserData<T>,
+    rule: ElementIndex,
+    text: Option<&str>,
+    message: String,
+) -> GrammarDiagnostic
+where
+    T: VM,
+{
+    let defined_at = parser_data
+        .get_element_data(rule)
+        .and_then(|data| data.defined_at);
+    GrammarDiagnostic {
+        message,
+        rule: Some(rule_name(parser_data, rule)),
+        location: text
+            .zip(defined_at)
+            .map(|(text, pos)| SourcePosition::of(text, pos)),
+    }
+}
+
+/// Turns the steuer map failures of [`find_steuer_conflicts`](crate::steuer_map::find_steuer_conflicts)
+/// into diagnostics, one per pair of alternatives in `report` that belongs to a failing rule.
+/// Failures without such a pair, like those of LL(k) parsers, keep their error message.
+///
+/// `needed` holds the lookahead each rule would need, as computed by
+/// [`minimal_lookahead`](crate::lookahead::minimal_lookahead), which tested every k up to
+/// `max_k`; it is appended as a hint.
+pub fn conflict_diagnostics<T>(
+    parser_data: &ParserData<T>,
+    conflicts: Vec<(ElementIndex, GrammarError)>,
+    report: &[Conflict],
+    needed: &HashMap<ElementIndex, Option<usize>>,
+    max_k: usize,
+    text: Option<&str>,
+) -> Vec<GrammarDiagnostic>
+where
+    T: VM,
+{
+    let mut diagnostics = vec![];
+    for (rule, err) in conflicts {
+        let mut messages: Vec<String> = report
+            .iter()
+            .filter(|conflict| conflict.rule == rule)
+            .map(Conflict::to_string)
+            .collect();
+        if messages.is_empty() {
+            messages.push(match err {
+                SteuerSetsNotDistinct {
+                    steuer_char,
+                    steuer_terminal,
+                    ..
+                } if steuer_terminal == "follow_set" => format!(
+                    "not LL(1): {steuer_char:?} can start an alternative and also follow the rule"
+                ),
+                SteuerSetsNotDistinct { steuer_char, .. } => format!(
+                    "not LL(1): more than one alternative can start with {steuer_char:?}"
+                ),
+                LookaheadNotDistinct { lookahead, k, .. } => format!(
+                    "not LL({k}): more than one alternative can start with {lookahead}"
+                ),
+                other => other.to_string(),
+            });
+        }
+        for mut message in messages {
+            match needed.get(&rule) {
+                Some(Some(k)) => {
+                    let _ = write!(message, "; needs $LOOKAHEAD: {k}");
+                }
+                Some(None) => {
+                    let _ = write!(message, "; not LL(k) for any k up to {max_k}");
+                }
+                None => {}
+            }
+            diagnostics.push(rule_diagnostic(parser_data, rule, text, message));
+        }
+    }
+    diagnostics
+}
+
+/// Reports every left-recursive cycle, located at the definition of the rule it starts with.
+#[must_use]
+pub fn left_recursion_diagnostics<T>(
+    parser_data: &ParserData<T>,
+    text: Option<&str>,
+) -> Vec<GrammarDiagnostic>
+where
+    T: VM,
+{
+    find_left_recursion(parser_data)
+        .into_iter()
+        .map(|cycle| {
+            let message = format!(
+                "left recursion: {}",
+                cycle_names(&cycle, parser_data).join(" -> ")
+            );
+            rule_diagnostic(parser_data, cycle[0], text, message)
+        })
+        .collect()
+}
+
+/// A diagnostic for an error of the FIRST/FOLLOW computation.
+#[must_use]
+pub fn grammar_error_diagnostic<T>(
+    parser_data: &ParserData<T>,
+    err: &GrammarError,
+    text: Option<&str>,
+) -> GrammarDiagnostic
+where
+    T: VM,
+{
+    match err {
+        GrammarError::MissingFollowSet { index }
+        | GrammarError::MissingFirstSet { index }
+        | GrammarError::MissingSteuerSet { index }
+        | GrammarError::MissingProduction { index } => {
+            rule_diagnostic(parser_data, *index, text, err.to_string())
+        }
+        _ => GrammarDiagnostic {
+            message: err.to_string(),
+            rule: None,
+            location: None,
+        },
+    }
+}
+
+fn rule_name<T>(parser_data: &ParserData<T>, rule: ElementIndex) -> String
+where
+    T: VM,
+{
+    parser_data
+        .get_element_data(rule)
+        .map_or_else(|| rule.to_string(), |data| data.name.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peekables::PeekableWrapper;
+    use crate::rule_parsing::RuleParser;
+    use crate::vms::NullVm;
+
+    #[test]
+    fn test_undefined_non_terminals() {
+        let text = "start -> a b;\na -> \"x\" c;";
+        let mut peekable = PeekableWrapper::from_str(text);
+        let vm = NullVm::new();
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+
+        let diagnostics = undefined_non_terminals(&rule_parser.parser_data, Some(text), &[]);
+        assert_eq!(2, diagnostics.len());
+        assert_eq!(Some(String::from("start")), diagnostics[0].rule);
+        assert_eq!("1:12", diagnostics[0].location.unwrap().to_string());
+        assert_eq!(Some(String::from("a")), diagnostics[1].rule);
+        assert_eq!("2:10", diagnostics[1].location.unwrap().to_string());
+    }
+
+    fn parse(text: &str) -> ParserData<NullVm> {
+        let mut peekable = PeekableWrapper::from_str(text);
+        let vm = NullVm::new();
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+        rule_parser.parser_data
+    }
+
+    fn rules_of(diagnostics: &[GrammarDiagnostic]) -> Vec<String> {
+        diagnostics
+            .iter()
+            .filter_map(|diagnostic| diagnostic.rule.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_unreachable_rules() {
+        let text = "$IGNORE: space;\nstart -> a (\"x\")*;\na -> \"y\";\nb -> c;\nc -> \"z\";\n\
+            $IGNORE: #;\nspace -> \" \"*;";
+        let parser_data = parse(text);
+        let diagnostics = unreachable_rules(&parser_data, Some(text));
+        assert_eq!(vec!["b", "c"], rules_of(&diagnostics));
+        assert_eq!("4:1", diagnostics[0].location.unwrap().to_string());
+    }
+
+    #[test]
+    fn test_unproductive_rules() {
+        let text = "start -> a | \"x\";\na -> \"y\" b;\nb -> \"z\" a;\nc -> d;";
+        let parser_data = parse(text);
+        assert_eq!(
+            vec!["a", "b"],
+            rules_of(&unproductive_rules(&parser_data, Some(text)))
+        );
+    }
+
+    #[test]
+    fn test_duplicate_alternatives() {
+        let text = "start -> \"x\" a | # | \"x\" a | # | \"x\" a;\na -> \"y\";";
+        let parser_data = parse(text);
+        let messages: Vec<String> = duplicate_alternatives(&parser_data, Some(text))
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect();
+        assert_eq!(
+            vec![
+                "alternative \"x\" a is written more than once",
+                "alternative # is written more than once"
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn test_validate_levels() {
+        let text = "start -> \"x\" | \"x\" | a;\na -> \"y\" a;\nb -> \"z\" c;";
+        let parser_data = parse(text);
+
+        let validation = validate(&parser_data, Some(text), &[], ValidationLevels::default());
+        assert_eq!(vec!["b", "a"], rules_of(&validation.errors));
+        assert_eq!(vec!["b", "start"], rules_of(&validation.warnings));
+
+        let levels = ValidationLevels {
+            undefined: Severity::Warning,
+            unreachable: Severity::Allow,
+            unproductive: Severity::Allow,
+            duplicate_alternatives: Severity::Error,
+        };
+        let validation = validate(&parser_data, Some(text), &[], levels);
+        assert_eq!(vec!["start"], rules_of(&validation.errors));
+        assert_eq!(vec!["b"], rules_of(&validation.warnings));
+    }
+}
@@ -0,0 +1,261 @@
+//! Graphviz DOT output of the dependencies between rules.
+//!
+//! [`rule_graph_dot`] draws which rules reference which, [`follow_graph_dot`] the graph FOLLOW
+//! sets are propagated along (see [`get_follow_graph`]). Both write a `digraph` that can be
+//! rendered with the standard Graphviz tools, e.g. `dot -Tsvg`. Nodes are named `n` followed by
+//! their element index and labeled with the name from the grammar.
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::errors::{GrammarDiagnostic, GrammarDiagnostics, GrammarError};
+use crate::first_sets::get_first_sets;
+use crate::follow_sets::{get_follow_graph, get_follow_sets_for_entry_points};
+use crate::parser_data::{ElementIndex, ElementType, ParserData, Production};
+use crate::script_parser::transformed_parser_data;
+use crate::sets::SetMember;
+use crate::vms::VM;
+
+/// The graphs of a grammar that can be written as DOT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DotGraph {
+    Rules,
+    Follow,
+}
+
+impl FromStr for DotGraph {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rules" => Ok(DotGraph::Rules),
+            "follow" => Ok(DotGraph::Follow),
+            other => Err(format!(
+                "unknown graph \"{other}\", expected rules or follow"
+            )),
+        }
+    }
+}
+
+/// Writes a graph of a grammar text as DOT, after the transforms it opted into. Syntax errors
+/// and errors computing the FOLLOW sets are returned as diagnostics.
+///
+/// # Errors
+///
+/// Returns [`GrammarDiagnostics`] for syntax errors and, for [`DotGraph::Follow`], errors
+/// computing the FIRST/FOLLOW sets.
+pub fn dot_from_text<T>(
+    rule_text: &str,
+    vm: &T,
+    graph: DotGraph,
+    options: DotOptions,
+) -> Result<String, GrammarDiagnostics>
+where
+    T: VM,
+{
+    let parser_data = transformed_parser_data(rule_text, vm)?;
+    match graph {
+        DotGraph::Rules => Ok(rule_graph_dot(&parser_data, options)),
+        DotGraph::Follow => {
+            follow_graph_dot(&parser_data, &parser_data.entry_points()).map_err(|err| {
+                GrammarDiagnostics {
+                    diagnostics: vec![GrammarDiagnostic {
+                        message: err.to_string(),
+                        rule: None,
+                        location: None,
+                    }],
+                }
+            })
+        }
+    }
+}
+
+/// What [`rule_graph_dot`] draws besides the rules.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DotOptions {
+    /// Draw terminals and char classes as boxes with edges from the rules using them.
+    pub terminals: bool,
+}
+
+/// The rule reference graph: an edge from every rule to the rules it uses.
+///
+/// Edges are labeled with the alternative and the position in it, both counted from 1 and
+/// without the woven in ignore rule, so `2.1` is the first element of the second alternative.
+/// Several uses of the same rule share one edge. The ignore rule gets one dashed edge from every
+/// rule it is woven into. Entry points are drawn with a double border.
+#[must_use]
+pub fn rule_graph_dot<T>(parser_data: &ParserData<T>, options: DotOptions) -> String
+where
+    T: VM,
+{
+    let mut nodes: HashSet<ElementIndex> = parser_data.parse_rules.rules.keys().copied().collect();
+    let mut edges: BTreeMap<(ElementIndex, ElementIndex), Vec<String>> = BTreeMap::new();
+    let mut ignore_edges: BTreeSet<(ElementIndex, ElementIndex)> = BTreeSet::new();
+    for (&rule, nt_rules) in &parser_data.parse_rules.rules {
+        for (alternative, production) in nt_rules.possible_productions.iter().enumerate() {
+            let Production::NotEmpty(el_ixs) = &**production else {
+                continue;
+            };
+            let written = el_ixs.iter().filter(|&&ix| Some(ix) != nt_rules.ignore);
+            for (position, &ix) in written.enumerate() {
+                let is_rule = parser_data.element_types.get(ix) == Some(&ElementType::NonTerminal);
+                if !is_rule && !options.terminals {
+                    continue;
+                }
+                nodes.insert(ix);
+                edges.entry((rule, ix)).or_default().push(format!(
+                    "{}.{}",
+                    alternative + 1,
+                    position + 1
+                ));
+            }
+            if let Some(ignore) = nt_rules.ignore.filter(|ignore| el_ixs.contains(ignore)) {
+                nodes.insert(ignore);
+                ignore_edges.insert((rule, ignore));
+            }
+        }
+    }
+
+    let entry_points = parser_data.entry_points();
+    let mut nodes: Vec<ElementIndex> = nodes.into_iter().collect();
+    nodes.sort_unstable();
+    let mut dot = String::from("digraph rules {\n");
+    for ix in nodes {
+        let label = dot_string(&parser_data.element_notation(ix));
+        let _ = match parser_data.element_types.get(ix) {
+            Some(ElementType::NonTerminal) if entry_points.contains(&ix) => {
+                writeln!(dot, "    n{ix} [label={label}, peripheries=2];")
+            }
+            Some(ElementType::NonTerminal) => writeln!(dot, "    n{ix} [label={label}];"),
+            _ => writeln!(dot, "    n{ix} [label={label}, shape=box];"),
+        };
+    }
+    for ((source, target), positions) in &edges {
+        let label = dot_string(&positions.join(", "));
+        let _ = writeln!(dot, "    n{source} -> n{target} [label={label}];");
+    }
+    for (source, target) in &ignore_edges {
+        let _ = writeln!(dot, "    n{source} -> n{target} [style=dashed];");
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// The FOLLOW propagation graph of a grammar parsed from `entry_points`.
+///
+/// Every rule is labeled with the members its FOLLOW set gets directly and its complete FOLLOW
+/// set. An edge from `a` to `b` means the FOLLOW set of `a` is part of the FOLLOW set of `b`.
+///
+/// # Errors
+///
+/// Returns a [`GrammarError`] if the FIRST/FOLLOW sets can't be computed, e.g. for a
+/// left-recursive grammar.
+pub fn follow_graph_dot<T>(
+    parser_data: &ParserData<T>,
+    entry_points: &[ElementIndex],
+) -> Result<String, GrammarError>
+where
+    T: VM,
+{
+    let first_sets = get_first_sets(parser_data)?;
+    let follow_graph = get_follow_graph(entry_points, &first_sets, parser_data)?;
+    let follow_sets = get_follow_sets_for_entry_points(entry_points, &first_sets, parser_data)?;
+
+    let mut rules: Vec<ElementIndex> = parser_data.parse_rules.rules.keys().copied().collect();
+    rules.sort_unstable();
+    let mut dot = String::from("digraph follow {\n    node [shape=box];\n");
+    for rule in rules {
+        let direct = &follow_graph.get_node(rule)?.data;
+        let follow = follow_sets
+            .get(&rule)
+            .ok_or(GrammarError::MissingFollowSet { index: rule })?;
+        let label = format!(
+            "{}\ndirect: {}\nFOLLOW: {}",
+            parser_data.element_notation(rule),
+            sorted_members(direct),
+            sorted_members(follow),
+        );
+        let _ = writeln!(dot, "    n{rule} [label={}];", dot_string(&label));
+    }
+    let mut edges = follow_graph.named_edges();
+    edges.sort_unstable();
+    edges.dedup();
+    for (source, target) in edges {
+        let _ = writeln!(dot, "    n{source} -> n{target};");
+    }
+    dot.push_str("}\n");
+    Ok(dot)
+}
+
+fn sorted_members(set: &HashSet<SetMember>) -> String {
+    let mut members: Vec<&SetMember> = set.iter().collect();
+    members.sort_unstable();
+    members
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A quoted DOT string. Newlines become the `\n` line break of DOT labels.
+fn dot_string(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vms::NullVm;
+
+    #[test]
+    fn test_rule_graph_dot() {
+        let rules = "ws -> \" \" | #;\
+            $IGNORE: ws;\
+            start -> value \"+\" value | value;\
+            value -> [0-9];";
+        let dot = dot_from_text(rules, &NullVm::new(), DotGraph::Rules, DotOptions::default());
+        assert_eq!(
+            "digraph rules {\n    \
+            n0 [label=\"ws\"];\n    \
+            n2 [label=\"start\", peripheries=2];\n    \
+            n3 [label=\"value\"];\n    \
+            n2 -> n3 [label=\"1.1, 1.3, 2.1\"];\n    \
+            n2 -> n0 [style=dashed];\n\
+            }\n",
+            dot.unwrap()
+        );
+
+        let options = DotOptions { terminals: true };
+        let dot = dot_from_text(rules, &NullVm::new(), DotGraph::Rules, options).unwrap();
+        assert!(dot.contains("    n1 [label=\"\\\" \\\"\", shape=box];\n"), "{dot}");
+        assert!(dot.contains("    n2 -> n4 [label=\"1.2\"];\n"), "{dot}");
+        assert!(dot.contains("    n3 -> n5 [label=\"1.1\"];\n"), "{dot}");
+    }
+
+    #[test]
+    fn test_follow_graph_dot() {
+        let rules = "start -> list \"!\";\
+            list -> item list_s;\
+            list_s -> \",\" list | #;\
+            item -> \"x\";";
+        let dot = dot_from_text(rules, &NullVm::new(), DotGraph::Follow, DotOptions::default());
+        assert_eq!(
+            "digraph follow {\n    \
+            node [shape=box];\n    \
+            n0 [label=\"start\\ndirect: terminate\\nFOLLOW: terminate\"];\n    \
+            n1 [label=\"list\\ndirect: '!'\\nFOLLOW: '!'\"];\n    \
+            n3 [label=\"item\\ndirect: ','\\nFOLLOW: '!' ','\"];\n    \
+            n4 [label=\"list_s\\ndirect: \\nFOLLOW: '!'\"];\n    \
+            n1 -> n3;\n    \
+            n1 -> n4;\n    \
+            n4 -> n1;\n\
+            }\n",
+            dot.unwrap()
+        );
+        assert!("tree".parse::<DotGraph>().is_err());
+    }
+}
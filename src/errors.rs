@@ -6,19 +6,32 @@
 
 use std::fmt;
 
+use crate::parse_funcs::SourcePosition;
 use crate::parser_data::ElementIndex;
+use crate::repair::Repair;
 use crate::tree::TreeError;
 
 #[derive(Debug)]
 /// Errors that can occur during parsing or while executing VM instructions.
 pub enum ParserError {
+    /// `rules` and `repairs` are only filled in by [`Parser`](crate::script_parser::Parser), see
+    /// [`ParserError::rules`] and [`ParserError::repairs`].
     UnexpectedCharError {
         chr: char,
         pos: usize,
         expected: String,
+        rules: Vec<String>,
+        repairs: Vec<Repair>,
     },
     EndOfCharsError {
         pos: usize,
+        rules: Vec<String>,
+        repairs: Vec<Repair>,
+    },
+    /// The start rule was complete but the input went on, starting with `chr` at `pos`.
+    TrailingInputError {
+        chr: char,
+        pos: usize,
     },
     GramError {
         err: GrammarError,
@@ -27,6 +40,10 @@ pub enum ParserError {
         operation: String,
         pos: usize,
     },
+    /// Parsing was requested from a rule that is not an entry point of the grammar.
+    UnknownEntryPoint {
+        name: String,
+    },
     Impossible,
     InternalError {
         message: String,
@@ -41,22 +58,84 @@ pub enum ParserError {
 
 impl std::error::Error for ParserError {}
 
+impl ParserError {
+    /// The char offset the error points to, if it has one.
+    #[must_use]
+    pub fn pos(&self) -> Option<usize> {
+        match self {
+            ParserError::UnexpectedCharError { pos, .. }
+            | ParserError::EndOfCharsError { pos, .. }
+            | ParserError::TrailingInputError { pos, .. }
+            | ParserError::UnknownSpecialOperation { pos, .. }
+            | ParserError::GramError {
+                err: GrammarError::UnexpectedElementError { pos, .. },
+            } => Some(*pos),
+            _ => None,
+        }
+    }
+
+    /// The names of the rules being parsed at the error, innermost first. Generated rules are
+    /// named after the rule they were generated for. Empty for errors in grammar texts.
+    #[must_use]
+    pub fn rules(&self) -> &[String] {
+        match self {
+            ParserError::UnexpectedCharError { rules, .. }
+            | ParserError::EndOfCharsError { rules, .. } => rules,
+            _ => &[],
+        }
+    }
+
+    /// The single char edits of the input parsing gets furthest with. Empty for errors in
+    /// grammar texts and if no edit gets the parser past the error.
+    #[must_use]
+    pub fn repairs(&self) -> &[Repair] {
+        match self {
+            ParserError::UnexpectedCharError { repairs, .. }
+            | ParserError::EndOfCharsError { repairs, .. } => repairs,
+            _ => &[],
+        }
+    }
+}
+
 impl fmt::Display for ParserError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            ParserError::UnexpectedCharError { chr, pos, expected } => write!(
-                f,
-                " \"{}\" at pos {} was not expected. Expected {}",
-                chr, pos, expected
-            ),
-            ParserError::EndOfCharsError { pos } => write!(
+            ParserError::UnexpectedCharError {
+                chr,
+                pos,
+                expected,
+                rules,
+                repairs,
+            } => {
+                write!(f, " \"{}\" at pos {} was not expected", chr, pos)?;
+                write_rules(f, rules)?;
+                write!(f, ". Expected {}", expected)?;
+                write_repairs(f, repairs)
+            }
+            ParserError::EndOfCharsError {
+                pos,
+                rules,
+                repairs,
+            } => {
+                write!(
+                    f,
+                    "There was a char expected but there was none at pos {}",
+                    pos
+                )?;
+                write_rules(f, rules)?;
+                write_repairs(f, repairs)
+            }
+            ParserError::TrailingInputError { chr, pos } => write!(
                 f,
-                "There was a char expected but there was none at pos {}",
-                pos
+                " \"{}\" at pos {} is trailing input after the end of the start rule",
+                chr, pos
             ),
             ParserError::UnknownSpecialOperation { operation, pos } => {
                 write!(f, " \"{}\" at pos {} was not expected", operation, pos)
             }
+            ParserError::UnknownEntryPoint { name } => {
+                write!(f, " \"{name}\" is not an entry point of the grammar")
+            }
             ParserError::GramError { .. } => write!(f, "There was a Grammar error"),
             ParserError::Impossible => write!(f, "This error should not be possible"),
             ParserError::InternalError { .. } => write!(f, "There was an internal error"),
@@ -66,6 +145,23 @@ impl fmt::Display for ParserError {
     }
 }
 
+/// " while parsing `number` in `term` in `terms`"
+fn write_rules(f: &mut fmt::Formatter, rules: &[String]) -> fmt::Result {
+    if rules.is_empty() {
+        return Ok(());
+    }
+    let rules: Vec<String> = rules.iter().map(|rule| format!("`{rule}`")).collect();
+    write!(f, " while parsing {}", rules.join(" in "))
+}
+
+fn write_repairs(f: &mut fmt::Formatter, repairs: &[Repair]) -> fmt::Result {
+    if repairs.is_empty() {
+        return Ok(());
+    }
+    let repairs: Vec<String> = repairs.iter().map(ToString::to_string).collect();
+    write!(f, ". Possible fixes: {}", repairs.join(", "))
+}
+
 impl From<GrammarError> for ParserError {
     fn from(err: GrammarError) -> Self {
         ParserError::GramError { err }
@@ -95,6 +191,8 @@ pub enum GrammarError {
     MissingProduction {
         index: ElementIndex,
     },
+    /// Two productions of a rule start with the same char. Generated rules are named after the
+    /// rule they were written in, e.g. `expr/(2)` for the second group in `expr`.
     SteuerSetsNotDistinct {
         steuer_terminal: String,
         steuer_char: char,
@@ -104,6 +202,25 @@ pub enum GrammarError {
         reason: String,
         pos: usize,
     },
+    /// Two productions of a rule can start with the same k chars in LL(k) mode.
+    LookaheadNotDistinct {
+        lookahead: String,
+        rule_name: String,
+        k: usize,
+    },
+    /// A rule can derive itself as its leftmost element. `cycle` starts and ends with that rule.
+    LeftRecursion {
+        cycle: Vec<String>,
+    },
+    /// A rule was asked for by name but is not defined.
+    UnknownRule {
+        rule_name: String,
+    },
+    /// A rule can never derive a string of terminals, every alternative leads back into such a
+    /// rule.
+    UnproductiveRule {
+        rule_name: String,
+    },
     GraphNodeAlreadyExistsError {
         node_name: usize,
     },
@@ -133,10 +250,34 @@ impl fmt::Display for GrammarError {
             GrammarError::MissingProduction { index } => {
                 write!(f, " \"{index}\" not in productions")
             }
-            GrammarError::SteuerSetsNotDistinct { .. } => write!(f, "steuersets not distinct"),
+            GrammarError::SteuerSetsNotDistinct {
+                steuer_char,
+                rule_name,
+                ..
+            } => write!(
+                f,
+                "steuersets of rule \"{rule_name}\" not distinct for {steuer_char:?}"
+            ),
             GrammarError::UnexpectedElementError { reason, pos } => {
                 write!(f, " \"{reason}\" at pos {pos} was not expected")
             }
+            GrammarError::LookaheadNotDistinct {
+                lookahead,
+                rule_name,
+                k,
+            } => write!(
+                f,
+                "lookahead of rule \"{rule_name}\" not distinct for {lookahead} with k = {k}"
+            ),
+            GrammarError::LeftRecursion { cycle } => {
+                write!(f, "left recursion: {}", cycle.join(" -> "))
+            }
+            GrammarError::UnknownRule { rule_name } => {
+                write!(f, "rule \"{rule_name}\" is not defined")
+            }
+            GrammarError::UnproductiveRule { rule_name } => {
+                write!(f, "rule \"{rule_name}\" can never derive a string of terminals")
+            }
             GrammarError::GraphNodeAlreadyExistsError { .. } => {
                 write!(f, "graph node already exists")
             }
@@ -149,3 +290,43 @@ impl fmt::Display for GrammarError {
         }
     }
 }
+
+/// A single problem found in a grammar.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct GrammarDiagnostic {
+    pub message: String,
+    /// The rule the problem belongs to, if any.
+    pub rule: Option<String>,
+    /// Where the problem is in the grammar text. `None` for grammars built without text.
+    pub location: Option<SourcePosition>,
+}
+
+impl fmt::Display for GrammarDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(location) = self.location {
+            write!(f, "{location}: ")?;
+        }
+        if let Some(rule) = &self.rule {
+            write!(f, "in rule \"{rule}\": ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Every problem found while building a parser from a grammar.
+#[derive(Debug)]
+pub struct GrammarDiagnostics {
+    pub diagnostics: Vec<GrammarDiagnostic>,
+}
+
+impl std::error::Error for GrammarDiagnostics {}
+
+impl fmt::Display for GrammarDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the grammar has {} problem(s)", self.diagnostics.len())?;
+        for diagnostic in &self.diagnostics {
+            write!(f, "\n{diagnostic}")?;
+        }
+        Ok(())
+    }
+}
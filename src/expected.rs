@@ -0,0 +1,176 @@
+//! What the parser expected at a syntax error, in the vocabulary of the grammar.
+//!
+//! [`Parser`](crate::script_parser::Parser) collects the terminals, rules and chars that could
+//! have come next and [`describe`] turns them into the `expected` text of
+//! [`ParserError::UnexpectedCharError`](crate::errors::ParserError::UnexpectedCharError), e.g.
+//! "`print`, `+`, `-` or a digit".
+use crate::char_class::{CharClass, char_after, char_before};
+use crate::sets::SetMember;
+
+/// Something that could have come next.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expected {
+    Terminal(String),
+    Rule(String),
+    /// A char out of the inclusive ranges
+    Chars(Vec<(char, char)>),
+    End,
+}
+
+impl From<SetMember> for Expected {
+    fn from(member: SetMember) -> Self {
+        match member {
+            SetMember::Char(chr) => Expected::Chars(vec![(chr, chr)]),
+            SetMember::Range(lo, hi) => Expected::Chars(vec![(lo, hi)]),
+            SetMember::Terminate => Expected::End,
+        }
+    }
+}
+
+/// The items as a list like "`print`, `+`, `-` or a digit": terminals and rules in the order
+/// given, then the chars with adjacent ones collapsed into ranges, then the end of the input.
+/// Chars that are a one char terminal of the list aren't repeated.
+#[must_use]
+pub fn describe(items: &[Expected]) -> String {
+    let mut named: Vec<String> = vec![];
+    let mut ranges = vec![];
+    let mut end = false;
+    for item in items {
+        let described = match item {
+            Expected::Terminal(terminal) => format!("`{}`", terminal.escape_debug()),
+            Expected::Rule(name) => format!("`{name}`"),
+            Expected::Chars(item_ranges) => {
+                ranges.extend_from_slice(item_ranges);
+                continue;
+            }
+            Expected::End => {
+                end = true;
+                continue;
+            }
+        };
+        if !named.contains(&described) {
+            named.push(described);
+        }
+    }
+    let mut ranges = CharClass::new(ranges, false).ranges().to_vec();
+    for item in items {
+        if let Expected::Terminal(terminal) = item
+            && let [chr] = terminal.chars().collect::<Vec<_>>()[..]
+        {
+            ranges = without(&ranges, chr);
+        }
+    }
+    named.extend(describe_chars(&ranges));
+    if end {
+        named.push(String::from("the end of the input"));
+    }
+    join_or(&named)
+}
+
+/// Names for the ranges: common classes like digits by name, single chars quoted, classes
+/// containing almost everything by what they leave out.
+fn describe_chars(ranges: &[(char, char)]) -> Vec<String> {
+    let (Some(&(first, _)), Some(&(_, last))) = (ranges.first(), ranges.last()) else {
+        return vec![];
+    };
+    if first == char::MIN && last == char::MAX {
+        let left_out = CharClass::new(ranges.to_vec(), true);
+        return match &describe_chars(left_out.ranges())[..] {
+            [] => vec![String::from("any char")],
+            left_out => vec![format!("any char but {}", join_or(left_out))],
+        };
+    }
+    let letters = ranges.contains(&('A', 'Z')) && ranges.contains(&('a', 'z'));
+    let mut described = vec![];
+    if letters {
+        described.push(String::from("a letter"));
+    }
+    for &(lo, hi) in ranges {
+        let name = match (lo, hi) {
+            ('A', 'Z') | ('a', 'z') if letters => continue,
+            ('0', '9') => String::from("a digit"),
+            ('a', 'z') => String::from("a lowercase letter"),
+            ('A', 'Z') => String::from("an uppercase letter"),
+            _ if lo == hi => format!("`{}`", lo.escape_debug()),
+            _ => format!(
+                "a char from `{}` to `{}`",
+                lo.escape_debug(),
+                hi.escape_debug()
+            ),
+        };
+        described.push(name);
+    }
+    described
+}
+
+/// The sorted ranges with `chr` taken out.
+fn without(ranges: &[(char, char)], chr: char) -> Vec<(char, char)> {
+    let mut rest = vec![];
+    for &(lo, hi) in ranges {
+        if chr < lo || chr > hi {
+            rest.push((lo, hi));
+            continue;
+        }
+        if let Some(before) = char_before(chr).filter(|&before| lo <= before) {
+            rest.push((lo, before));
+        }
+        if let Some(after) = char_after(chr).filter(|&after| after <= hi) {
+            rest.push((after, hi));
+        }
+    }
+    rest
+}
+
+/// "a", "a or b", "a, b or c".
+fn join_or(items: &[String]) -> String {
+    match items {
+        [] => String::from("nothing"),
+        [item] => item.clone(),
+        [init @ .., last] => format!("{} or {last}", init.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_terminals_rules_and_chars() {
+        let items = [
+            Expected::Terminal(String::from("print")),
+            Expected::Chars(vec![('+', '+')]),
+            Expected::Terminal(String::from("+")),
+            Expected::Chars(('0'..='9').map(|chr| (chr, chr)).collect()),
+            Expected::Terminal(String::from("-")),
+            Expected::Rule(String::from("name")),
+            Expected::Terminal(String::from("print")),
+        ];
+        assert_eq!("`print`, `+`, `-`, `name` or a digit", describe(&items));
+        let items = [
+            Expected::Chars(vec![('a', 'z'), ('_', '_'), ('A', 'Z'), ('\n', '\n')]),
+            Expected::End,
+        ];
+        assert_eq!(
+            "a letter, `\\n`, `_` or the end of the input",
+            describe(&items)
+        );
+        assert_eq!("nothing", describe(&[]));
+    }
+
+    #[test]
+    fn test_describe_chars() {
+        assert_eq!(
+            vec!["a char from `a` to `f`", "`x`"],
+            describe_chars(&[('a', 'f'), ('x', 'x')])
+        );
+        assert_eq!(vec!["a lowercase letter"], describe_chars(&[('a', 'z')]));
+        let not_quote = CharClass::from_source("[^\"]").unwrap();
+        assert_eq!(
+            vec!["any char but `\\\"`"],
+            describe_chars(not_quote.ranges())
+        );
+        assert_eq!(vec!["any char"], describe_chars(CharClass::any().ranges()));
+        assert_eq!(vec![('a', 'b'), ('d', 'e')], without(&[('a', 'e')], 'c'));
+        assert_eq!(vec![('b', 'e')], without(&[('a', 'e')], 'a'));
+    }
+}
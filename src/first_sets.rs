@@ -1,10 +1,12 @@
 //! Compute FIRST sets for elements and productions.
 //!
 //! This module calculates FIRST sets used by the LL(1) machinery and
-//! is consumed by follow_sets and steuer_sets.
+//! is consumed by follow_sets and steuer_sets. The sets are computed as a fixpoint, like the
+//! FOLLOW sets in follow_sets.
 use crate::errors::GrammarError;
+use crate::left_recursion::check_left_recursion;
 use crate::parser_data::{ElementIndex, ElementType, ParserData, Production};
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use crate::sets::{NamedSets, SetMemberWithEmpty};
 use crate::vms::VM;
@@ -41,42 +43,64 @@ where
     Ok(set)
 }
 
+/// Computes the FIRST sets of all non-terminals.
+///
+/// The sets are grown to a fixpoint with a worklist: a rule is computed again whenever the FIRST
+/// set of a non-terminal it uses grows, so mutually recursive and nullable rules end up complete
+/// and deep grammars don't need deep recursion. A rule is nullable if its set contains
+/// [`SetMemberWithEmpty::Empty`].
+///
+/// Fails with [`GrammarError::LeftRecursion`] for left-recursive grammars, which no LL(1) parser
+/// can be built for, and with [`GrammarError::MissingProduction`] for non-terminals without a rule.
 pub fn get_first_sets<T>(parser_data: &ParserData<T>) -> Result<NamedSets, GrammarError>
 where
     T: VM,
 {
-    let mut first_sets: NamedSets = HashMap::new();
-    for &el_ix in parser_data.parse_rules.rules.keys() {
-        get_set_first_set_of_element(el_ix, &mut first_sets, parser_data)?;
+    check_left_recursion(parser_data)?;
+    let rules = &parser_data.parse_rules.rules;
+    let mut users: HashMap<ElementIndex, Vec<ElementIndex>> = HashMap::new();
+    for (&rule, nt_rules) in rules {
+        for production in &nt_rules.possible_productions {
+            let Production::NotEmpty(el_ixs) = &**production else {
+                continue;
+            };
+            for &el_ix in el_ixs {
+                if parser_data.element_types[el_ix] != ElementType::NonTerminal {
+                    continue;
+                }
+                if !rules.contains_key(&el_ix) {
+                    return Err(GrammarError::MissingProduction { index: el_ix });
+                }
+                users.entry(el_ix).or_default().push(rule);
+            }
+        }
     }
-    Ok(first_sets)
-}
 
-fn get_set_first_set_of_element<T>(
-    el_ix: ElementIndex,
-    first_sets: &mut NamedSets,
-    parser_data: &ParserData<T>,
-) -> Result<HashSet<SetMemberWithEmpty>, GrammarError>
-where
-    T: VM,
-{
-    let set_maybe = get_first_set_of_element(el_ix, first_sets, parser_data)?;
-    let res = match set_maybe {
-        None => {
-            let mut set: HashSet<SetMemberWithEmpty> = HashSet::new();
-            let Some(productions) = parser_data.parse_rules.rules.get(&el_ix) else {
-                    return Err(GrammarError::MissingProduction { index: el_ix });
-                };
-            for production in &productions.possible_productions {
-                let rus = get_first_set_of_production(production, first_sets, parser_data)?;
-                set.extend(&rus);
+    let mut first_sets: NamedSets = rules.keys().map(|&rule| (rule, HashSet::new())).collect();
+    let mut worklist: VecDeque<ElementIndex> = rules.keys().copied().collect();
+    let mut queued: HashSet<ElementIndex> = worklist.iter().copied().collect();
+    while let Some(rule) = worklist.pop_front() {
+        queued.remove(&rule);
+        let mut set = HashSet::new();
+        for production in &rules[&rule].possible_productions {
+            set.extend(get_first_set_of_production(
+                production,
+                &first_sets,
+                parser_data,
+            )?);
+        }
+        let known = first_sets.entry(rule).or_default();
+        if set.is_subset(known) {
+            continue;
+        }
+        known.extend(set);
+        for &user in users.get(&rule).into_iter().flatten() {
+            if queued.insert(user) {
+                worklist.push_back(user);
             }
-            first_sets.insert(el_ix, set.clone());
-            Ok(set)
         }
-        Some(x) => Ok(x),
-    };
-    res
+    }
+    Ok(first_sets)
 }
 
 fn get_first_set_of_element<T>(
@@ -98,37 +122,42 @@ where
                 Some(ch) => HashSet::from([SetMemberWithEmpty::Char(ch)]),
             })
         }
+        ElementType::CharClass => {
+            let class = parser_data
+                .get_char_class(el_ix)
+                .ok_or(GrammarError::MissingElementForIndex { index: el_ix })?;
+            Some(class.set_members_with_empty().into_iter().collect())
+        }
     };
     Ok(res)
 }
 
 fn get_first_set_of_production<T>(
     production: &Production,
-    first_sets: &mut NamedSets,
+    first_sets: &NamedSets,
     parser_data: &ParserData<T>,
 ) -> Result<HashSet<SetMemberWithEmpty>, GrammarError>
 where
     T: VM,
 {
-    match production {
-        Production::Empty => Ok(HashSet::from([SetMemberWithEmpty::Empty])),
-        Production::NotEmpty(el_ixs) => {
-            let mut first_menge =
-                get_set_first_set_of_element(*el_ixs.first().unwrap(), first_sets, parser_data)?;
-            if first_menge.contains(&SetMemberWithEmpty::Empty) {
-                for el_ix in el_ixs.iter().skip(1) {
-                    let cur_set = get_set_first_set_of_element(*el_ix, first_sets, parser_data)?;
-                    let has_empty = cur_set.contains(&SetMemberWithEmpty::Empty);
-                    first_menge.extend(cur_set);
-                    if !has_empty {
-                        first_menge.remove(&SetMemberWithEmpty::Empty);
-                        break;
-                    }
-                }
-            }
-            Ok(first_menge)
+    let Production::NotEmpty(el_ixs) = production else {
+        return Ok(HashSet::from([SetMemberWithEmpty::Empty]));
+    };
+    let mut first_menge = HashSet::new();
+    for &el_ix in el_ixs {
+        let cur_set = get_first_set_of_element(el_ix, first_sets, parser_data)?.unwrap_or_default();
+        let has_empty = cur_set.contains(&SetMemberWithEmpty::Empty);
+        first_menge.extend(
+            cur_set
+                .into_iter()
+                .filter(|member| *member != SetMemberWithEmpty::Empty),
+        );
+        if !has_empty {
+            return Ok(first_menge);
         }
     }
+    first_menge.insert(SetMemberWithEmpty::Empty);
+    Ok(first_menge)
 }
 
 #[cfg(test)]
@@ -490,4 +519,43 @@ mod tests {
                 .clone()
         );
     }
+
+    #[test]
+    fn test_first_sets_of_mutually_recursive_nullable_rules() {
+        let to_parse = "start -> a \"end\";\
+            a -> b c;\
+            b -> \"x\" c | #;\
+            c -> \"y\" a | b \"z\";";
+        let mut peekable = PeekableWrapper::from_str(to_parse);
+        let vm = NullVm::new();
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+        let parser_data = rule_parser.parser_data;
+        let first_dict = get_first_sets(&parser_data).unwrap();
+        let first_of =
+            |name: &str| first_dict[&parser_data.get_element_nt_index(name).unwrap()].clone();
+        assert_eq!(make_memberset("xyz"), first_of("a"));
+        assert_eq!(make_memberset("x#"), first_of("b"));
+        assert_eq!(make_memberset("xyz"), first_of("c"));
+        assert_eq!(make_memberset("xyz"), first_of("start"));
+    }
+
+    #[test]
+    fn test_first_sets_of_deep_grammar() {
+        let depth = 3000;
+        let mut to_parse: String = (0..depth)
+            .map(|i| format!("r{i} -> r{} \"a\";", i + 1))
+            .collect();
+        to_parse.push_str(&format!("r{depth} -> \"z\" | #;"));
+        let mut peekable = PeekableWrapper::from_str(&to_parse);
+        let vm = NullVm::new();
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+        let parser_data = rule_parser.parser_data;
+        let first_dict = get_first_sets(&parser_data).unwrap();
+        assert_eq!(
+            make_memberset("za"),
+            first_dict[&parser_data.get_element_nt_index("r0").unwrap()]
+        );
+    }
 }
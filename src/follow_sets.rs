@@ -25,19 +25,54 @@ pub type Graph = GraphNamedNodes<HashSet<SetMember>>;
 /// The start symbol's FOLLOW set is initialized with Terminate and information
 /// is propagated along a graph built from productions. Returns a map from
 /// a non-terminal index to the set of terminal symbols that may follow it.
+///
+/// If the start rule has an ignore rule, the parser skips it before and after the start symbol.
+/// Its FOLLOW set is therefore seeded with the FIRST set of the start symbol and Terminate.
 pub fn get_follow_sets<T>(
     start: ElementIndex,
     first_sets: &NamedSets,
     parser_data: &ParserData<T>,
 ) -> Result<NamedSetsNoEmpty, GrammarError>
+where
+    T: VM,
+{
+    get_follow_sets_for_entry_points(&[start], first_sets, parser_data)
+}
+
+/// Computes the FOLLOW sets of a grammar that can be parsed from several rules.
+///
+/// Every entry point is seeded like the start symbol in [`get_follow_sets`], so the resulting
+/// steuer maps work no matter which entry point a parse starts from.
+pub fn get_follow_sets_for_entry_points<T>(
+    entry_points: &[ElementIndex],
+    first_sets: &NamedSets,
+    parser_data: &ParserData<T>,
+) -> Result<NamedSetsNoEmpty, GrammarError>
+where
+    T: VM,
+{
+    let mut follow_graph = get_follow_graph(entry_points, first_sets, parser_data)?;
+    make_follow_sets_from_marked_graph(&mut follow_graph)
+}
+
+/// Builds the graph FOLLOW sets are propagated along.
+///
+/// Every non-terminal is a node holding the members its FOLLOW set gets directly, from the
+/// elements after it in a production or from being an entry point. An edge from `a` to `b` means
+/// `b` ends a production of `a`, possibly followed by nullable elements, so everything in the
+/// FOLLOW set of `a` is in the FOLLOW set of `b` too.
+pub fn get_follow_graph<T>(
+    entry_points: &[ElementIndex],
+    first_sets: &NamedSets,
+    parser_data: &ParserData<T>,
+) -> Result<Graph, GrammarError>
 where
     T: VM,
 {
     let mut follow_graph = make_graph_with_index(&parser_data.parse_rules.rules)?;
-    follow_graph
-        .get_node_mut(start)?
-        .data
-        .insert(SetMember::Terminate);
+    for &start in entry_points {
+        seed_entry_point(&mut follow_graph, start, first_sets, parser_data)?;
+    }
     for (&el_index, nt_rules) in &parser_data.parse_rules.rules {
         for production in &nt_rules.possible_productions {
             let prodi = &**production;
@@ -52,7 +87,35 @@ where
             }
         }
     }
-    make_follow_sets_from_marked_graph(&mut follow_graph)
+    Ok(follow_graph)
+}
+
+fn seed_entry_point<T>(
+    follow_graph: &mut Graph,
+    start: ElementIndex,
+    first_sets: &NamedSets,
+    parser_data: &ParserData<T>,
+) -> Result<(), GrammarError>
+where
+    T: VM,
+{
+    follow_graph
+        .get_node_mut(start)?
+        .data
+        .insert(SetMember::Terminate);
+    if let Some(ignore) = parser_data.get_rule_by_key(start).and_then(|rule| rule.ignore) {
+        let first_of_start = first_sets
+            .get(&start)
+            .ok_or(GrammarError::MissingFirstSet { index: start })?;
+        let ignore_follow = &mut follow_graph.get_node_mut(ignore)?.data;
+        ignore_follow.insert(SetMember::Terminate);
+        ignore_follow.extend(
+            first_of_start
+                .iter()
+                .filter_map(|member| SetMember::try_from(*member).ok()),
+        );
+    }
+    Ok(())
 }
 
 fn make_graph_with_index<T>(rules: &RuleMap<T>) -> Result<Graph, GrammarError>
@@ -370,4 +433,32 @@ identifier3 -> \"c_terminal\"| #;
                 .clone()
         );
     }
+
+    #[test]
+    fn test_follow_entry_points() {
+        let to_parse = "$START: file, expression;\
+file -> expression \";\" file | #;\
+expression -> \"x\" operand;\
+operand -> \"+\" \"x\" | #;
+";
+        let mut peekable = PeekableWrapper::from_str(to_parse);
+        let vm = NullVm::new();
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+        let parser_data = rule_parser.parser_data;
+        let first_dict = get_first_sets(&parser_data).unwrap();
+        let follow_dict = get_follow_sets_for_entry_points(
+            &parser_data.entry_points(),
+            &first_dict,
+            &parser_data,
+        )
+        .unwrap();
+        assert_eq!(
+            make_memberset_no_empty(";!"),
+            follow_dict
+                .get(&parser_data.get_element_nt_index("operand").unwrap())
+                .unwrap()
+                .clone()
+        );
+    }
 }
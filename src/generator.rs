@@ -0,0 +1,373 @@
+//! Random sentences of the language a grammar describes.
+//!
+//! [`SentenceGenerator`] walks the productions of a [`ParserData`] from an entry point and picks
+//! alternatives at random, terminals are written as they are and char classes produce a random
+//! char of the class. Since the ignore rule is woven into the productions, it is generated between
+//! elements like any other rule, and around the entry point where the parser skips it.
+//!
+//! Recursive rules could grow a sentence forever, so every rule knows how deep its shallowest
+//! derivation is. Alternatives are only picked at random while they fit into the depth budget,
+//! beyond it the shallowest alternatives are taken, which always terminates.
+//!
+//! The random numbers come from [`Rng`], so the same seed gives the same sentences on every
+//! platform and version of Rust.
+use std::collections::HashMap;
+
+use crate::char_class::CharClass;
+use crate::errors::{GrammarDiagnostics, GrammarError};
+use crate::parser_data::{ElementIndex, ElementType, ParserData, Production};
+use crate::script_parser::parsed_parser_data;
+use crate::vms::VM;
+
+/// How many rules deep a sentence may go before only the shallowest alternatives are picked.
+pub const DEFAULT_MAX_DEPTH: usize = 16;
+
+/// A small seedable pseudo random number generator (`SplitMix64`).
+///
+/// Not suitable for anything that needs to be unpredictable, but fast and reproducible.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    #[must_use]
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`. `bound` must not be 0.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        let wide = u128::from(self.next_u64()) * u128::from(bound);
+        u64::try_from(wide >> 64).unwrap_or_default()
+    }
+
+    /// A random element of `items`, `None` if it is empty.
+    pub fn choose<'i, I>(&mut self, items: &'i [I]) -> Option<&'i I> {
+        let len = u64::try_from(items.len()).ok()?;
+        if len == 0 {
+            return None;
+        }
+        let index = usize::try_from(self.below(len)).ok()?;
+        items.get(index)
+    }
+}
+
+/// Generates random sentences of a grammar.
+pub struct SentenceGenerator<T>
+where
+    T: VM,
+{
+    parser_data: ParserData<T>,
+    rng: Rng,
+    /// See [`DEFAULT_MAX_DEPTH`].
+    pub max_depth: usize,
+    /// How deep the shallowest derivation of every productive rule goes.
+    depths: HashMap<ElementIndex, usize>,
+}
+
+impl<T> SentenceGenerator<T>
+where
+    T: VM,
+{
+    #[must_use]
+    pub fn new(parser_data: ParserData<T>, seed: u64) -> SentenceGenerator<T> {
+        let depths = min_depths(&parser_data);
+        SentenceGenerator {
+            parser_data,
+            rng: Rng::new(seed),
+            max_depth: DEFAULT_MAX_DEPTH,
+            depths,
+        }
+    }
+
+    /// A generator for a grammar text as written. Syntax errors are returned as diagnostics.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`GrammarDiagnostics`] if `rule_text` has syntax errors.
+    pub fn from_text(
+        rule_text: &str,
+        vm: &T,
+        seed: u64,
+    ) -> Result<SentenceGenerator<T>, GrammarDiagnostics> {
+        Ok(SentenceGenerator::new(
+            parsed_parser_data(rule_text, vm)?,
+            seed,
+        ))
+    }
+
+    /// A sentence starting from the default entry point.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`GrammarError::UnknownRule`] if the grammar has no entry point and
+    /// [`GrammarError::UnproductiveRule`] if a rule the sentence needs derives no input.
+    pub fn generate(&mut self) -> Result<String, GrammarError> {
+        let start = self
+            .parser_data
+            .entry_points()
+            .first()
+            .copied()
+            .ok_or_else(|| GrammarError::UnknownRule {
+                rule_name: String::from("start"),
+            })?;
+        self.generate_from_index(start)
+    }
+
+    /// A sentence starting from the rule `rule_name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`GrammarError::UnknownRule`] if there is no rule `rule_name` and
+    /// [`GrammarError::UnproductiveRule`] if a rule the sentence needs derives no input.
+    pub fn generate_from(&mut self, rule_name: &str) -> Result<String, GrammarError> {
+        let start = self
+            .parser_data
+            .get_element_nt_index(rule_name)
+            .filter(|ix| self.parser_data.get_rule_by_key(*ix).is_some())
+            .ok_or_else(|| GrammarError::UnknownRule {
+                rule_name: String::from(rule_name),
+            })?;
+        self.generate_from_index(start)
+    }
+
+    fn generate_from_index(&mut self, start: ElementIndex) -> Result<String, GrammarError> {
+        let ignore = self
+            .parser_data
+            .get_rule_by_key(start)
+            .and_then(|rule| rule.ignore);
+        let mut sentence = String::new();
+        if let Some(ignore) = ignore {
+            self.generate_rule(ignore, 0, &mut sentence)?;
+        }
+        self.generate_rule(start, 0, &mut sentence)?;
+        if let Some(ignore) = ignore {
+            self.generate_rule(ignore, 0, &mut sentence)?;
+        }
+        Ok(sentence)
+    }
+
+    /// Appends a derivation of `rule` to `sentence`. `depth` is the number of rules it is nested
+    /// in.
+    fn generate_rule(
+        &mut self,
+        rule: ElementIndex,
+        depth: usize,
+        sentence: &mut String,
+    ) -> Result<(), GrammarError> {
+        let productions = &self
+            .parser_data
+            .get_rule_by_key(rule)
+            .ok_or_else(|| GrammarError::UnknownRule {
+                rule_name: self.parser_data.element_notation(rule),
+            })?
+            .possible_productions;
+        let heights: Vec<Option<usize>> = productions
+            .iter()
+            .map(|production| production_height(production, &self.parser_data, &self.depths))
+            .collect();
+        let budget = self.max_depth.saturating_sub(depth + 1);
+        let mut candidates: Vec<usize> = (0..productions.len())
+            .filter(|&i| heights[i].is_some_and(|height| height <= budget))
+            .collect();
+        if candidates.is_empty() {
+            let lowest = heights.iter().flatten().min().copied().ok_or_else(|| {
+                GrammarError::UnproductiveRule {
+                    rule_name: self.parser_data.element_notation(rule),
+                }
+            })?;
+            candidates = (0..productions.len())
+                .filter(|&i| heights[i] == Some(lowest))
+                .collect();
+        }
+        let chosen = *self
+            .rng
+            .choose(&candidates)
+            .ok_or(GrammarError::MissingProduction { index: rule })?;
+        let Production::NotEmpty(el_ixs) = &*productions[chosen] else {
+            return Ok(());
+        };
+        for ix in el_ixs.clone() {
+            match self.parser_data.element_types.get(ix) {
+                Some(ElementType::NonTerminal) => self.generate_rule(ix, depth + 1, sentence)?,
+                Some(ElementType::Terminal) => {
+                    if let Some(data) = self.parser_data.get_element_data(ix) {
+                        sentence.push_str(&data.name);
+                    }
+                }
+                Some(ElementType::CharClass) => {
+                    let chr = self
+                        .parser_data
+                        .get_char_class(ix)
+                        .and_then(|class| random_char(&mut self.rng, &class))
+                        .ok_or_else(|| GrammarError::UnproductiveRule {
+                            rule_name: self.parser_data.element_notation(rule),
+                        })?;
+                    sentence.push(chr);
+                }
+                None => return Err(GrammarError::MissingElementForIndex { index: ix }),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A random char of `class`, preferring printable ASCII chars if the class has any.
+fn random_char(rng: &mut Rng, class: &CharClass) -> Option<char> {
+    let printable: Vec<(char, char)> = class
+        .ranges()
+        .iter()
+        .filter_map(|&(lo, hi)| {
+            let (lo, hi) = (lo.max(' '), hi.min('~'));
+            (lo <= hi).then_some((lo, hi))
+        })
+        .collect();
+    let ranges = if printable.is_empty() {
+        class.ranges()
+    } else {
+        &printable
+    };
+    let total: u64 = ranges
+        .iter()
+        .map(|&(lo, hi)| u64::from(hi) - u64::from(lo) + 1)
+        .sum();
+    if total == 0 {
+        return None;
+    }
+    let mut pick = rng.below(total);
+    for &(lo, hi) in ranges {
+        let size = u64::from(hi) - u64::from(lo) + 1;
+        if pick < size {
+            let code = u32::try_from(u64::from(lo) + pick).ok()?;
+            return Some(char::from_u32(code).unwrap_or(lo));
+        }
+        pick -= size;
+    }
+    None
+}
+
+/// How deep the shallowest derivation of every rule goes: a rule whose alternatives only hold
+/// terminals has depth 1, a rule using it depth 2. Rules that can never derive a string of
+/// terminals are missing.
+fn min_depths<T>(parser_data: &ParserData<T>) -> HashMap<ElementIndex, usize>
+where
+    T: VM,
+{
+    let mut depths: HashMap<ElementIndex, usize> = HashMap::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (&rule, nt_rules) in &parser_data.parse_rules.rules {
+            let depth = nt_rules
+                .possible_productions
+                .iter()
+                .filter_map(|production| production_height(production, parser_data, &depths))
+                .min()
+                .map(|height| height + 1);
+            if let Some(depth) = depth
+                && depths.get(&rule).is_none_or(|&known| depth < known)
+            {
+                depths.insert(rule, depth);
+                changed = true;
+            }
+        }
+    }
+    depths
+}
+
+/// The depth of the deepest rule in a production if every rule takes its shallowest derivation,
+/// 0 for productions without rules. `None` if a rule has no known derivation.
+fn production_height<T>(
+    production: &Production,
+    parser_data: &ParserData<T>,
+    depths: &HashMap<ElementIndex, usize>,
+) -> Option<usize>
+where
+    T: VM,
+{
+    let Production::NotEmpty(el_ixs) = production else {
+        return Some(0);
+    };
+    el_ixs
+        .iter()
+        .filter(|&&ix| parser_data.element_types.get(ix) == Some(&ElementType::NonTerminal))
+        .map(|ix| depths.get(ix).copied())
+        .try_fold(0, |height, depth| Some(height.max(depth?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script_parser::Parser;
+    use crate::vms::NullVm;
+
+    const RULES: &str = "$IGNORE: space;\
+        start -> expr \";\";\
+        expr -> number | \"(\" expr op expr \")\";\
+        op -> \"+\" | \"-\";\
+        number -> $[IGNORE: #] [0-9]+;\
+        $IGNORE: #;\
+        space -> \" \"*;";
+
+    #[test]
+    fn test_generated_sentences_parse() {
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut generator = SentenceGenerator::from_text(RULES, &vm, 7).unwrap();
+        let sentences: Vec<String> = (0..200).map(|_| generator.generate().unwrap()).collect();
+        assert!(sentences.iter().any(|sentence| sentence.starts_with(' ')));
+        assert!(sentences.iter().any(|sentence| sentence.contains("( (")));
+
+        let mut parser = Parser::try_from_text(RULES, &vm).unwrap();
+        for sentence in &sentences {
+            assert!(parser.parse(sentence, &mut state).is_ok(), "{sentence:?}");
+        }
+    }
+
+    #[test]
+    fn test_same_seed_gives_same_sentences() {
+        let sentences = |seed| {
+            let mut generator = SentenceGenerator::from_text(RULES, &NullVm::new(), seed).unwrap();
+            (0..10)
+                .map(|_| generator.generate_from("expr").unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sentences(1), sentences(1));
+        assert_ne!(sentences(1), sentences(2));
+    }
+
+    #[test]
+    fn test_depth_budget_steers_to_terminating_alternatives() {
+        let rules = "start -> \"(\" start \")\" | \"x\";";
+        let mut generator = SentenceGenerator::from_text(rules, &NullVm::new(), 3).unwrap();
+        generator.max_depth = 3;
+        let depths: Vec<usize> = (0..50)
+            .map(|_| generator.generate().unwrap().matches('(').count())
+            .collect();
+        assert!(depths.iter().all(|&depth| depth <= 2));
+        assert!(depths.contains(&2));
+    }
+
+    #[test]
+    fn test_unproductive_and_unknown_rules() {
+        let rules = "start -> \"a\" forever | forever;forever -> \"b\" forever;";
+        let mut generator = SentenceGenerator::from_text(rules, &NullVm::new(), 0).unwrap();
+        match generator.generate() {
+            Err(GrammarError::UnproductiveRule { rule_name }) => assert_eq!("start", rule_name),
+            other => panic!("expected an unproductive rule, got {other:?}"),
+        }
+        match generator.generate_from("missing") {
+            Err(GrammarError::UnknownRule { rule_name }) => assert_eq!("missing", rule_name),
+            other => panic!("expected an unknown rule, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,358 @@
+//! Factor out common prefixes of productions.
+//!
+//! Productions of one rule that start with the same elements, like
+//! `stmt -> "if" cond "then" body | "if" cond "then" body "else" body;`, can't be told apart by the
+//! next char. [`left_factor`] moves the shared prefix in front of a generated continuation rule
+//! that holds the different rests, so many such grammars become LL(1).
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::parser_data::{
+    ElementIndex, ElementType, GENERATED_NAME_SEPARATOR, NonTerminalRules, ParserData, Production,
+    generated_name,
+};
+use crate::vms::VM;
+
+/// One rewrite done by [`left_factor`].
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct LeftFactoring {
+    /// The rule whose productions were factored.
+    pub rule: String,
+    /// The shared prefix in grammar notation, without the rule's ignore rule.
+    pub prefix: Vec<String>,
+    /// How many productions shared the prefix.
+    pub alternatives: usize,
+    /// The generated rule with the rests of the productions.
+    pub continuation: String,
+}
+
+impl fmt::Display for LeftFactoring {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: factored {} out of {} alternatives into {}",
+            self.rule,
+            self.prefix.join(" "),
+            self.alternatives,
+            self.continuation
+        )
+    }
+}
+
+/// Factors out the common prefixes of the productions of every rule.
+///
+/// `stmt -> "if" cond "then" body | "if" cond "then" body "else" body` becomes
+/// `stmt -> "if" cond "then" body stmt/factor1` and `stmt/factor1 -> # | "else" body`. Terminals
+/// that only share some chars are split, `cmd -> "print" | "pop"` becomes `cmd -> "p" cmd/factor1`
+/// with `cmd/factor1 -> "rint" | "op"`, so the tree gets a leaf per part.
+///
+/// Continuation rules are named after the rule written in the grammar and add no tree node of
+/// their own, their children end up in the node of the factored rule. Only the written elements
+/// are compared; productions whose first non-terminals merely start with the same chars are left
+/// alone. Returns a report of every rewrite.
+pub fn left_factor<T>(parser_data: &mut ParserData<T>) -> Vec<LeftFactoring>
+where
+    T: VM,
+{
+    let mut rules: Vec<ElementIndex> = parser_data.parse_rules.rules.keys().copied().collect();
+    rules.sort_unstable();
+    let mut queue = VecDeque::from(rules);
+    let mut report = vec![];
+    while let Some(rule) = queue.pop_front() {
+        while let Some(factoring) = factor_first_group(parser_data, rule) {
+            queue.push_back(factoring.0);
+            report.push(factoring.1);
+        }
+    }
+    report
+}
+
+/// What the productions of a group have in common at their start.
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum Start {
+    Element(ElementIndex),
+    Char(char),
+}
+
+fn start_of<T>(production: &Production, parser_data: &ParserData<T>) -> Option<Start>
+where
+    T: VM,
+{
+    let Production::NotEmpty(el_ixs) = production else {
+        return None;
+    };
+    let first = el_ixs[0];
+    if parser_data.element_types[first] == ElementType::Terminal
+        && let Some(chr) = terminal_name(first, parser_data).chars().next()
+    {
+        return Some(Start::Char(chr));
+    }
+    Some(Start::Element(first))
+}
+
+fn terminal_name<T>(el_ix: ElementIndex, parser_data: &ParserData<T>) -> &str
+where
+    T: VM,
+{
+    parser_data
+        .get_element_data(el_ix)
+        .map_or("", |data| data.name.as_str())
+}
+
+/// Factors the first group of productions of `rule` that share a start. Returns the generated
+/// continuation rule and the report entry, or `None` if the productions have no common start.
+fn factor_first_group<T>(
+    parser_data: &mut ParserData<T>,
+    rule: ElementIndex,
+) -> Option<(ElementIndex, LeftFactoring)>
+where
+    T: VM,
+{
+    let nt_rules = parser_data.parse_rules.rules.get(&rule)?;
+    let productions = nt_rules.possible_productions.clone();
+    let ignore = nt_rules.ignore;
+    let starts: Vec<Option<Start>> = productions
+        .iter()
+        .map(|production| start_of(production, parser_data))
+        .collect();
+    let (first, start) = starts.iter().enumerate().find_map(|(ix, start)| {
+        start
+            .filter(|start| starts[ix + 1..].contains(&Some(*start)))
+            .map(|start| (ix, start))
+    })?;
+    let group: Vec<&[ElementIndex]> = productions
+        .iter()
+        .zip(&starts)
+        .filter(|(_, other)| **other == Some(start))
+        .filter_map(|(production, _)| match &**production {
+            Production::NotEmpty(el_ixs) => Some(el_ixs.as_slice()),
+            Production::Empty => None,
+        })
+        .collect();
+
+    let alternatives = group.len();
+    let (prefix, rests) = split_common_prefix(parser_data, &group);
+    let continuation = new_continuation(parser_data, rule);
+    let mut continuation_rule = NonTerminalRules::new(
+        rests
+            .into_iter()
+            .map(|rest| Rc::new(production_of(rest)))
+            .collect(),
+        ignore,
+        vec![],
+    );
+    continuation_rule.flatten = true;
+    parser_data
+        .parse_rules
+        .rules
+        .insert(continuation, continuation_rule);
+
+    let mut factored = prefix.clone();
+    factored.push(continuation);
+    let mut new_productions = vec![];
+    for (ix, production) in productions.iter().enumerate() {
+        if ix == first {
+            new_productions.push(Rc::new(Production::NotEmpty(factored.clone())));
+        } else if starts[ix] != Some(start) {
+            new_productions.push(Rc::clone(production));
+        }
+    }
+    parser_data
+        .parse_rules
+        .rules
+        .get_mut(&rule)?
+        .possible_productions = new_productions;
+
+    let report = LeftFactoring {
+        rule: element_name(parser_data, rule),
+        prefix: prefix
+            .iter()
+            .filter(|&&ix| Some(ix) != ignore)
+            .map(|&ix| parser_data.element_notation(ix))
+            .collect(),
+        alternatives,
+        continuation: element_name(parser_data, continuation),
+    };
+    Some((continuation, report))
+}
+
+/// Splits the productions of a group into their longest common prefix and their rests.
+///
+/// If the productions only share the first chars of their leading terminals, those chars are the
+/// prefix and the rests start with the remaining chars.
+fn split_common_prefix<T>(
+    parser_data: &mut ParserData<T>,
+    group: &[&[ElementIndex]],
+) -> (Vec<ElementIndex>, Vec<Vec<ElementIndex>>)
+where
+    T: VM,
+{
+    let shortest = group.iter().map(|el_ixs| el_ixs.len()).min().unwrap_or(0);
+    let common = (0..shortest)
+        .take_while(|&pos| group.iter().all(|el_ixs| el_ixs[pos] == group[0][pos]))
+        .count();
+    if common > 0 {
+        let rests = group
+            .iter()
+            .map(|el_ixs| el_ixs[common..].to_vec())
+            .collect();
+        return (group[0][..common].to_vec(), rests);
+    }
+
+    let terminals: Vec<String> = group
+        .iter()
+        .map(|el_ixs| String::from(terminal_name(el_ixs[0], parser_data)))
+        .collect();
+    let common_chars = terminals[0]
+        .chars()
+        .enumerate()
+        .take_while(|&(pos, chr)| {
+            terminals
+                .iter()
+                .all(|terminal| terminal.chars().nth(pos) == Some(chr))
+        })
+        .count();
+    let prefix: String = terminals[0].chars().take(common_chars).collect();
+    let prefix = vec![parser_data.get_or_add_terminal(&prefix)];
+    let rests = group
+        .iter()
+        .zip(&terminals)
+        .map(|(el_ixs, terminal)| {
+            let rest: String = terminal.chars().skip(common_chars).collect();
+            let mut el_ixs_new = vec![];
+            if !rest.is_empty() {
+                el_ixs_new.push(parser_data.get_or_add_terminal(&rest));
+            }
+            el_ixs_new.extend_from_slice(&el_ixs[1..]);
+            el_ixs_new
+        })
+        .collect();
+    (prefix, rests)
+}
+
+fn new_continuation<T>(parser_data: &mut ParserData<T>, rule: ElementIndex) -> ElementIndex
+where
+    T: VM,
+{
+    let name = element_name(parser_data, rule);
+    let name = name.split(GENERATED_NAME_SEPARATOR).next().unwrap_or(&name);
+    let defined_at = parser_data
+        .get_element_data(rule)
+        .and_then(|data| data.defined_at);
+    let mut count = 1;
+    let continuation = loop {
+        let candidate = generated_name(name, &format!("factor{count}"));
+        if parser_data.get_element_nt_index(&candidate).is_none() {
+            break parser_data.get_or_add_non_terminal(&candidate);
+        }
+        count += 1;
+    };
+    if let Some(pos) = defined_at {
+        parser_data.note_definition(continuation, pos);
+    }
+    continuation
+}
+
+fn production_of(el_ixs: Vec<ElementIndex>) -> Production {
+    if el_ixs.is_empty() {
+        Production::Empty
+    } else {
+        Production::NotEmpty(el_ixs)
+    }
+}
+
+fn element_name<T>(parser_data: &ParserData<T>, el_ix: ElementIndex) -> String
+where
+    T: VM,
+{
+    parser_data
+        .get_element_data(el_ix)
+        .map_or_else(|| el_ix.to_string(), |data| data.name.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peekables::PeekableWrapper;
+    use crate::rule_parsing::RuleParser;
+    use crate::vms::NullVm;
+
+    fn productions_of(parser_data: &ParserData<NullVm>, rule: &str) -> Vec<String> {
+        let rule = parser_data.get_element_nt_index(rule).unwrap();
+        parser_data.parse_rules.rules[&rule]
+            .possible_productions
+            .iter()
+            .map(|production| match &**production {
+                Production::Empty => String::from("#"),
+                Production::NotEmpty(el_ixs) => el_ixs
+                    .iter()
+                    .map(|&ix| parser_data.element_notation(ix))
+                    .collect::<Vec<String>>()
+                    .join(" "),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_factor_common_elements() {
+        let mut peekable = PeekableWrapper::from_str(
+            "start -> \"if\" cond \"then\" body | \"if\" cond \"then\" body \"else\" body | \"x\";\
+            cond -> \"c\";\
+            body -> \"b\";",
+        );
+        let vm = NullVm::new();
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+        let parser_data = &mut rule_parser.parser_data;
+
+        let report = left_factor(parser_data);
+        assert_eq!(1, report.len());
+        assert_eq!(
+            "start: factored \"if\" cond \"then\" body out of 2 alternatives into start/factor1",
+            report[0].to_string()
+        );
+        assert_eq!(
+            vec!["\"if\" cond \"then\" body start/factor1", "\"x\""],
+            productions_of(parser_data, "start")
+        );
+        assert_eq!(
+            vec!["#", "\"else\" body"],
+            productions_of(parser_data, "start/factor1")
+        );
+    }
+
+    #[test]
+    fn test_factor_terminal_prefixes() {
+        let mut peekable =
+            PeekableWrapper::from_str("start -> \"print\" | \"pop\" | \"push\" \"1\" | \"pull\";");
+        let vm = NullVm::new();
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+        let parser_data = &mut rule_parser.parser_data;
+
+        let report = left_factor(parser_data);
+        assert_eq!(2, report.len());
+        assert_eq!(
+            vec!["\"p\" start/factor1"],
+            productions_of(parser_data, "start")
+        );
+        assert_eq!(
+            vec!["\"rint\"", "\"op\"", "\"u\" start/factor2"],
+            productions_of(parser_data, "start/factor1")
+        );
+        assert_eq!(
+            vec!["\"sh\" \"1\"", "\"ll\""],
+            productions_of(parser_data, "start/factor2")
+        );
+    }
+
+    #[test]
+    fn test_nothing_to_factor() {
+        let mut peekable = PeekableWrapper::from_str("start -> \"a\" | \"b\" | #;");
+        let vm = NullVm::new();
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+        assert!(left_factor(&mut rule_parser.parser_data).is_empty());
+    }
+}
@@ -0,0 +1,675 @@
+//! Detect left-recursive rules before FIRST sets are computed.
+//!
+//! A rule is left recursive if it can derive itself as the first element of a production,
+//! directly (`expr -> expr "+" term`), through other rules (`expr -> term ...; term -> expr ...`)
+//! or behind elements that can derive the empty string. The recursive FIRST set computation does
+//! not terminate on such grammars, so this pass has to run first.
+//!
+//! [`eliminate_left_recursion`] rewrites left-recursive rules into right-recursive ones for
+//! grammars that opt in.
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+
+use crate::errors::GrammarError;
+use crate::parser_data::{
+    ElementIndex, ElementType, NonTerminalRules, ParserData, Production, generated_name,
+};
+use crate::vms::VM;
+
+/// Computes the non-terminals that can derive the empty string.
+///
+/// Does not rely on FIRST sets, so it is safe to call on left-recursive grammars.
+#[must_use]
+pub fn nullable_non_terminals<T>(parser_data: &ParserData<T>) -> HashSet<ElementIndex>
+where
+    T: VM,
+{
+    let mut nullable = HashSet::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (&el_ix, nt_rules) in &parser_data.parse_rules.rules {
+            if nullable.contains(&el_ix) {
+                continue;
+            }
+            let derives_empty = nt_rules
+                .possible_productions
+                .iter()
+                .any(|production| match &**production {
+                    Production::Empty => true,
+                    Production::NotEmpty(el_ixs) => el_ixs
+                        .iter()
+                        .all(|&ix| is_nullable_element(ix, &nullable, parser_data)),
+                });
+            if derives_empty {
+                nullable.insert(el_ix);
+                changed = true;
+            }
+        }
+    }
+    nullable
+}
+
+fn is_nullable_element<T>(
+    el_ix: ElementIndex,
+    nullable: &HashSet<ElementIndex>,
+    parser_data: &ParserData<T>,
+) -> bool
+where
+    T: VM,
+{
+    match parser_data.element_types[el_ix] {
+        ElementType::NonTerminal => nullable.contains(&el_ix),
+        ElementType::Terminal => parser_data
+            .get_element_data(el_ix)
+            .is_some_and(|data| data.name.is_empty()),
+        ElementType::CharClass => false,
+    }
+}
+
+/// Finds the left-recursive cycles of a grammar.
+///
+/// Every cycle starts and ends with the same rule, e.g. `[expr, term, expr]`. The shortest cycle
+/// through each left-recursive rule is reported, and rules that are already part of a reported
+/// cycle do not start another one. Cycles are ordered by the index of their first rule.
+#[must_use]
+pub fn find_left_recursion<T>(parser_data: &ParserData<T>) -> Vec<Vec<ElementIndex>>
+where
+    T: VM,
+{
+    let left_edges = left_edges(parser_data);
+    let mut rules: Vec<ElementIndex> = cycle_candidates(&left_edges).into_iter().collect();
+    rules.sort_unstable();
+
+    let mut in_cycle = HashSet::new();
+    let mut cycles = vec![];
+    for rule in rules {
+        if in_cycle.contains(&rule) {
+            continue;
+        }
+        if let Some(cycle) = shortest_cycle(rule, &left_edges) {
+            in_cycle.extend(cycle.iter().copied());
+            cycles.push(cycle);
+        }
+    }
+    cycles
+}
+
+/// Checks that the grammar has no left-recursive cycle.
+///
+/// # Errors
+///
+/// Returns [`GrammarError::LeftRecursion`] for the first left-recursive cycle of the grammar.
+pub fn check_left_recursion<T>(parser_data: &ParserData<T>) -> Result<(), GrammarError>
+where
+    T: VM,
+{
+    match find_left_recursion(parser_data).into_iter().next() {
+        None => Ok(()),
+        Some(cycle) => Err(GrammarError::LeftRecursion {
+            cycle: cycle_names(&cycle, parser_data),
+        }),
+    }
+}
+
+/// The names of the rules in `cycle`, for error messages.
+#[must_use]
+pub fn cycle_names<T>(cycle: &[ElementIndex], parser_data: &ParserData<T>) -> Vec<String>
+where
+    T: VM,
+{
+    cycle
+        .iter()
+        .map(|&ix| {
+            parser_data
+                .get_element_data(ix)
+                .map_or_else(|| ix.to_string(), |data| data.name.clone())
+        })
+        .collect()
+}
+
+/// Rewrites left recursion into right recursion with generated tail rules.
+///
+/// `expr -> expr "+" term | term` becomes `expr -> term expr/tail` and
+/// `expr/tail -> "+" term expr/tail | #`. The tail rule adds no tree node of its own, so the
+/// children of the whole chain end up in the node of `expr`. If `expr` has instructions, `term`
+/// and `"+" term` are wrapped into the flattened rules `expr/head` and `expr/step`, which run them
+/// on that node once per level, as often and in the same order as the written grammar would. A
+/// stack VM still sees a left-associative order.
+///
+/// Indirect recursion is resolved by inlining: in `expr -> term "+" | "x"; term -> expr "*" | "("`,
+/// the productions of `expr` replace its leading occurrence in `term`, which is then rewritten like
+/// above. Only rules on the same left-recursive cycle are inlined into each other. A production of
+/// the inlined rule keeps its leading non-terminal in place, the rest of it is wrapped in a
+/// generated rule like `expr/inlined1` whose node runs the instructions of `expr`. Inlined rules
+/// that none of `entry_points` lead to anymore are removed.
+///
+/// Left recursion hidden behind a nullable prefix is left in place, [`check_left_recursion`] still
+/// reports it. Returns the generated tail rules.
+pub fn eliminate_left_recursion<T>(
+    parser_data: &mut ParserData<T>,
+    entry_points: &[ElementIndex],
+) -> Vec<ElementIndex>
+where
+    T: VM,
+{
+    let left_edges = left_edges(parser_data);
+    let mut recursive: Vec<ElementIndex> = left_edges
+        .keys()
+        .copied()
+        .filter(|&rule| shortest_cycle(rule, &left_edges).is_some())
+        .collect();
+    recursive.sort_unstable();
+    let reachable: HashMap<ElementIndex, HashSet<ElementIndex>> = recursive
+        .iter()
+        .map(|&rule| (rule, reachable_rules(rule, &left_edges)))
+        .collect();
+
+    let mut tails = vec![];
+    let mut inlined: HashMap<ElementIndex, Vec<(Option<ElementIndex>, ElementIndex)>> =
+        HashMap::new();
+    for (i, &rule) in recursive.iter().enumerate() {
+        for &earlier in &recursive[..i] {
+            if reachable[&rule].contains(&earlier) && reachable[&earlier].contains(&rule) {
+                inline_leading(parser_data, rule, earlier, &mut inlined);
+            }
+        }
+        if let Some(tail) = split_immediate_recursion(parser_data, rule) {
+            tails.push(tail);
+        }
+    }
+    remove_unreached(parser_data, entry_points, &inlined);
+    tails
+}
+
+/// Replaces `earlier` at the start of the productions of `rule` by the productions of `earlier`,
+/// wrapped into the parts of `earlier` in `inlined`.
+fn inline_leading<T>(
+    parser_data: &mut ParserData<T>,
+    rule: ElementIndex,
+    earlier: ElementIndex,
+    inlined: &mut HashMap<ElementIndex, Vec<(Option<ElementIndex>, ElementIndex)>>,
+) where
+    T: VM,
+{
+    let leads = parser_data.get_rule_by_key(rule).is_some_and(|nt_rules| {
+        nt_rules.possible_productions.iter().any(|production| {
+            matches!(&**production, Production::NotEmpty(el_ixs) if el_ixs[0] == earlier)
+        })
+    });
+    if !leads {
+        return;
+    }
+    let parts = match inlined.entry(earlier) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            let Some(parts) = inlined_parts(parser_data, earlier) else {
+                return;
+            };
+            entry.insert(parts)
+        }
+    };
+    let Some(nt_rules) = parser_data.parse_rules.rules.get_mut(&rule) else {
+        return;
+    };
+    let mut productions = vec![];
+    for production in &nt_rules.possible_productions {
+        match &**production {
+            Production::NotEmpty(el_ixs) if el_ixs[0] == earlier => {
+                for &(leading, part) in parts.iter() {
+                    let mut el_ixs_new: Vec<ElementIndex> = leading.into_iter().collect();
+                    el_ixs_new.push(part);
+                    el_ixs_new.extend_from_slice(&el_ixs[1..]);
+                    productions.push(Rc::new(production_of(el_ixs_new)));
+                }
+            }
+            _ => productions.push(production.clone()),
+        }
+    }
+    nt_rules.possible_productions = productions;
+}
+
+/// Splits the productions of `rule` by their leading non-terminal and adds a generated rule for
+/// the rest of each group, which runs the instructions of `rule`. Productions that don't start
+/// with a non-terminal form a group of their own. `rule -> a "z" | a "y" | "w"` gives
+/// `(a, rule/inlined1)` and `(None, rule/inlined2)` with `rule/inlined1 -> "z" | "y"` and
+/// `rule/inlined2 -> "w"`.
+fn inlined_parts<T>(
+    parser_data: &mut ParserData<T>,
+    rule: ElementIndex,
+) -> Option<Vec<(Option<ElementIndex>, ElementIndex)>>
+where
+    T: VM,
+{
+    let nt_rules = parser_data.parse_rules.rules.get(&rule)?;
+    let ignore = nt_rules.ignore;
+    let mut groups: Vec<(Option<ElementIndex>, Vec<Rc<Production>>)> = vec![];
+    for production in &nt_rules.possible_productions {
+        let (leading, rest) = match &**production {
+            Production::NotEmpty(el_ixs)
+                if parser_data.element_types[el_ixs[0]] == ElementType::NonTerminal =>
+            {
+                (
+                    Some(el_ixs[0]),
+                    Rc::new(production_of(el_ixs[1..].to_vec())),
+                )
+            }
+            _ => (None, production.clone()),
+        };
+        match groups.iter_mut().find(|(other, _)| *other == leading) {
+            Some((_, rests)) => rests.push(rest),
+            None => groups.push((leading, vec![rest])),
+        }
+    }
+    let data = parser_data.get_element_data(rule)?;
+    let name = data.name.clone();
+    let defined_at = data.defined_at;
+    let mut count = 1;
+    let mut parts = vec![];
+    for (leading, rests) in groups {
+        let part = loop {
+            let candidate = generated_name(&name, &format!("inlined{count}"));
+            count += 1;
+            if parser_data.get_element_nt_index(&candidate).is_none() {
+                break parser_data.get_or_add_non_terminal(&candidate);
+            }
+        };
+        if let Some(pos) = defined_at {
+            parser_data.note_definition(part, pos);
+        }
+        let mut part_rule = NonTerminalRules::new(rests, ignore, vec![]);
+        part_rule.instructions_of = Some(rule);
+        parser_data.parse_rules.rules.insert(part, part_rule);
+        parts.push((leading, part));
+    }
+    Some(parts)
+}
+
+/// Removes the inlined rules `entry_points` don't lead to anymore. Their instructions move to
+/// their first part, the other parts run them from there.
+fn remove_unreached<T>(
+    parser_data: &mut ParserData<T>,
+    entry_points: &[ElementIndex],
+    inlined: &HashMap<ElementIndex, Vec<(Option<ElementIndex>, ElementIndex)>>,
+) where
+    T: VM,
+{
+    let reached = parser_data.rules_reached_from(entry_points);
+    for (rule, parts) in inlined {
+        if reached.contains(rule) {
+            continue;
+        }
+        let Some(removed) = parser_data.parse_rules.rules.remove(rule) else {
+            continue;
+        };
+        let Some(&(_, first)) = parts.first() else {
+            continue;
+        };
+        for &(_, part) in parts {
+            if let Some(part_rule) = parser_data.parse_rules.rules.get_mut(&part) {
+                part_rule.instructions_of = Some(first);
+            }
+        }
+        if let Some(first_rule) = parser_data.parse_rules.rules.get_mut(&first) {
+            first_rule.instruction = removed.instruction;
+            first_rule.instructions_of = None;
+        }
+    }
+}
+
+/// Rewrites `rule -> rule a | b` into `rule -> b rule/tail` and `rule/tail -> a rule/tail | #`.
+/// If `rule` has instructions, `b` and `a` are wrapped into `rule/head` and `rule/step`, which run
+/// them once per level of the chain.
+fn split_immediate_recursion<T>(
+    parser_data: &mut ParserData<T>,
+    rule: ElementIndex,
+) -> Option<ElementIndex>
+where
+    T: VM,
+{
+    let nt_rules = parser_data.parse_rules.rules.get(&rule)?;
+    let mut recursive = vec![];
+    let mut others = vec![];
+    for production in &nt_rules.possible_productions {
+        match &**production {
+            Production::NotEmpty(el_ixs) if el_ixs[0] == rule => {
+                recursive.push(el_ixs[1..].to_vec());
+            }
+            Production::NotEmpty(el_ixs) => others.push(el_ixs.clone()),
+            Production::Empty => others.push(vec![]),
+        }
+    }
+    if recursive.is_empty() || others.is_empty() {
+        return None;
+    }
+    let ignore = nt_rules.ignore;
+    let has_instructions = !nt_rules.instruction.is_empty() || nt_rules.instructions_of.is_some();
+    let data = parser_data.get_element_data(rule)?;
+    let defined_at = data.defined_at;
+    let tail_name = generated_name(&data.name, "tail");
+    let tail = parser_data.get_or_add_non_terminal(&tail_name);
+    if let Some(pos) = defined_at {
+        parser_data.note_definition(tail, pos);
+    }
+
+    let (recursive, others) = if has_instructions {
+        let head = add_level_rule(parser_data, rule, "head", others, ignore, defined_at);
+        let step = add_level_rule(parser_data, rule, "step", recursive, ignore, defined_at);
+        let nt_rules = parser_data.parse_rules.rules.get_mut(&rule)?;
+        let instructions_of = nt_rules.instructions_of.take();
+        let instruction = std::mem::take(&mut nt_rules.instruction);
+        let head_rule = parser_data.parse_rules.rules.get_mut(&head)?;
+        head_rule.instruction = instruction;
+        head_rule.instructions_of = instructions_of;
+        let step_rule = parser_data.parse_rules.rules.get_mut(&step)?;
+        step_rule.instructions_of = instructions_of.or(Some(head));
+        (vec![vec![step]], vec![vec![head]])
+    } else {
+        (recursive, others)
+    };
+
+    let mut tail_productions: Vec<Rc<Production>> = recursive
+        .into_iter()
+        .map(|mut el_ixs| {
+            el_ixs.push(tail);
+            Rc::new(production_of(el_ixs))
+        })
+        .collect();
+    tail_productions.push(Rc::new(Production::Empty));
+    let mut tail_rule = NonTerminalRules::new(tail_productions, ignore, vec![]);
+    tail_rule.flatten = true;
+    parser_data.parse_rules.rules.insert(tail, tail_rule);
+
+    let nt_rules = parser_data.parse_rules.rules.get_mut(&rule)?;
+    nt_rules.possible_productions = others
+        .into_iter()
+        .map(|mut el_ixs| {
+            el_ixs.push(tail);
+            Rc::new(production_of(el_ixs))
+        })
+        .collect();
+    Some(tail)
+}
+
+/// Adds the flattened rule `rule/<suffix>` with one production per entry of `alternatives`. It
+/// stands for one level of `rule` in the rewritten chain, so it gets to run its instructions.
+fn add_level_rule<T>(
+    parser_data: &mut ParserData<T>,
+    rule: ElementIndex,
+    suffix: &str,
+    alternatives: Vec<Vec<ElementIndex>>,
+    ignore: Option<ElementIndex>,
+    defined_at: Option<usize>,
+) -> ElementIndex
+where
+    T: VM,
+{
+    let name = parser_data
+        .get_element_data(rule)
+        .map_or_else(|| rule.to_string(), |data| data.name.clone());
+    let level = parser_data.get_or_add_non_terminal(&generated_name(&name, suffix));
+    if let Some(pos) = defined_at {
+        parser_data.note_definition(level, pos);
+    }
+    let productions = alternatives
+        .into_iter()
+        .map(|el_ixs| Rc::new(production_of(el_ixs)))
+        .collect();
+    let mut level_rule = NonTerminalRules::new(productions, ignore, vec![]);
+    level_rule.flatten = true;
+    parser_data.parse_rules.rules.insert(level, level_rule);
+    level
+}
+
+fn production_of(el_ixs: Vec<ElementIndex>) -> Production {
+    if el_ixs.is_empty() {
+        Production::Empty
+    } else {
+        Production::NotEmpty(el_ixs)
+    }
+}
+
+/// The rules reachable from `rule` along the left edges.
+fn reachable_rules(
+    rule: ElementIndex,
+    left_edges: &HashMap<ElementIndex, Vec<ElementIndex>>,
+) -> HashSet<ElementIndex> {
+    let mut reached = HashSet::new();
+    let mut queue = VecDeque::from([rule]);
+    while let Some(current) = queue.pop_front() {
+        for &next in left_edges.get(&current).into_iter().flatten() {
+            if reached.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    reached
+}
+
+/// For every rule, the non-terminals that can be the leftmost element of one of its productions.
+fn left_edges<T>(parser_data: &ParserData<T>) -> HashMap<ElementIndex, Vec<ElementIndex>>
+where
+    T: VM,
+{
+    let nullable = nullable_non_terminals(parser_data);
+    let mut edges = HashMap::new();
+    for (&el_ix, nt_rules) in &parser_data.parse_rules.rules {
+        let mut targets: Vec<ElementIndex> = vec![];
+        for production in &nt_rules.possible_productions {
+            let Production::NotEmpty(el_ixs) = &**production else {
+                continue;
+            };
+            for &ix in el_ixs {
+                if parser_data.element_types[ix] == ElementType::NonTerminal
+                    && !targets.contains(&ix)
+                {
+                    targets.push(ix);
+                }
+                if !is_nullable_element(ix, &nullable, parser_data) {
+                    break;
+                }
+            }
+        }
+        edges.insert(el_ix, targets);
+    }
+    edges
+}
+
+/// The rules that may lie on a cycle of left edges. Rules without incoming or outgoing edges among
+/// the remaining rules are peeled off until none are left, which keeps [`find_left_recursion`]
+/// from searching from every rule of a large grammar.
+fn cycle_candidates(
+    left_edges: &HashMap<ElementIndex, Vec<ElementIndex>>,
+) -> HashSet<ElementIndex> {
+    let mut incoming: HashMap<ElementIndex, Vec<ElementIndex>> = HashMap::new();
+    let mut out_degree: HashMap<ElementIndex, usize> = HashMap::new();
+    let mut in_degree: HashMap<ElementIndex, usize> = HashMap::new();
+    for (&rule, targets) in left_edges {
+        for &target in targets
+            .iter()
+            .filter(|target| left_edges.contains_key(target))
+        {
+            incoming.entry(target).or_default().push(rule);
+            *out_degree.entry(rule).or_default() += 1;
+            *in_degree.entry(target).or_default() += 1;
+        }
+    }
+    let mut remaining: HashSet<ElementIndex> = left_edges.keys().copied().collect();
+    let mut peel: Vec<ElementIndex> = remaining
+        .iter()
+        .copied()
+        .filter(|rule| !out_degree.contains_key(rule) || !in_degree.contains_key(rule))
+        .collect();
+    while let Some(rule) = peel.pop() {
+        if !remaining.remove(&rule) {
+            continue;
+        }
+        for &target in &left_edges[&rule] {
+            if let Some(degree) = in_degree.get_mut(&target) {
+                *degree -= 1;
+                if *degree == 0 {
+                    peel.push(target);
+                }
+            }
+        }
+        for &source in incoming.get(&rule).into_iter().flatten() {
+            let degree = out_degree.get_mut(&source).expect("source has an edge");
+            *degree -= 1;
+            if *degree == 0 {
+                peel.push(source);
+            }
+        }
+    }
+    remaining
+}
+
+/// Breadth-first search from `rule` back to itself along the left edges.
+fn shortest_cycle(
+    rule: ElementIndex,
+    left_edges: &HashMap<ElementIndex, Vec<ElementIndex>>,
+) -> Option<Vec<ElementIndex>> {
+    let mut parents: HashMap<ElementIndex, ElementIndex> = HashMap::new();
+    let mut queue = VecDeque::from([rule]);
+    while let Some(current) = queue.pop_front() {
+        for &next in left_edges.get(&current).into_iter().flatten() {
+            if next == rule {
+                let mut cycle = vec![rule];
+                let mut step = current;
+                loop {
+                    cycle.push(step);
+                    if step == rule {
+                        break;
+                    }
+                    step = parents[&step];
+                }
+                cycle.reverse();
+                return Some(cycle);
+            }
+            if next != rule && !parents.contains_key(&next) {
+                parents.insert(next, current);
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peekables::PeekableWrapper;
+    use crate::rule_parsing::RuleParser;
+    use crate::vms::NullVm;
+
+    fn cycles_of(rules: &str) -> Vec<Vec<String>> {
+        let mut peekable = PeekableWrapper::from_str(rules);
+        let vm = NullVm::new();
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+        let parser_data = &rule_parser.parser_data;
+        find_left_recursion(parser_data)
+            .iter()
+            .map(|cycle| cycle_names(cycle, parser_data))
+            .collect()
+    }
+
+    #[test]
+    fn test_direct_left_recursion() {
+        let cycles = cycles_of("start -> start \"a\" | \"b\";");
+        assert_eq!(vec![vec!["start", "start"]], cycles);
+    }
+
+    #[test]
+    fn test_indirect_left_recursion() {
+        let cycles = cycles_of(
+            "start -> expr;\
+            expr -> term \"+\" | \"x\";\
+            term -> \"(\" | expr \"*\";",
+        );
+        assert_eq!(vec![vec!["expr", "term", "expr"]], cycles);
+    }
+
+    #[test]
+    fn test_left_recursion_behind_nullable_prefix() {
+        let cycles = cycles_of(
+            "start -> opt list;\
+            list -> opt start \"a\" | \"b\";\
+            opt -> \"c\" | #;",
+        );
+        assert_eq!(vec![vec!["start", "list", "start"]], cycles);
+    }
+
+    #[test]
+    fn test_no_left_recursion() {
+        let cycles = cycles_of(
+            "start -> \"a\" start | list;\
+            list -> \"b\" list | #;",
+        );
+        assert!(cycles.is_empty());
+    }
+
+    fn productions_of(parser_data: &ParserData<NullVm>, rule: &str) -> Vec<Vec<String>> {
+        let rule = parser_data.get_element_nt_index(rule).unwrap();
+        parser_data.parse_rules.rules[&rule]
+            .possible_productions
+            .iter()
+            .map(|production| match &**production {
+                Production::Empty => vec![String::from("#")],
+                Production::NotEmpty(el_ixs) => cycle_names(el_ixs, parser_data),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_eliminate_immediate_left_recursion() {
+        let mut peekable =
+            PeekableWrapper::from_str("start -> start \"+\" num | num;num -> \"1\";");
+        let vm = NullVm::new();
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+        let parser_data = &mut rule_parser.parser_data;
+
+        let entry_points = parser_data.entry_points();
+        let tails = eliminate_left_recursion(parser_data, &entry_points);
+        assert_eq!(vec!["start/tail"], cycle_names(&tails, parser_data));
+        assert_eq!(
+            vec![vec!["num", "start/tail"]],
+            productions_of(parser_data, "start")
+        );
+        assert_eq!(
+            vec![vec!["+", "num", "start/tail"], vec!["#"]],
+            productions_of(parser_data, "start/tail")
+        );
+        assert!(find_left_recursion(parser_data).is_empty());
+    }
+
+    #[test]
+    fn test_hidden_left_recursion_is_kept() {
+        let mut peekable = PeekableWrapper::from_str(
+            "start -> opt start \"a\" | \"b\";\
+            opt -> \"c\" | #;",
+        );
+        let vm = NullVm::new();
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+        let parser_data = &mut rule_parser.parser_data;
+
+        let entry_points = parser_data.entry_points();
+        assert!(eliminate_left_recursion(parser_data, &entry_points).is_empty());
+        assert!(check_left_recursion(parser_data).is_err());
+    }
+
+    #[test]
+    fn test_check_left_recursion_names_cycle() {
+        let rules = "start -> expr;\
+            expr -> term \"+\";\
+            term -> expr;";
+        let mut peekable = PeekableWrapper::from_str(rules);
+        let vm = NullVm::new();
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+        let err = check_left_recursion(&rule_parser.parser_data).unwrap_err();
+        assert_eq!("left recursion: expr -> term -> expr", err.to_string());
+    }
+}
@@ -41,24 +41,51 @@
 //!
 //! - `script_parser`: Core parser implementation
 //! - `rule_parsing`: Parsing of grammar rules
+//! - `char_class`: Character classes like `[0-9]` used as grammar elements
 //! - `vms`: Virtual machine implementations
 //! - `parser_data`: Data structures for parser
+//! - `tree`: The parse tree, with the source span of every node
 //! - `first_sets`, `follow_sets`: LL(1) parsing algorithm components
+//! - `left_recursion`: Detection and elimination of left-recursive rules
+//! - `left_factoring`: Factoring out common prefixes of productions
+//! - `lookahead`: LL(k) parsers that look ahead more than one char
+//! - `terminal_trie`: Choosing alternatives by whole terminals where one char is not enough
 //! - `errors`: Error types and handling
+//! - `repair`: Single char edits suggested as quick fixes for syntax errors
+//! - `expected`: What the parser expected at a syntax error, in terms of the grammar
+//! - `diagnostics`: Collecting every problem of a grammar before building a parser
+//! - `conflicts`: Explaining LL(1) conflicts with the competing alternatives and example inputs
+//! - `report`: FIRST/FOLLOW sets and parse tables as text, Markdown or CSV
+//! - `dot`: Graphviz DOT output of the rule reference and FOLLOW graphs
+//! - `railroad`: Railroad diagrams of rules as SVG and an HTML page
+//! - `generator`: Random sentences of a grammar, e.g. as fuzz input
 #![warn(clippy::pedantic)]
+pub mod char_class;
+pub mod conflicts;
+pub mod diagnostics;
+pub mod dot;
 pub mod errors;
+pub mod expected;
 pub mod first_sets;
 pub mod follow_sets;
+pub mod generator;
+pub mod left_factoring;
+pub mod left_recursion;
+pub mod lookahead;
 pub mod named_graph;
 pub mod parse_funcs;
 pub mod parser_data;
 pub mod peekables;
+pub mod railroad;
+pub mod repair;
+pub mod report;
 pub mod rule_parsing;
 pub mod script_parser;
 pub mod sets;
 pub mod simple_graph;
 pub mod steuer_map;
 pub mod steuer_sets;
+pub mod terminal_trie;
 pub mod test_helpers;
-mod tree;
+pub mod tree;
 pub mod vms;
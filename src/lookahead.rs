@@ -0,0 +1,762 @@
+//! LL(k) parsing with up to k chars of lookahead.
+//!
+//! The regular construction picks a production by the next char. Grammars declaring
+//! `$LOOKAHEAD: k;` are built from `FIRST_k` and `FOLLOW_k` sets instead: sets of char sequences of
+//! length k, or shorter ones ending in Terminate. Every production gets the sequences it can start
+//! with, and the parser peeks up to k chars to find the one that matches. This is strong LL(k):
+//! the sequences of an empty production come from the `FOLLOW_k` set of its rule, independent of
+//! where the rule is used.
+//!
+//! [`minimal_lookahead`] tells how many chars a rule needs, which the conflict reports use.
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::errors::GrammarError;
+use crate::parser_data::{ElementIndex, ElementType, ParserData, Production};
+use crate::peekables::TPeekable;
+use crate::sets::SetMember;
+use crate::steuer_map::NTRules;
+use crate::vms::VM;
+
+/// The largest k [`minimal_lookahead`] tries before giving up on a rule.
+pub const MAX_TESTED_LOOKAHEAD: usize = 5;
+/// The most lookaheads a `FIRST_k` or `FOLLOW_k` set may hold before [`minimal_lookahead`] stops
+/// trying larger k.
+pub const MAX_LOOKAHEAD_SET_SIZE: usize = 10_000;
+
+/// A sequence of up to k lookahead chars. Only the last member can be Terminate.
+pub type Lookahead = Vec<SetMember>;
+pub type LookaheadSets = HashMap<ElementIndex, HashSet<Lookahead>>;
+/// The lookaheads that select each production of a rule.
+pub type LookaheadTable = Vec<(Lookahead, Rc<Production>)>;
+
+/// True if the upcoming input starts with `lookahead`.
+pub fn matches_lookahead<P>(lookahead: &[SetMember], to_parse: &mut P) -> bool
+where
+    P: TPeekable<Item = char>,
+{
+    lookahead
+        .iter()
+        .enumerate()
+        .all(|(ahead, member)| SetMember::from(to_parse.peek_nth(ahead)).overlaps(member))
+}
+
+/// The production whose lookahead the upcoming input starts with.
+pub fn find_in_lookahead_table<'t, P>(
+    table: &'t LookaheadTable,
+    to_parse: &mut P,
+) -> Option<&'t Rc<Production>>
+where
+    P: TPeekable<Item = char>,
+{
+    table
+        .iter()
+        .find(|(lookahead, _)| matches_lookahead(lookahead, to_parse))
+        .map(|(_, production)| production)
+}
+
+/// How many of the upcoming chars match the start of a lookahead of the table, and the members
+/// that could have come next there, sorted. Tells where the input stopped matching every
+/// lookahead.
+pub fn deepest_lookahead_match<P>(
+    table: &LookaheadTable,
+    to_parse: &mut P,
+) -> (usize, Vec<SetMember>)
+where
+    P: TPeekable<Item = char>,
+{
+    let mut deepest = 0;
+    let mut next: Vec<SetMember> = vec![];
+    for (lookahead, _) in table {
+        let matched = lookahead
+            .iter()
+            .enumerate()
+            .take_while(|(ahead, member)| {
+                SetMember::from(to_parse.peek_nth(*ahead)).overlaps(member)
+            })
+            .count();
+        let Some(&member) = lookahead.get(matched) else {
+            continue;
+        };
+        if matched > deepest {
+            deepest = matched;
+            next.clear();
+        }
+        if matched == deepest {
+            next.push(member);
+        }
+    }
+    next.sort_unstable();
+    next.dedup();
+    (deepest, next)
+}
+
+/// The production to take if the input may end at any char, as in
+/// [`Parser::parse_prefix`](crate::script_parser::Parser::parse_prefix): a lookahead ending in
+/// Terminate fits if the upcoming input starts with the chars before it, whatever comes next. Of
+/// several, the one with the most chars is taken.
+pub fn find_in_lookahead_table_as_prefix<'t, P>(
+    table: &'t LookaheadTable,
+    to_parse: &mut P,
+) -> Option<&'t Rc<Production>>
+where
+    P: TPeekable<Item = char>,
+{
+    table
+        .iter()
+        .filter_map(|(lookahead, production)| match lookahead.split_last() {
+            Some((SetMember::Terminate, chars)) if matches_lookahead(chars, to_parse) => {
+                Some((chars.len(), production))
+            }
+            _ => None,
+        })
+        .max_by_key(|(chars, _)| *chars)
+        .map(|(_, production)| production)
+}
+
+/// The lookahead in grammar notation, e.g. `"ab"` or `"a" terminate`.
+#[must_use]
+pub fn describe_lookahead(lookahead: &[SetMember]) -> String {
+    if lookahead.is_empty() {
+        return String::from("#");
+    }
+    let mut chars = String::new();
+    let mut parts = vec![];
+    for member in lookahead {
+        match member {
+            SetMember::Char(chr) => chars.push(*chr),
+            other => {
+                if !chars.is_empty() {
+                    parts.push(format!("{chars:?}"));
+                    chars.clear();
+                }
+                parts.push(other.to_string());
+            }
+        }
+    }
+    if !chars.is_empty() {
+        parts.push(format!("{chars:?}"));
+    }
+    parts.join(" ")
+}
+
+/// `left` followed by `right`, cut to k members. Lookaheads that are already complete are kept
+/// as they are.
+fn concat_k(left: &HashSet<Lookahead>, right: &HashSet<Lookahead>, k: usize) -> HashSet<Lookahead> {
+    let mut result = HashSet::new();
+    // `right` cut to each length needed, as many lookaheads only differ after the cut
+    let mut cut: HashMap<usize, HashSet<&[SetMember]>> = HashMap::new();
+    for first in left {
+        if first.len() >= k || first.last() == Some(&SetMember::Terminate) {
+            result.insert(first.clone());
+            continue;
+        }
+        let missing = k - first.len();
+        let seconds = cut.entry(missing).or_insert_with(|| {
+            right
+                .iter()
+                .map(|second| &second[..missing.min(second.len())])
+                .collect()
+        });
+        for second in seconds.iter() {
+            let mut joined = first.clone();
+            joined.extend_from_slice(second);
+            result.insert(joined);
+        }
+    }
+    result
+}
+
+fn first_k_of_element<'s, T>(
+    el_ix: ElementIndex,
+    first_k_sets: &'s LookaheadSets,
+    parser_data: &ParserData<T>,
+    k: usize,
+) -> Result<Cow<'s, HashSet<Lookahead>>, GrammarError>
+where
+    T: VM,
+{
+    Ok(match parser_data.element_types[el_ix] {
+        ElementType::Terminal => {
+            let name = &parser_data
+                .get_element_data(el_ix)
+                .ok_or(GrammarError::MissingElementForIndex { index: el_ix })?
+                .name;
+            Cow::Owned(HashSet::from([name
+                .chars()
+                .take(k)
+                .map(SetMember::Char)
+                .collect()]))
+        }
+        ElementType::CharClass => Cow::Owned(
+            parser_data
+                .get_char_class(el_ix)
+                .ok_or(GrammarError::MissingElementForIndex { index: el_ix })?
+                .set_members()
+                .into_iter()
+                .map(|member| vec![member])
+                .collect(),
+        ),
+        ElementType::NonTerminal => first_k_sets
+            .get(&el_ix)
+            .map_or_else(|| Cow::Owned(HashSet::new()), Cow::Borrowed),
+    })
+}
+
+/// The `FIRST_k` set of a sequence of elements, given the `FIRST_k` sets of the non-terminals.
+///
+/// # Errors
+/// Fails if a terminal or char class of `el_ixs` is missing from `parser_data`.
+pub fn first_k_of_partial<T>(
+    el_ixs: &[ElementIndex],
+    first_k_sets: &LookaheadSets,
+    parser_data: &ParserData<T>,
+    k: usize,
+) -> Result<HashSet<Lookahead>, GrammarError>
+where
+    T: VM,
+{
+    let mut set = HashSet::from([vec![]]);
+    for &el_ix in el_ixs {
+        if set.iter().all(|lookahead| lookahead.len() >= k) {
+            break;
+        }
+        let first = first_k_of_element(el_ix, first_k_sets, parser_data, k)?;
+        set = concat_k(&set, &first, k);
+    }
+    Ok(set)
+}
+
+fn first_k_of_production<T>(
+    production: &Production,
+    first_k_sets: &LookaheadSets,
+    parser_data: &ParserData<T>,
+    k: usize,
+) -> Result<HashSet<Lookahead>, GrammarError>
+where
+    T: VM,
+{
+    match production {
+        Production::Empty => Ok(HashSet::from([vec![]])),
+        Production::NotEmpty(el_ixs) => first_k_of_partial(el_ixs, first_k_sets, parser_data, k),
+    }
+}
+
+/// Computes the `FIRST_k` sets of all non-terminals. Sequences shorter than k are complete words.
+///
+/// # Errors
+/// Fails if an element of a production is missing from `parser_data`.
+pub fn get_first_k_sets<T>(
+    parser_data: &ParserData<T>,
+    k: usize,
+) -> Result<LookaheadSets, GrammarError>
+where
+    T: VM,
+{
+    let rules = parser_data.parse_rules.rules.keys().copied().collect();
+    Ok(first_k_sets_of(&rules, parser_data, k, usize::MAX)?.unwrap_or_default())
+}
+
+/// The `FIRST_k` sets of `rules`, which must contain every non-terminal their productions use.
+/// `None` if a set grows beyond `limit` lookaheads.
+fn first_k_sets_of<T>(
+    rules: &HashSet<ElementIndex>,
+    parser_data: &ParserData<T>,
+    k: usize,
+    limit: usize,
+) -> Result<Option<LookaheadSets>, GrammarError>
+where
+    T: VM,
+{
+    let mut first_k_sets: LookaheadSets =
+        rules.iter().map(|&el_ix| (el_ix, HashSet::new())).collect();
+    // After the first pass only productions using a rule that changed in the last pass can add
+    // anything.
+    let mut changed: Option<HashSet<ElementIndex>> = None;
+    while changed.as_ref().is_none_or(|changed| !changed.is_empty()) {
+        let mut changed_now = HashSet::new();
+        for &el_ix in rules {
+            let nt_rules = parser_data
+                .get_rule_by_key(el_ix)
+                .ok_or(GrammarError::MissingProduction { index: el_ix })?;
+            let mut set = HashSet::new();
+            for production in &nt_rules.possible_productions {
+                let affected = match (&changed, &**production) {
+                    (None, _) => true,
+                    (Some(_), Production::Empty) => false,
+                    (Some(changed), Production::NotEmpty(el_ixs)) => {
+                        el_ixs.iter().any(|el_ix| changed.contains(el_ix))
+                    }
+                };
+                if affected {
+                    set.extend(first_k_of_production(
+                        production,
+                        &first_k_sets,
+                        parser_data,
+                        k,
+                    )?);
+                }
+            }
+            let known = first_k_sets.entry(el_ix).or_default();
+            if !set.is_subset(known) {
+                known.extend(set);
+                if known.len() > limit {
+                    return Ok(None);
+                }
+                changed_now.insert(el_ix);
+            }
+        }
+        changed = Some(changed_now);
+    }
+    Ok(Some(first_k_sets))
+}
+
+/// Computes the `FOLLOW_k` sets of all non-terminals, seeding entry points and their ignore rule
+/// like [`get_follow_sets_for_entry_points`](crate::follow_sets::get_follow_sets_for_entry_points).
+///
+/// # Errors
+/// Fails if an element of a production is missing from `parser_data`, or the `FIRST_k` set of an
+/// entry point with an ignore rule is missing from `first_k_sets`.
+pub fn get_follow_k_sets<T>(
+    entry_points: &[ElementIndex],
+    first_k_sets: &LookaheadSets,
+    parser_data: &ParserData<T>,
+    k: usize,
+) -> Result<LookaheadSets, GrammarError>
+where
+    T: VM,
+{
+    let rules = parser_data.parse_rules.rules.keys().copied().collect();
+    Ok(follow_k_sets_of(
+        &rules,
+        entry_points,
+        first_k_sets,
+        parser_data,
+        k,
+        usize::MAX,
+    )?
+    .unwrap_or_default())
+}
+
+/// The `FOLLOW_k` sets of `rules`, which must contain every rule using one of them. `None` if a set
+/// grows beyond `limit` lookaheads.
+///
+/// The `FIRST_k` set of what comes after a non-terminal in a production is computed once. The parts
+/// of it that are complete lookaheads go straight into the `FOLLOW_k` set, the others are extended by
+/// the lookaheads that newly reach the `FOLLOW_k` set of the rule, until nothing changes.
+fn follow_k_sets_of<T>(
+    rules: &HashSet<ElementIndex>,
+    entry_points: &[ElementIndex],
+    first_k_sets: &LookaheadSets,
+    parser_data: &ParserData<T>,
+    k: usize,
+    limit: usize,
+) -> Result<Option<LookaheadSets>, GrammarError>
+where
+    T: VM,
+{
+    let terminate = HashSet::from([vec![SetMember::Terminate]]);
+    let mut follow_k_sets: LookaheadSets =
+        rules.iter().map(|&el_ix| (el_ix, HashSet::new())).collect();
+    for &start in entry_points {
+        if let Some(follow) = follow_k_sets.get_mut(&start) {
+            follow.extend(terminate.clone());
+        }
+        if let Some(ignore) = parser_data
+            .get_rule_by_key(start)
+            .and_then(|rule| rule.ignore)
+            .filter(|ignore| rules.contains(ignore))
+        {
+            let first_of_start = first_k_sets
+                .get(&start)
+                .ok_or(GrammarError::MissingFirstSet { index: start })?;
+            let ignore_follow = follow_k_sets.entry(ignore).or_default();
+            ignore_follow.extend(terminate.clone());
+            ignore_follow.extend(concat_k(first_of_start, &terminate, k));
+        }
+    }
+
+    // For each rule, the rules used in it with the open lookaheads of what comes after them.
+    let mut continuations: HashMap<ElementIndex, Vec<(ElementIndex, HashSet<Lookahead>)>> =
+        HashMap::new();
+    for &el_ix in rules {
+        let nt_rules = parser_data
+            .get_rule_by_key(el_ix)
+            .ok_or(GrammarError::MissingProduction { index: el_ix })?;
+        for production in &nt_rules.possible_productions {
+            let Production::NotEmpty(el_ixs) = &**production else {
+                continue;
+            };
+            for (i, &next) in el_ixs.iter().enumerate() {
+                if !rules.contains(&next) {
+                    continue;
+                }
+                let (complete, open): (HashSet<Lookahead>, HashSet<Lookahead>) =
+                    first_k_of_partial(&el_ixs[i + 1..], first_k_sets, parser_data, k)?
+                        .into_iter()
+                        .partition(|lookahead| {
+                            lookahead.len() >= k || lookahead.last() == Some(&SetMember::Terminate)
+                        });
+                follow_k_sets.entry(next).or_default().extend(complete);
+                if !open.is_empty() {
+                    continuations.entry(el_ix).or_default().push((next, open));
+                }
+            }
+        }
+    }
+
+    let mut added: LookaheadSets = follow_k_sets.clone();
+    while let Some(&el_ix) = added.keys().next() {
+        let new = added.remove(&el_ix).unwrap_or_default();
+        for (next, open) in continuations.get(&el_ix).into_iter().flatten() {
+            let known = follow_k_sets.entry(*next).or_default();
+            for lookahead in concat_k(open, &new, k) {
+                if !known.contains(&lookahead) {
+                    known.insert(lookahead.clone());
+                    added.entry(*next).or_default().insert(lookahead);
+                }
+            }
+            if known.len() > limit {
+                return Ok(None);
+            }
+        }
+    }
+    Ok(Some(follow_k_sets))
+}
+
+/// The lookahead table of a rule.
+///
+/// # Errors
+/// Fails with [`GrammarError::LookaheadNotDistinct`] if two productions share a lookahead, or if
+/// the rule, an element of its productions or its `FOLLOW_k` set is missing.
+pub fn lookahead_table_of_rule<T>(
+    rule: ElementIndex,
+    first_k_sets: &LookaheadSets,
+    follow_k_sets: &LookaheadSets,
+    parser_data: &ParserData<T>,
+    k: usize,
+) -> Result<LookaheadTable, GrammarError>
+where
+    T: VM,
+{
+    let nt_rules = parser_data
+        .get_rule_by_key(rule)
+        .ok_or(GrammarError::MissingProduction { index: rule })?;
+    let follow = follow_k_sets
+        .get(&rule)
+        .ok_or(GrammarError::MissingFollowSet { index: rule })?;
+    let mut table: LookaheadTable = vec![];
+    for production in &nt_rules.possible_productions {
+        let first = first_k_of_production(production, first_k_sets, parser_data, k)?;
+        let mut lookaheads: Vec<Lookahead> = concat_k(&first, follow, k).into_iter().collect();
+        lookaheads.sort();
+        for lookahead in lookaheads {
+            let clash = table.iter().find(|(other, other_production)| {
+                !Rc::ptr_eq(other_production, production) && lookaheads_overlap(other, &lookahead)
+            });
+            if let Some((other, _)) = clash {
+                return Err(GrammarError::LookaheadNotDistinct {
+                    lookahead: describe_lookahead(other),
+                    rule_name: parser_data
+                        .get_element_data(rule)
+                        .map_or_else(|| rule.to_string(), |data| data.name.clone()),
+                    k,
+                });
+            }
+            table.push((lookahead, Rc::clone(production)));
+        }
+    }
+    Ok(table)
+}
+
+fn lookaheads_overlap(first: &[SetMember], second: &[SetMember]) -> bool {
+    first
+        .iter()
+        .zip(second)
+        .all(|(first, second)| first.overlaps(second))
+}
+
+/// Builds the rules of an LL(k) parser, with a lookahead table instead of a steuer map.
+///
+/// # Errors
+/// Fails like [`lookahead_table_of_rule`] for the first rule whose table can't be built.
+pub fn get_lookahead_rules<T>(
+    parser_data: ParserData<T>,
+    entry_points: &[ElementIndex],
+    k: usize,
+) -> Result<HashMap<ElementIndex, NTRules<T>>, GrammarError>
+where
+    T: VM,
+{
+    let first_k_sets = get_first_k_sets(&parser_data, k)?;
+    let follow_k_sets = get_follow_k_sets(entry_points, &first_k_sets, &parser_data, k)?;
+    let mut tables = HashMap::new();
+    for &rule in parser_data.parse_rules.rules.keys() {
+        let table = lookahead_table_of_rule(rule, &first_k_sets, &follow_k_sets, &parser_data, k)?;
+        tables.insert(rule, table);
+    }
+    Ok(parser_data
+        .parse_rules
+        .rules
+        .into_iter()
+        .map(|(rule, productions)| {
+            (
+                rule,
+                NTRules {
+                    steuermap: HashMap::new(),
+                    ignore: productions.ignore,
+                    instruction: productions.instruction,
+                    flatten: productions.flatten,
+                    instructions_of: productions.instructions_of,
+                    tries: HashMap::new(),
+                    lookahead: tables.remove(&rule).unwrap_or_default(),
+                },
+            )
+        })
+        .collect())
+}
+
+/// Like [`get_lookahead_rules`], but collects the failing rules instead of stopping at the
+/// first. Returns at most one error per rule, sorted by rule.
+///
+/// # Errors
+/// Fails if an element of a production is missing from `parser_data`.
+pub fn find_lookahead_conflicts<T>(
+    parser_data: &ParserData<T>,
+    entry_points: &[ElementIndex],
+    k: usize,
+) -> Result<Vec<(ElementIndex, GrammarError)>, GrammarError>
+where
+    T: VM,
+{
+    let first_k_sets = get_first_k_sets(parser_data, k)?;
+    let follow_k_sets = get_follow_k_sets(entry_points, &first_k_sets, parser_data, k)?;
+    let mut rules: Vec<ElementIndex> = parser_data.parse_rules.rules.keys().copied().collect();
+    rules.sort_unstable();
+    Ok(rules
+        .into_iter()
+        .filter_map(|rule| {
+            lookahead_table_of_rule(rule, &first_k_sets, &follow_k_sets, parser_data, k)
+                .err()
+                .map(|err| (rule, err))
+        })
+        .collect())
+}
+
+/// The smallest k up to `max_k` for which each of `rules` has no lookahead conflict, or `None`
+/// if it has conflicts for every tested k, together with the largest k that was tested.
+///
+/// Each k only computes the `FIRST_k` and `FOLLOW_k` sets the undecided rules depend on. Testing
+/// stops before the first k for which one of them holds more than [`MAX_LOOKAHEAD_SET_SIZE`]
+/// lookaheads, so the largest tested k can be below `max_k`.
+///
+/// # Errors
+/// Fails if a rule or an element of a production is missing from `parser_data`.
+pub fn minimal_lookahead<T>(
+    parser_data: &ParserData<T>,
+    entry_points: &[ElementIndex],
+    rules: &[ElementIndex],
+    max_k: usize,
+) -> Result<(HashMap<ElementIndex, Option<usize>>, usize), GrammarError>
+where
+    T: VM,
+{
+    let mut needed: HashMap<ElementIndex, Option<usize>> =
+        rules.iter().map(|&rule| (rule, None)).collect();
+    for k in 1..=max_k {
+        let undecided: Vec<ElementIndex> = needed
+            .iter()
+            .filter(|(_, minimal)| minimal.is_none())
+            .map(|(&rule, _)| rule)
+            .collect();
+        if undecided.is_empty() {
+            return Ok((needed, max_k));
+        }
+        let follow_rules = users_closure(&undecided, parser_data);
+        let ignoring_entry_points = entry_points.iter().copied().filter(|&start| {
+            parser_data
+                .get_rule_by_key(start)
+                .and_then(|rule| rule.ignore)
+                .is_some_and(|ignore| follow_rules.contains(&ignore))
+        });
+        let first_rules = uses_closure(
+            follow_rules.iter().copied().chain(ignoring_entry_points),
+            parser_data,
+        );
+        let Some(first_k_sets) =
+            first_k_sets_of(&first_rules, parser_data, k, MAX_LOOKAHEAD_SET_SIZE)?
+        else {
+            return Ok((needed, k - 1));
+        };
+        let Some(follow_k_sets) = follow_k_sets_of(
+            &follow_rules,
+            entry_points,
+            &first_k_sets,
+            parser_data,
+            k,
+            MAX_LOOKAHEAD_SET_SIZE,
+        )?
+        else {
+            return Ok((needed, k - 1));
+        };
+        for rule in undecided {
+            if lookahead_table_of_rule(rule, &first_k_sets, &follow_k_sets, parser_data, k).is_ok()
+            {
+                needed.insert(rule, Some(k));
+            }
+        }
+    }
+    Ok((needed, max_k))
+}
+
+/// `rules` and every non-terminal their productions use, transitively.
+fn uses_closure<T>(
+    rules: impl IntoIterator<Item = ElementIndex>,
+    parser_data: &ParserData<T>,
+) -> HashSet<ElementIndex>
+where
+    T: VM,
+{
+    let mut closure = HashSet::new();
+    let mut todo: Vec<ElementIndex> = rules.into_iter().collect();
+    while let Some(rule) = todo.pop() {
+        if !closure.insert(rule) {
+            continue;
+        }
+        let Some(nt_rules) = parser_data.get_rule_by_key(rule) else {
+            continue;
+        };
+        for production in &nt_rules.possible_productions {
+            if let Production::NotEmpty(el_ixs) = &**production {
+                todo.extend(el_ixs.iter().filter(|&&el_ix| {
+                    parser_data.element_types[el_ix] == ElementType::NonTerminal
+                }));
+            }
+        }
+    }
+    closure
+}
+
+/// `rules` and every rule using one of them, transitively.
+fn users_closure<T>(rules: &[ElementIndex], parser_data: &ParserData<T>) -> HashSet<ElementIndex>
+where
+    T: VM,
+{
+    let mut users: HashMap<ElementIndex, Vec<ElementIndex>> = HashMap::new();
+    for (&rule, nt_rules) in &parser_data.parse_rules.rules {
+        for production in &nt_rules.possible_productions {
+            if let Production::NotEmpty(el_ixs) = &**production {
+                for &el_ix in el_ixs {
+                    users.entry(el_ix).or_default().push(rule);
+                }
+            }
+        }
+    }
+    let mut closure = HashSet::new();
+    let mut todo = rules.to_vec();
+    while let Some(rule) = todo.pop() {
+        if closure.insert(rule) {
+            todo.extend(users.get(&rule).into_iter().flatten());
+        }
+    }
+    closure
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peekables::PeekableWrapper;
+    use crate::rule_parsing::RuleParser;
+    use crate::vms::NullVm;
+    use std::time::{Duration, Instant};
+
+    fn lookaheads(set: &HashSet<Lookahead>) -> Vec<String> {
+        let mut described: Vec<String> = set.iter().map(|la| describe_lookahead(la)).collect();
+        described.sort();
+        described
+    }
+
+    #[test]
+    fn test_first_and_follow_k() {
+        let mut peekable = PeekableWrapper::from_str(
+            "start -> a \"b\";\
+            a -> \"x\" a | #;",
+        );
+        let vm = NullVm::new();
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+        let parser_data = &rule_parser.parser_data;
+        let start = parser_data.get_element_nt_index("start").unwrap();
+        let a = parser_data.get_element_nt_index("a").unwrap();
+
+        let first = get_first_k_sets(parser_data, 2).unwrap();
+        assert_eq!(
+            vec!["\"b\"", "\"xb\"", "\"xx\""],
+            lookaheads(&first[&start])
+        );
+        assert_eq!(vec!["\"x\"", "\"xx\"", "#"], lookaheads(&first[&a]));
+
+        let follow = get_follow_k_sets(&[start], &first, parser_data, 2).unwrap();
+        assert_eq!(vec!["\"b\" terminate"], lookaheads(&follow[&a]));
+    }
+
+    #[test]
+    fn test_minimal_lookahead() {
+        let mut peekable = PeekableWrapper::from_str(
+            "start -> a | b | c;\
+            a -> \"x\" \"y\" \"1\";\
+            b -> \"x\" \"y\" \"2\";\
+            c -> d \"z\" | d \"z\";\
+            d -> \"q\";",
+        );
+        let vm = NullVm::new();
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+        let parser_data = &rule_parser.parser_data;
+        let start = parser_data.get_element_nt_index("start").unwrap();
+        let c = parser_data.get_element_nt_index("c").unwrap();
+
+        let (needed, tested_k) = minimal_lookahead(parser_data, &[start], &[start, c], 4).unwrap();
+        assert_eq!(4, tested_k);
+        assert_eq!(Some(3), needed[&start]);
+        assert_eq!(None, needed[&c]);
+    }
+
+    #[test]
+    fn test_minimal_lookahead_of_expression_grammar_is_bounded() {
+        let mut peekable = PeekableWrapper::from_str(
+            "start -> stmt+;\
+            stmt -> assign | call | \"while \" expr \" do \" stmt | \"{\" stmt* \"}\"\
+                | \"return \" expr? \";\" | \"if \" expr \" then \" stmt | \"print \" expr \";\";\
+            assign -> name \"=\" expr \";\";\
+            call -> name \"(\" args \")\" \";\";\
+            args -> expr (\",\" expr)* | #;\
+            expr -> cmp ((\"&&\" | \"||\") cmp)*;\
+            cmp -> sum ((\"==\" | \"!=\" | \"<\" | \">\") sum)?;\
+            sum -> term ((\"+\" | \"-\") term)*;\
+            term -> factor ((\"*\" | \"/\") factor)*;\
+            factor -> num | string | \"true\" | \"false\" | name | name \"(\" args \")\"\
+                | \"(\" expr \")\" | \"-\" factor;\
+            name -> [a-z] [a-z0-9_]*;\
+            num -> [0-9]+ (\".\" [0-9]+)?;\
+            string -> \"\\\"\" [^\"]* \"\\\"\";",
+        );
+        let vm = NullVm::new();
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+        let parser_data = &rule_parser.parser_data;
+        let start = parser_data.get_element_nt_index("start").unwrap();
+        let stmt = parser_data.get_element_nt_index("stmt").unwrap();
+        let factor = parser_data.get_element_nt_index("factor").unwrap();
+
+        let started = Instant::now();
+        let (needed, tested_k) =
+            minimal_lookahead(parser_data, &[start], &[stmt, factor], 5).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(20));
+        // the sets for k = 5 hold more than MAX_LOOKAHEAD_SET_SIZE lookaheads
+        assert_eq!(4, tested_k);
+        assert_eq!(None, needed[&stmt]);
+        assert_eq!(None, needed[&factor]);
+    }
+}
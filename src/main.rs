@@ -1,8 +1,12 @@
 extern crate core;
 
+use henriks_parsing_project::dot::{DotGraph, DotOptions, dot_from_text};
+use henriks_parsing_project::railroad::RailroadDiagrams;
+use henriks_parsing_project::report::{GrammarReport, ReportFormat};
 use henriks_parsing_project::script_parser::Parser;
 use henriks_parsing_project::vms::{NullVm, VM};
-use std::{env, fs};
+use std::path::Path;
+use std::{env, fs, process};
 
 /// Main entry point for the command-line tool.
 ///
@@ -13,9 +17,36 @@ use std::{env, fs};
 /// 2. Path to a file containing a script to parse
 ///
 /// It parses the script according to the grammar rules and returns the parse tree.
+///
+/// Usage: henriks-parsing-project tables <rule_file> [text|markdown|csv]
+///
+/// Prints the nullable flag, FIRST and FOLLOW set and parse table of every rule, see
+/// [`GrammarReport`].
+///
+/// Usage: henriks-parsing-project dot <rule_file> [rules|follow] [--terminals]
+///
+/// Prints the rule reference graph or the FOLLOW propagation graph as Graphviz DOT, see
+/// [`henriks_parsing_project::dot`].
+///
+/// Usage: henriks-parsing-project railroad <rule_file> <out_dir>
+///
+/// Writes a railroad diagram SVG per rule and an `index.html` with all of them to `out_dir`, see
+/// [`RailroadDiagrams`].
 
 fn main() {
     let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("tables") {
+        print_tables(&args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("dot") {
+        print_dot(&args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("railroad") {
+        write_railroad_diagrams(&args[2..]);
+        return;
+    }
     let rule_path = &args[1];
     let script_path = &args[2];
     let rules = fs::read_to_string(rule_path).expect("Unable to read rule file");
@@ -27,3 +58,76 @@ fn main() {
 
     let graph = parser.parse(&script, &mut state).unwrap();
 }
+
+fn print_tables(args: &[String]) {
+    let Some(rule_path) = args.first() else {
+        eprintln!("Usage: henriks-parsing-project tables <rule_file> [text|markdown|csv]");
+        process::exit(2);
+    };
+    let format = args
+        .get(1)
+        .map_or(Ok(ReportFormat::Text), |format| format.parse())
+        .unwrap_or_else(|err| {
+            eprintln!("{err}");
+            process::exit(2);
+        });
+    let rules = fs::read_to_string(rule_path).expect("Unable to read rule file");
+
+    let vm = NullVm::new();
+    match GrammarReport::from_text(&rules, &vm) {
+        Ok(report) => print!("{}", report.render(format)),
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+    }
+}
+
+fn print_dot(args: &[String]) {
+    let Some(rule_path) = args.first() else {
+        eprintln!("Usage: henriks-parsing-project dot <rule_file> [rules|follow] [--terminals]");
+        process::exit(2);
+    };
+    let options = DotOptions {
+        terminals: args[1..].iter().any(|arg| arg == "--terminals"),
+    };
+    let graph = args[1..]
+        .iter()
+        .find(|arg| *arg != "--terminals")
+        .map_or(Ok(DotGraph::Rules), |graph| graph.parse())
+        .unwrap_or_else(|err| {
+            eprintln!("{err}");
+            process::exit(2);
+        });
+    let rules = fs::read_to_string(rule_path).expect("Unable to read rule file");
+
+    let vm = NullVm::new();
+    match dot_from_text(&rules, &vm, graph, options) {
+        Ok(dot) => print!("{dot}"),
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+    }
+}
+
+fn write_railroad_diagrams(args: &[String]) {
+    let [rule_path, out_dir] = args else {
+        eprintln!("Usage: henriks-parsing-project railroad <rule_file> <out_dir>");
+        process::exit(2);
+    };
+    let rules = fs::read_to_string(rule_path).expect("Unable to read rule file");
+
+    let vm = NullVm::new();
+    let diagrams = RailroadDiagrams::from_text(&rules, &vm).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        process::exit(1);
+    });
+    let out_dir = Path::new(out_dir);
+    fs::create_dir_all(out_dir).expect("Unable to create output directory");
+    for (file_name, svg) in diagrams.svg_files() {
+        fs::write(out_dir.join(file_name), svg).expect("Unable to write diagram");
+    }
+    fs::write(out_dir.join("index.html"), diagrams.html_index(rule_path))
+        .expect("Unable to write index");
+}
@@ -134,6 +134,20 @@ impl<Ta> GraphNamedNodes<Ta> {
             current_edge_index: first_outgoing_edge,
         })
     }
+
+    /// All edges as pairs of source and target name, in no particular order.
+    #[must_use]
+    pub fn named_edges(&self) -> Vec<(ElementIndex, ElementIndex)> {
+        let names_by_index: HashMap<NodeIndex, ElementIndex> =
+            self.names.iter().map(|(&name, &index)| (index, name)).collect();
+        let mut edges = vec![];
+        for (&source, &index) in &self.names {
+            for target in self.graph.successors(index) {
+                edges.push((source, names_by_index[&target]));
+            }
+        }
+        edges
+    }
 }
 pub struct SuccessorsData<'graph, Ta> {
     graph: &'graph GraphNamedNodes<Ta>,
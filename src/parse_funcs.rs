@@ -4,10 +4,89 @@
 //! from a ParseProcess and are reusable building blocks for higher-level
 //! parsers.
 
+use crate::errors::GrammarError::UnexpectedElementError;
 use crate::errors::ParserError;
 use crate::errors::ParserError::{EndOfCharsError, Impossible, UnexpectedCharError};
 use crate::peekables::{ParseProcess, TPeekable};
 
+/// A position in a text as char offset, byte offset and 1-based line and column.
+///
+/// Char offsets are what [`ParseProcess::cur_pos`] and the positions in errors count, byte
+/// offsets can slice the text directly.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub struct SourcePosition {
+    pub char_offset: usize,
+    pub byte_offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourcePosition {
+    /// The position of the char at char offset `pos` in `text`.
+    #[must_use]
+    pub fn of(text: &str, pos: usize) -> SourcePosition {
+        text.chars()
+            .take(pos)
+            .fold(SourcePosition::default(), SourcePosition::after)
+    }
+
+    /// The position right after `chr`, if `chr` is at this position.
+    #[must_use]
+    pub fn after(self, chr: char) -> SourcePosition {
+        let (line, column) = if chr == '\n' {
+            (self.line + 1, 1)
+        } else {
+            (self.line, self.column + 1)
+        };
+        SourcePosition {
+            char_offset: self.char_offset + 1,
+            byte_offset: self.byte_offset + chr.len_utf8(),
+            line,
+            column,
+        }
+    }
+}
+
+impl Default for SourcePosition {
+    /// The start of a text.
+    fn default() -> Self {
+        SourcePosition {
+            char_offset: 0,
+            byte_offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl std::fmt::Display for SourcePosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// The part of a text a parse tree node was parsed from. `end` is the position after its last
+/// char, so an empty span has `start == end`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash, Default)]
+pub struct Span {
+    pub start: SourcePosition,
+    pub end: SourcePosition,
+}
+
+impl Span {
+    /// The spanned part of the text the span was parsed from.
+    #[must_use]
+    pub fn text<'t>(&self, text: &'t str) -> &'t str {
+        &text[self.start.byte_offset..self.end.byte_offset]
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
 pub fn parse_whitespace<T>(to_parse: &mut ParseProcess<T>)
 where
     T: TPeekable<Item = char>,
@@ -20,6 +99,66 @@ where
     }
 }
 
+/// Skips whitespace together with `// line` and `/* block */` comments.
+///
+/// A `/` that does not start a comment is an error, since a lone slash has no meaning in
+/// grammar text.
+pub fn parse_whitespace_and_comments<T>(to_parse: &mut ParseProcess<T>) -> Result<(), ParserError>
+where
+    T: TPeekable<Item = char>,
+{
+    loop {
+        parse_whitespace(to_parse);
+        let pos = to_parse.cur_pos();
+        if to_parse.next_if_eq(&'/').is_none() {
+            return Ok(());
+        }
+        match to_parse.next() {
+            Some('/') => {
+                while to_parse.next().is_some_and(|chr| chr != '\n') {}
+            }
+            Some('*') => skip_block_comment(to_parse, pos)?,
+            Some(chr) => {
+                return Err(UnexpectedCharError {
+                    chr,
+                    pos: pos + 1,
+                    expected: String::from("/ or * to start a comment"),
+                    rules: vec![],
+                    repairs: vec![],
+                });
+            }
+            None => {
+                return Err(EndOfCharsError {
+                    pos: pos + 1,
+                    rules: vec![],
+                    repairs: vec![],
+                });
+            }
+        }
+    }
+}
+
+fn skip_block_comment<T>(to_parse: &mut ParseProcess<T>, start: usize) -> Result<(), ParserError>
+where
+    T: TPeekable<Item = char>,
+{
+    let mut after_star = false;
+    loop {
+        match to_parse.next() {
+            None => {
+                return Err(ParserError::GramError {
+                    err: UnexpectedElementError {
+                        reason: String::from("block comment is never closed"),
+                        pos: start,
+                    },
+                });
+            }
+            Some('/') if after_star => return Ok(()),
+            Some(chr) => after_star = chr == '*',
+        }
+    }
+}
+
 pub fn parse_digits<T>(to_parse: &mut ParseProcess<T>) -> Result<String, ParserError>
 where
     T: TPeekable<Item = char>,
@@ -31,7 +170,11 @@ where
             break;
         }
         let pos = to_parse.cur_pos();
-        number_string.push(to_parse.next().ok_or(EndOfCharsError { pos })?);
+        number_string.push(to_parse.next().ok_or(EndOfCharsError {
+            pos,
+            rules: vec![],
+            repairs: vec![],
+        })?);
         cur_char = to_parse.peek();
     }
     Ok(number_string)
@@ -42,12 +185,18 @@ where
     T: TPeekable<Item = char>,
 {
     let pos = to_parse.cur_pos();
-    let cur_char = to_parse.peek().ok_or(EndOfCharsError { pos })?;
+    let cur_char = to_parse.peek().ok_or(EndOfCharsError {
+        pos,
+        rules: vec![],
+        repairs: vec![],
+    })?;
     if !cur_char.is_ascii_digit() {
         return Err(UnexpectedCharError {
             chr: *cur_char,
             pos: to_parse.cur_pos(),
             expected: String::from("digit expected"),
+            rules: vec![],
+            repairs: vec![],
         });
     }
 
@@ -68,7 +217,11 @@ where
     T: TPeekable<Item = char>,
 {
     let pos = to_parse.cur_pos();
-    let cur_char = *to_parse.peek().ok_or(EndOfCharsError { pos })?;
+    let cur_char = *to_parse.peek().ok_or(EndOfCharsError {
+        pos,
+        rules: vec![],
+        repairs: vec![],
+    })?;
 
     let mut number_string = String::new();
     if cur_char == '-' {
@@ -79,6 +232,8 @@ where
             chr: cur_char,
             pos: to_parse.cur_pos(),
             expected: String::from("digit or - expected"),
+            rules: vec![],
+            repairs: vec![],
         });
     }
 
@@ -98,11 +253,15 @@ where
                 chr: *chr,
                 pos: to_parse.cur_pos(),
                 expected: String::from(sym),
+                rules: vec![],
+                repairs: vec![],
             })
         };
     }
     Err(EndOfCharsError {
         pos: to_parse.cur_pos(),
+        rules: vec![],
+        repairs: vec![],
     })
 }
 
@@ -111,12 +270,18 @@ where
     T: TPeekable<Item = char>,
 {
     let pos = to_parse.cur_pos();
-    let cur_char = to_parse.peek().ok_or(EndOfCharsError { pos })?;
+    let cur_char = to_parse.peek().ok_or(EndOfCharsError {
+        pos,
+        rules: vec![],
+        repairs: vec![],
+    })?;
     if !cur_char.is_alphabetic() {
         return Err(UnexpectedCharError {
             chr: *cur_char,
             pos: to_parse.cur_pos(),
             expected: String::from("alphabetic character"),
+            rules: vec![],
+            repairs: vec![],
         });
     }
     let mut id_name = cur_char.to_string();
@@ -128,16 +293,87 @@ where
         }
         id_name.push(to_parse.next().ok_or(EndOfCharsError {
             pos: to_parse.cur_pos(),
+            rules: vec![],
+            repairs: vec![],
         })?);
         cur_char = to_parse.peek();
     }
     Ok(id_name)
 }
 
+/// Decodes an escape sequence. The leading `\` must already be consumed.
+///
+/// Understands `\n`, `\t`, `\r`, `\\`, `\"` and `\u{XXXX}`. Chars in `literal` additionally stand
+/// for themselves when escaped. Anything else is a grammar error reported at `backslash_pos`.
+pub fn parse_escape<T>(
+    to_parse: &mut ParseProcess<T>,
+    backslash_pos: usize,
+    literal: &[char],
+) -> Result<char, ParserError>
+where
+    T: TPeekable<Item = char>,
+{
+    let pos = to_parse.cur_pos();
+    let escaped = to_parse.next().ok_or(EndOfCharsError {
+        pos,
+        rules: vec![],
+        repairs: vec![],
+    })?;
+    match escaped {
+        'n' => Ok('\n'),
+        't' => Ok('\t'),
+        'r' => Ok('\r'),
+        '\\' | '"' => Ok(escaped),
+        'u' => parse_unicode_escape(to_parse, backslash_pos),
+        chr if literal.contains(&chr) => Ok(chr),
+        chr => Err(ParserError::GramError {
+            err: UnexpectedElementError {
+                reason: format!("unknown escape sequence \\{chr}"),
+                pos: backslash_pos,
+            },
+        }),
+    }
+}
+
+fn parse_unicode_escape<T>(
+    to_parse: &mut ParseProcess<T>,
+    backslash_pos: usize,
+) -> Result<char, ParserError>
+where
+    T: TPeekable<Item = char>,
+{
+    let invalid = || ParserError::GramError {
+        err: UnexpectedElementError {
+            reason: String::from("\\u must be followed by {XXXX} with 1 to 6 hex digits of a valid char"),
+            pos: backslash_pos,
+        },
+    };
+    parse_symbol(to_parse, '{').map_err(|_| invalid())?;
+    let mut hex = String::new();
+    while let Some(chr) = to_parse.peek() {
+        if !chr.is_ascii_hexdigit() {
+            break;
+        }
+        hex.push(*chr);
+        to_parse.next();
+    }
+    parse_symbol(to_parse, '}').map_err(|_| invalid())?;
+    if hex.is_empty() || hex.len() > 6 {
+        return Err(invalid());
+    }
+    u32::from_str_radix(&hex, 16)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or_else(invalid)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::errors::ParserError;
-    use crate::parse_funcs::{parse_isize, parse_usize};
+    use crate::errors::GrammarError;
+    use crate::parse_funcs::{
+        SourcePosition, parse_escape, parse_isize, parse_usize, parse_whitespace_and_comments,
+    };
     use crate::peekables::{ParseProcess, PeekableWrapper, TPeekable};
 
     #[test]
@@ -179,7 +415,9 @@ mod tests {
         let mut parse_process = ParseProcess::new(&mut peekable, None, None);
         let error = parse_isize(&mut parse_process).unwrap_err();
         match error {
-            ParserError::UnexpectedCharError { chr, pos, expected } => {
+            ParserError::UnexpectedCharError {
+                chr, pos, expected, ..
+            } => {
                 assert_eq!(chr, '+');
                 assert_eq!(pos, 0);
                 assert_eq!(expected, "digit or - expected");
@@ -194,7 +432,7 @@ mod tests {
         let mut parse_process = ParseProcess::new(&mut peekable, None, None);
         let error = parse_isize(&mut parse_process).unwrap_err();
         match error {
-            ParserError::EndOfCharsError { pos } => assert_eq!(pos, 1),
+            ParserError::EndOfCharsError { pos, .. } => assert_eq!(pos, 1),
             _ => panic!("unexpected error"),
         }
     }
@@ -230,7 +468,9 @@ mod tests {
         let mut parse_process = ParseProcess::new(&mut peekable, None, None);
         let error = parse_usize(&mut parse_process).unwrap_err();
         match error {
-            ParserError::UnexpectedCharError { chr, pos, expected } => {
+            ParserError::UnexpectedCharError {
+                chr, pos, expected, ..
+            } => {
                 assert_eq!(chr, '-');
                 assert_eq!(pos, 0);
                 assert_eq!(expected, "digit expected");
@@ -245,8 +485,67 @@ mod tests {
         let mut parse_process = ParseProcess::new(&mut peekable, None, None);
         let error = parse_usize(&mut parse_process).unwrap_err();
         match error {
-            ParserError::EndOfCharsError { pos } => assert_eq!(pos, 0),
+            ParserError::EndOfCharsError { pos, .. } => assert_eq!(pos, 0),
             _ => panic!("unexpected error"),
         }
     }
+
+    #[test]
+    fn test_parse_escape() {
+        let mut peekable = PeekableWrapper::from_str("n\\u{1F600}u{e9}");
+        let mut parse_process = ParseProcess::new(&mut peekable, None, None);
+        assert_eq!('\n', parse_escape(&mut parse_process, 0, &[]).unwrap());
+        parse_process.next();
+        assert_eq!('\u{1F600}', parse_escape(&mut parse_process, 2, &[]).unwrap());
+        assert_eq!('é', parse_escape(&mut parse_process, 10, &[]).unwrap());
+    }
+
+    #[test]
+    fn test_parse_escape_rejects_unknown() {
+        let mut peekable = PeekableWrapper::from_str("q");
+        let mut parse_process = ParseProcess::new(&mut peekable, None, None);
+        match parse_escape(&mut parse_process, 7, &[]).unwrap_err() {
+            ParserError::GramError {
+                err: GrammarError::UnexpectedElementError { pos, .. },
+            } => assert_eq!(pos, 7),
+            _ => panic!("unexpected error"),
+        }
+    }
+
+    #[test]
+    fn test_parse_escape_rejects_surrogate() {
+        let mut peekable = PeekableWrapper::from_str("u{D800}");
+        let mut parse_process = ParseProcess::new(&mut peekable, None, None);
+        assert!(parse_escape(&mut parse_process, 0, &[]).is_err());
+    }
+
+    #[test]
+    fn test_parse_whitespace_and_comments() {
+        let mut peekable = PeekableWrapper::from_str(" // line\n /* block * / **/\t/**/x");
+        let mut parse_process = ParseProcess::new(&mut peekable, None, None);
+        parse_whitespace_and_comments(&mut parse_process).unwrap();
+        assert_eq!(parse_process.peek(), Some(&'x'));
+    }
+
+    #[test]
+    fn test_parse_whitespace_and_comments_unclosed_block() {
+        let mut peekable = PeekableWrapper::from_str("  /* never closed *");
+        let mut parse_process = ParseProcess::new(&mut peekable, None, None);
+        match parse_whitespace_and_comments(&mut parse_process).unwrap_err() {
+            ParserError::GramError {
+                err: GrammarError::UnexpectedElementError { pos, .. },
+            } => assert_eq!(pos, 2),
+            _ => panic!("unexpected error"),
+        }
+    }
+
+    #[test]
+    fn test_source_position() {
+        let text = "ab\ncd\n\näf";
+        assert_eq!(SourcePosition::default(), SourcePosition::of(text, 0));
+        assert_eq!("2:2", SourcePosition::of(text, 4).to_string());
+        let position = SourcePosition::of(text, 8);
+        assert_eq!((4, 2), (position.line, position.column));
+        assert_eq!((8, 9), (position.char_offset, position.byte_offset));
+    }
 }
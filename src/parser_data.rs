@@ -4,9 +4,11 @@
 //! programmatically or as the result of parsing a grammar description. It is the
 //! core in-memory representation used by the Parser.
 
+use crate::char_class::CharClass;
+use crate::errors::ParserError;
 use crate::peekables::{ParseProcess, PeekableWrapper};
 use crate::vms::VM;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::rc::Rc;
 use std::str::Chars;
@@ -37,6 +39,10 @@ where
             parse_rules: ParseRules {
                 rules: Default::default(),
                 ignore: None,
+                entry_points: vec![],
+                eliminate_left_recursion: false,
+                left_factor: false,
+                lookahead: 1,
             },
             element_types: vec![],
             element_verbose_map: Default::default(),
@@ -71,6 +77,47 @@ where
         elements_verbose
     }
 
+    /// The element as written in a grammar: terminals quoted, rules and char classes by name.
+    pub fn element_notation(&self, ix: ElementIndex) -> String {
+        let name = self
+            .get_element_data(ix)
+            .map_or_else(|| ix.to_string(), |data| data.name.clone());
+        match self.element_types.get(ix) {
+            Some(ElementType::Terminal) => format!("{name:?}"),
+            _ => name,
+        }
+    }
+
+    /// A production of `rule` as written in a grammar, without the woven in ignore rule.
+    pub fn production_notation(&self, rule: ElementIndex, production: &Production) -> String {
+        match production {
+            Production::Empty => String::from("#"),
+            Production::NotEmpty(_) => self
+                .written_elements(rule, production)
+                .into_iter()
+                .map(|ix| self.element_notation(ix))
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+
+    /// The elements of a production of `rule` without the woven in ignore rule.
+    pub fn written_elements(
+        &self,
+        rule: ElementIndex,
+        production: &Production,
+    ) -> Vec<ElementIndex> {
+        let ignore = self.get_rule_by_key(rule).and_then(|rule| rule.ignore);
+        match production {
+            Production::Empty => vec![],
+            Production::NotEmpty(el_ixs) => el_ixs
+                .iter()
+                .copied()
+                .filter(|&ix| Some(ix) != ignore)
+                .collect(),
+        }
+    }
+
     pub fn get_rule_by_key(&self, index: ElementIndex) -> Option<&NonTerminalRules<T>> {
         self.parse_rules.rules.get(&index)
     }
@@ -95,6 +142,8 @@ where
                 self.element_data.push(ElementData {
                     keep_data: false,
                     name: identifier.name.clone(),
+                    defined_at: None,
+                    first_reference: None,
                 });
                 self.element_types.push(identifier.et);
                 self.element_verbose_map.insert(identifier.clone(), index);
@@ -146,6 +195,123 @@ where
         let identifier = ElementVerbose::new_t(String::from(name));
         self.get_or_add_element_key(&identifier)
     }
+    /// Adds a character class element given by its source text, e.g. `[0-9]` or `.`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ParserError`] if `source` is not a well-formed class.
+    pub fn get_or_add_char_class(&mut self, source: &str) -> Result<ElementIndex, ParserError> {
+        CharClass::from_source(source)?;
+        let identifier = ElementVerbose::new_class(String::from(source));
+        Ok(self.get_or_add_element_key(&identifier))
+    }
+
+    /// The parsed class of a character class element, `None` for every other element.
+    #[must_use]
+    pub fn get_char_class(&self, ix: ElementIndex) -> Option<CharClass> {
+        if self.element_types.get(ix) != Some(&ElementType::CharClass) {
+            return None;
+        }
+        CharClass::from_source(&self.element_data.get(ix)?.name).ok()
+    }
+
+    /// Adds the helper rules behind `element*`, `element+` or `element?` and returns the helper.
+    ///
+    /// The helper gets a tree node of its own. For `*` and `+` the repetition tail is flattened
+    /// into that node, so `digit+` ends up as one node with a child per digit. `name` should come
+    /// from [`generated_name`]; a helper that already has rules is reused as it is.
+    pub fn add_repetition(
+        &mut self,
+        name: &str,
+        element: ElementIndex,
+        repetition: Repetition,
+        ignore: Option<ElementIndex>,
+    ) -> ElementIndex {
+        let helper = self.get_or_add_non_terminal(name);
+        if self.parse_rules.rules.contains_key(&helper) {
+            return helper;
+        }
+        let productions = match repetition {
+            Repetition::Optional => vec![
+                Rc::new(Production::NotEmpty(vec![element])),
+                Rc::new(Production::Empty),
+            ],
+            Repetition::ZeroOrMore | Repetition::OneOrMore => {
+                let tail = self.get_or_add_non_terminal(&format!("{name}'"));
+                let mut tail_rule = NonTerminalRules::new(
+                    vec![
+                        Rc::new(Production::NotEmpty(vec![element, tail])),
+                        Rc::new(Production::Empty),
+                    ],
+                    ignore,
+                    vec![],
+                );
+                tail_rule.flatten = true;
+                self.parse_rules.rules.insert(tail, tail_rule);
+                if repetition == Repetition::ZeroOrMore {
+                    vec![Rc::new(Production::NotEmpty(vec![tail]))]
+                } else {
+                    vec![Rc::new(Production::NotEmpty(vec![element, tail]))]
+                }
+            }
+        };
+        self.parse_rules
+            .rules
+            .insert(helper, NonTerminalRules::new(productions, ignore, vec![]));
+        helper
+    }
+
+    /// The rules parsing can start from: the ones declared with `$START`, or the rule named
+    /// `start` if none are declared. The first one is the default.
+    pub fn entry_points(&self) -> Vec<ElementIndex> {
+        if !self.parse_rules.entry_points.is_empty() {
+            return self.parse_rules.entry_points.clone();
+        }
+        self.get_element_nt_index("start").into_iter().collect()
+    }
+
+    /// The rules `entry_points` lead to, including themselves and the ignore rules on the way.
+    #[must_use]
+    pub fn rules_reached_from(&self, entry_points: &[ElementIndex]) -> HashSet<ElementIndex> {
+        let mut reached: HashSet<ElementIndex> = entry_points.iter().copied().collect();
+        let mut stack = entry_points.to_vec();
+        while let Some(rule) = stack.pop() {
+            let Some(nt_rules) = self.get_rule_by_key(rule) else {
+                continue;
+            };
+            let used = nt_rules
+                .possible_productions
+                .iter()
+                .flat_map(|production| match &**production {
+                    Production::Empty => &[][..],
+                    Production::NotEmpty(el_ixs) => el_ixs.as_slice(),
+                })
+                .copied()
+                .chain(nt_rules.ignore)
+                .filter(|&ix| self.element_types[ix] == ElementType::NonTerminal);
+            for next in used {
+                if reached.insert(next) {
+                    stack.push(next);
+                }
+            }
+        }
+        reached
+    }
+
+    /// Remembers where a rule is defined in grammar text. Only the first definition is kept.
+    pub fn note_definition(&mut self, ix: ElementIndex, pos: usize) {
+        if let Some(data) = self.element_data.get_mut(ix) {
+            data.defined_at.get_or_insert(pos);
+        }
+    }
+
+    /// Remembers where an element is used in grammar text. Only the first use is kept.
+    pub fn note_reference(&mut self, ix: ElementIndex, pos: usize, rule: Option<ElementIndex>) {
+        if let Some(data) = self.element_data.get_mut(ix) {
+            data.first_reference.get_or_insert(Reference { pos, rule });
+        }
+    }
+
     pub fn get_element_index(&self, key: &ElementVerbose) -> Option<ElementIndex> {
         match self.element_verbose_map.get(key) {
             None => None,
@@ -169,12 +335,64 @@ where
     }
 }
 
+/// Separates the enclosing rule from the rest of a generated non-terminal's name.
+///
+/// Rule names in grammar text can't contain it, so generated names never collide with them.
+pub const GENERATED_NAME_SEPARATOR: char = '/';
+
+/// Name for a non-terminal generated for `suffix` inside `rule`, e.g. `number/digit+`.
+pub fn generated_name(rule: &str, suffix: &str) -> String {
+    format!("{rule}{GENERATED_NAME_SEPARATOR}{suffix}")
+}
+
+pub fn is_generated_name(name: &str) -> bool {
+    name.contains(GENERATED_NAME_SEPARATOR)
+}
+
+/// The postfix operators `*`, `+` and `?`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum Repetition {
+    ZeroOrMore,
+    OneOrMore,
+    Optional,
+}
+
+impl Repetition {
+    pub fn from_symbol(chr: char) -> Option<Repetition> {
+        match chr {
+            '*' => Some(Repetition::ZeroOrMore),
+            '+' => Some(Repetition::OneOrMore),
+            '?' => Some(Repetition::Optional),
+            _ => None,
+        }
+    }
+
+    pub fn symbol(self) -> char {
+        match self {
+            Repetition::ZeroOrMore => '*',
+            Repetition::OneOrMore => '+',
+            Repetition::Optional => '?',
+        }
+    }
+}
+
 pub struct ParseRules<T>
 where
     T: VM,
 {
     pub rules: RuleMap<T>,
     pub ignore: Option<ElementIndex>,
+    /// Rules declared with `$START`, in declaration order.
+    pub entry_points: Vec<ElementIndex>,
+    /// Rewrite left recursion before building a parser, see
+    /// [`eliminate_left_recursion`](crate::left_recursion::eliminate_left_recursion).
+    pub eliminate_left_recursion: bool,
+    /// Factor out common prefixes before building a parser, see
+    /// [`left_factor`](crate::left_factoring::left_factor).
+    pub left_factor: bool,
+    /// How many chars the parser looks ahead, set with `$LOOKAHEAD`. Above 1 the parser is built
+    /// by [`crate::lookahead`].
+    pub lookahead: usize,
 }
 impl<T> fmt::Debug for ParseRules<T>
 where
@@ -184,6 +402,10 @@ where
         f.debug_struct("ParseRules")
             .field("rules", &self.rules)
             .field("ignore", &self.ignore)
+            .field("entry_points", &self.entry_points)
+            .field("eliminate_left_recursion", &self.eliminate_left_recursion)
+            .field("left_factor", &self.left_factor)
+            .field("lookahead", &self.lookahead)
             .finish()
     }
 }
@@ -199,6 +421,11 @@ where
     pub possible_productions: PossibleProductions,
     pub ignore: Option<ElementIndex>,
     pub instruction: Vec<<T as VM>::Tinstrution>,
+    /// The rule adds no tree node of its own, its children are attached to the parent's node.
+    pub flatten: bool,
+    /// Its node runs the instructions of this rule instead of its own, for the parts of an
+    /// inlined rule, see [`eliminate_left_recursion`](crate::left_recursion::eliminate_left_recursion).
+    pub instructions_of: Option<ElementIndex>,
 }
 
 impl<T> NonTerminalRules<T>
@@ -214,6 +441,8 @@ where
             possible_productions,
             ignore,
             instruction,
+            flatten: false,
+            instructions_of: None,
         }
     }
 }
@@ -225,6 +454,8 @@ where
         f.debug_struct("NonTerminalRules")
             .field("possible_productions", &self.possible_productions)
             .field("ignore", &self.ignore)
+            .field("flatten", &self.flatten)
+            .field("instructions_of", &self.instructions_of)
             .finish()
     }
 }
@@ -249,12 +480,17 @@ impl ElementVerbose {
     pub fn new_nt(name: String) -> ElementVerbose {
         ElementVerbose::new(name, ElementType::NonTerminal)
     }
+    pub fn new_class(name: String) -> ElementVerbose {
+        ElementVerbose::new(name, ElementType::CharClass)
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
 pub enum ElementType {
     Terminal,
     NonTerminal,
+    /// Matches a single char out of a class like `[0-9]`. The element name is the class source.
+    CharClass,
 }
 #[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
 pub struct Element {
@@ -277,6 +513,20 @@ impl Element {
 pub struct ElementData {
     pub keep_data: bool,
     pub name: String,
+    /// Char offset in the grammar text where the rule is first defined. For generated rules
+    /// this is the group or operator they come from.
+    pub defined_at: Option<usize>,
+    /// Where the element is first used in the grammar text.
+    pub first_reference: Option<Reference>,
+}
+
+/// A use of an element in grammar text.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Reference {
+    /// Char offset in the grammar text.
+    pub pos: usize,
+    /// The rule the element is used in, `None` for directives like `$IGNORE`.
+    pub rule: Option<ElementIndex>,
 }
 #[derive(Debug)]
 pub enum Production {
@@ -4,12 +4,18 @@
 //! conditional consumption while tracking positions, which is helpful for
 //! error reporting during parsing.
 
+use std::collections::VecDeque;
 use std::iter::Peekable;
 use std::str::Chars;
 
+use crate::parse_funcs::SourcePosition;
+
 pub trait TPeekable: Iterator {
     fn peek(&mut self) -> Option<&Self::Item>;
 
+    /// Looks `n` items ahead without consuming anything. `peek_nth(0)` is `peek()`.
+    fn peek_nth(&mut self, n: usize) -> Option<&Self::Item>;
+
     fn peek_mut(&mut self) -> Option<&mut Self::Item>;
 
     fn next_if(&mut self, func: impl FnOnce(&Self::Item) -> bool) -> Option<Self::Item>;
@@ -23,6 +29,8 @@ where
     <T as Iterator>::Item: PartialEq,
 {
     peekable: Peekable<T>,
+    /// Items taken out of `peekable` by `peek_nth`. They come before the rest of `peekable`.
+    lookahead: VecDeque<T::Item>,
 }
 
 impl<T> Iterator for PeekableWrapper<T>
@@ -33,7 +41,7 @@ where
     type Item = T::Item;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.peekable.next()
+        self.lookahead.pop_front().or_else(|| self.peekable.next())
     }
 }
 
@@ -43,19 +51,41 @@ where
     <T as Iterator>::Item: PartialEq,
 {
     fn peek(&mut self) -> Option<&Self::Item> {
-        self.peekable.peek()
+        match self.lookahead.front() {
+            Some(item) => Some(item),
+            None => self.peekable.peek(),
+        }
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Option<&Self::Item> {
+        while self.lookahead.len() <= n {
+            self.lookahead.push_back(self.peekable.next()?);
+        }
+        self.lookahead.get(n)
     }
 
     fn peek_mut(&mut self) -> Option<&mut Self::Item> {
-        self.peekable.peek_mut()
+        match self.lookahead.front_mut() {
+            Some(item) => Some(item),
+            None => self.peekable.peek_mut(),
+        }
     }
 
     fn next_if(&mut self, func: impl FnOnce(&Self::Item) -> bool) -> Option<Self::Item> {
-        self.peekable.next_if(func)
+        match self.lookahead.front() {
+            Some(item) => {
+                if func(item) {
+                    self.lookahead.pop_front()
+                } else {
+                    None
+                }
+            }
+            None => self.peekable.next_if(func),
+        }
     }
 
     fn next_if_eq(&mut self, expected: &Self::Item) -> Option<Self::Item> {
-        self.peekable.next_if_eq(expected)
+        self.next_if(|item| item == expected)
     }
 }
 
@@ -66,7 +96,10 @@ where
 {
     pub fn new(peekable: Peekable<T>) -> PeekableWrapper<T>
     {
-        PeekableWrapper { peekable }
+        PeekableWrapper {
+            peekable,
+            lookahead: VecDeque::new(),
+        }
     }
 }
 
@@ -81,7 +114,7 @@ where
     T: TPeekable<Item = char>,
 {
     to_parse: &'a mut T,
-    current_position: usize,
+    current_position: SourcePosition,
     stop_on: Option<char>,
     escape_char: Option<char>,
     escape: bool,
@@ -98,7 +131,7 @@ where
     ) -> ParseProcess<'_, T> {
         ParseProcess {
             to_parse: peekable,
-            current_position: 0,
+            current_position: SourcePosition::default(),
             stop_on,
             escape_char,
             escape: false,
@@ -106,11 +139,13 @@ where
     }
 
 
+    /// A parse process over a part of the text `peekable` reads, that starts at
+    /// `global_position` of that text.
     pub fn new_nested(
         peekable: &mut T,
         stop_on: Option<char>,
         escape_char: Option<char>,
-        global_position: usize,
+        global_position: SourcePosition,
     ) -> ParseProcess<'_, T> {
         ParseProcess {
             to_parse: peekable,
@@ -120,9 +155,23 @@ where
             escape: false,
         }
     }
+    /// The char offset of the next char.
     pub fn cur_pos(&self) -> usize {
+        self.current_position.char_offset
+    }
+
+    /// Char and byte offset, line and column of the next char.
+    #[must_use]
+    pub fn source_position(&self) -> SourcePosition {
         self.current_position
     }
+
+    fn advance(&mut self, consumed: Option<char>) -> Option<char> {
+        if let Some(chr) = consumed {
+            self.current_position = self.current_position.after(chr);
+        }
+        consumed
+    }
 }
 
 impl<'a, T> Iterator for ParseProcess<'a, T>
@@ -135,10 +184,7 @@ where
             return None;
         }
         let res = self.to_parse.next();
-        if res.is_some() {
-            self.current_position += 1
-        }
-        res
+        self.advance(res)
     }
 }
 
@@ -153,6 +199,27 @@ where
         self.to_parse.peek()
     }
 
+    /// Like `peek`, does not look past an unescaped stop char.
+    fn peek_nth(&mut self, n: usize) -> Option<&char> {
+        if n == 0 {
+            return self.peek();
+        }
+        if let (Some(stop_char), Some(escape_char)) = (self.stop_on, self.escape_char) {
+            let mut escape = self.escape;
+            for i in 0..=n {
+                let c = *self.to_parse.peek_nth(i)?;
+                if c == escape_char {
+                    escape = true;
+                } else if c == stop_char && !escape {
+                    return None;
+                } else {
+                    escape = false;
+                }
+            }
+        }
+        self.to_parse.peek_nth(n)
+    }
+
     fn peek_mut(&mut self) -> Option<&mut char> {
         self.to_parse.peek_mut()
     }
@@ -161,10 +228,7 @@ where
             return None;
         }
         let res = self.to_parse.next_if(func);
-        if res.is_some() {
-            self.current_position += 1
-        }
-        res
+        self.advance(res)
     }
 
     fn next_if_eq(&mut self, expected: &char) -> Option<char> {
@@ -172,10 +236,7 @@ where
             return None;
         }
         let res = self.to_parse.next_if_eq(expected);
-        if res.is_some() {
-            self.current_position += 1
-        }
-        res
+        self.advance(res)
     }
 }
 
@@ -0,0 +1,601 @@
+//! Railroad (syntax) diagrams of grammar rules as SVG.
+//!
+//! Every rule is turned into a [`Diagram`]: its alternatives as a choice, each alternative as a
+//! sequence of terminal, char class and non-terminal boxes. The helper rules behind groups and
+//! `*`, `+` and `?` are drawn inline as the choices and loops they were written as, so a diagram
+//! looks like the rule in the grammar text. The woven in ignore rule is left out.
+//!
+//! [`RailroadDiagrams`] renders one self-contained SVG per rule, with non-terminal boxes linking
+//! to the SVG of their rule, and an HTML page with all of them.
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::errors::GrammarDiagnostics;
+use crate::parser_data::{
+    ElementIndex, ElementType, GENERATED_NAME_SEPARATOR, ParserData, Production, Repetition,
+    is_generated_name,
+};
+use crate::script_parser::parsed_parser_data;
+use crate::vms::VM;
+
+const CHAR_WIDTH: usize = 8;
+const BOX_HEIGHT: usize = 22;
+const BOX_PADDING: usize = 10;
+const GAP: usize = 10;
+const RADIUS: usize = 10;
+const MARGIN: usize = 20;
+const TITLE_HEIGHT: usize = 24;
+
+const STYLE: &str = "<style>\
+    path { fill: none; stroke: #333; stroke-width: 2; }\
+    rect { fill: #eef; stroke: #333; stroke-width: 2; }\
+    rect.terminal { fill: #efe; }\
+    rect.char-class { fill: #ffe; }\
+    text { font-family: monospace; font-size: 13px; text-anchor: middle; }\
+    text.title { font-size: 15px; font-weight: bold; text-anchor: start; }\
+    a text { fill: #00c; text-decoration: underline; }\
+    </style>";
+
+/// A railroad diagram of (a part of) a rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagram {
+    /// A terminal as written in the grammar, quoted.
+    Terminal(String),
+    /// A char class like `[0-9]`.
+    CharClass(String),
+    /// A box linking to the diagram of another rule.
+    NonTerminal(String),
+    /// The empty string, a plain line.
+    Epsilon,
+    Sequence(Vec<Diagram>),
+    /// Alternatives stacked below each other, the first one on the main line.
+    Choice(Vec<Diagram>),
+    /// The inner diagram with a loop back around it.
+    OneOrMore(Box<Diagram>),
+}
+
+/// The space a diagram takes around the line it is entered and left on.
+struct Extent {
+    width: usize,
+    up: usize,
+    down: usize,
+}
+
+impl Diagram {
+    /// The diagram of `rule`, or `None` if it has no rule.
+    #[must_use]
+    pub fn of_rule<T>(parser_data: &ParserData<T>, rule: ElementIndex) -> Option<Diagram>
+    where
+        T: VM,
+    {
+        parser_data.get_rule_by_key(rule)?;
+        Some(rule_body(parser_data, rule, &mut HashSet::new()))
+    }
+
+    fn extent(&self) -> Extent {
+        match self {
+            Diagram::Terminal(text) | Diagram::CharClass(text) | Diagram::NonTerminal(text) => {
+                Extent {
+                    width: box_width(text),
+                    up: BOX_HEIGHT / 2,
+                    down: BOX_HEIGHT / 2,
+                }
+            }
+            Diagram::Epsilon => Extent {
+                width: 0,
+                up: 0,
+                down: 0,
+            },
+            Diagram::Sequence(items) => {
+                let extents: Vec<Extent> = items.iter().map(Diagram::extent).collect();
+                Extent {
+                    width: extents.iter().map(|extent| extent.width).sum::<usize>()
+                        + GAP * items.len().saturating_sub(1),
+                    up: extents.iter().map(|extent| extent.up).max().unwrap_or(0),
+                    down: extents.iter().map(|extent| extent.down).max().unwrap_or(0),
+                }
+            }
+            Diagram::Choice(alternatives) => {
+                let extents: Vec<Extent> = alternatives.iter().map(Diagram::extent).collect();
+                let offsets = choice_offsets(&extents);
+                let last = extents.len() - 1;
+                Extent {
+                    width: extents.iter().map(|extent| extent.width).max().unwrap_or(0)
+                        + 4 * RADIUS,
+                    up: extents[0].up,
+                    down: offsets[last] + extents[last].down,
+                }
+            }
+            Diagram::OneOrMore(inner) => {
+                let extent = inner.extent();
+                Extent {
+                    width: extent.width + 4 * RADIUS,
+                    up: extent.up,
+                    down: loop_offset(&extent),
+                }
+            }
+        }
+    }
+
+    /// Draws the diagram entered at `(x, y)` into `svg`. `link` turns a rule name into the target
+    /// of its box.
+    fn draw(&self, svg: &mut String, x: usize, y: usize, link: &dyn Fn(&str) -> String) {
+        match self {
+            Diagram::Terminal(text) => draw_box(svg, x, y, text, Some("terminal"), None),
+            Diagram::CharClass(text) => draw_box(svg, x, y, text, Some("char-class"), None),
+            Diagram::NonTerminal(name) => draw_box(svg, x, y, name, None, Some(&link(name))),
+            Diagram::Epsilon => {}
+            Diagram::Sequence(items) => {
+                let mut x = x;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        let _ = writeln!(svg, "<path d=\"M{x} {y}h{GAP}\"/>");
+                        x += GAP;
+                    }
+                    item.draw(svg, x, y, link);
+                    x += item.extent().width;
+                }
+            }
+            Diagram::Choice(alternatives) => {
+                let extents: Vec<Extent> = alternatives.iter().map(Diagram::extent).collect();
+                let offsets = choice_offsets(&extents);
+                let width = self.extent().width;
+                let r = RADIUS;
+                for ((alternative, extent), offset) in
+                    alternatives.iter().zip(&extents).zip(offsets)
+                {
+                    let start = x + 2 * r;
+                    let end = x + width - 2 * r;
+                    let line_y = y + offset;
+                    if offset == 0 {
+                        let _ = writeln!(svg, "<path d=\"M{x} {y}h{}\"/>", 2 * r);
+                    } else {
+                        let _ = writeln!(
+                            svg,
+                            "<path d=\"M{x} {y}a{r} {r} 0 0 1 {r} {r}v{}a{r} {r} 0 0 0 {r} {r}\"/>",
+                            offset - 2 * r
+                        );
+                    }
+                    alternative.draw(svg, start, line_y, link);
+                    if start + extent.width < end {
+                        let _ = writeln!(
+                            svg,
+                            "<path d=\"M{} {line_y}H{end}\"/>",
+                            start + extent.width
+                        );
+                    }
+                    if offset == 0 {
+                        let _ = writeln!(svg, "<path d=\"M{end} {y}h{}\"/>", 2 * r);
+                    } else {
+                        let _ = writeln!(
+                            svg,
+                            "<path d=\"M{end} {line_y}a{r} {r} 0 0 0 {r} -{r}v-{}a{r} {r} 0 0 1 {r} -{r}\"/>",
+                            offset - 2 * r
+                        );
+                    }
+                }
+            }
+            Diagram::OneOrMore(inner) => {
+                let extent = inner.extent();
+                let r = RADIUS;
+                let offset = loop_offset(&extent);
+                let end = x + 2 * r + extent.width;
+                let _ = writeln!(svg, "<path d=\"M{x} {y}h{}\"/>", 2 * r);
+                inner.draw(svg, x + 2 * r, y, link);
+                let _ = writeln!(svg, "<path d=\"M{end} {y}h{}\"/>", 2 * r);
+                let _ = writeln!(
+                    svg,
+                    "<path d=\"M{end} {y}a{r} {r} 0 0 1 {r} {r}v{}a{r} {r} 0 0 1 -{r} {r}H{}a{r} {r} 0 0 1 -{r} -{r}v-{}a{r} {r} 0 0 1 {r} -{r}\"/>",
+                    offset - 2 * r,
+                    x + 2 * r,
+                    offset - 2 * r
+                );
+            }
+        }
+    }
+}
+
+/// Where the lines of the alternatives of a choice are, relative to its main line.
+fn choice_offsets(extents: &[Extent]) -> Vec<usize> {
+    let mut offsets = vec![0];
+    for pair in extents.windows(2) {
+        let previous = offsets[offsets.len() - 1];
+        let offset = (previous + pair[0].down + GAP + pair[1].up).max(previous + 2 * RADIUS);
+        offsets.push(offset);
+    }
+    offsets
+}
+
+/// How far below the main line the loop of a [`Diagram::OneOrMore`] runs.
+fn loop_offset(inner: &Extent) -> usize {
+    (inner.down + GAP).max(2 * RADIUS)
+}
+
+fn box_width(text: &str) -> usize {
+    text.chars().count() * CHAR_WIDTH + 2 * BOX_PADDING
+}
+
+fn draw_box(
+    svg: &mut String,
+    x: usize,
+    y: usize,
+    text: &str,
+    class: Option<&str>,
+    href: Option<&str>,
+) {
+    let width = box_width(text);
+    let top = y - BOX_HEIGHT / 2;
+    let class = class.map_or_else(String::new, |class| format!(" class=\"{class}\""));
+    let rounded = if class.is_empty() { "" } else { " rx=\"10\"" };
+    let _ = writeln!(
+        svg,
+        "<rect{class} x=\"{x}\" y=\"{top}\" width=\"{width}\" height=\"{BOX_HEIGHT}\"{rounded}/>"
+    );
+    let text = format!(
+        "<text x=\"{}\" y=\"{}\">{}</text>",
+        x + width / 2,
+        y + 4,
+        xml_escape(text)
+    );
+    match href {
+        Some(href) => {
+            let _ = writeln!(svg, "<a href=\"{}\">{text}</a>", xml_escape(href));
+        }
+        None => {
+            let _ = writeln!(svg, "{text}");
+        }
+    }
+}
+
+/// The alternatives of `rule` as a diagram. `inlined` holds the helper rules currently drawn
+/// inline, a helper referring to itself is drawn as a box instead.
+fn rule_body<T>(
+    parser_data: &ParserData<T>,
+    rule: ElementIndex,
+    inlined: &mut HashSet<ElementIndex>,
+) -> Diagram
+where
+    T: VM,
+{
+    let Some(nt_rules) = parser_data.get_rule_by_key(rule) else {
+        return Diagram::NonTerminal(rule_name(parser_data, rule));
+    };
+    let mut alternatives: Vec<Diagram> = nt_rules
+        .possible_productions
+        .iter()
+        .map(|production| production_diagram(parser_data, rule, production, inlined))
+        .collect();
+    if alternatives.len() == 1 {
+        alternatives.remove(0)
+    } else {
+        Diagram::Choice(alternatives)
+    }
+}
+
+fn production_diagram<T>(
+    parser_data: &ParserData<T>,
+    rule: ElementIndex,
+    production: &Production,
+    inlined: &mut HashSet<ElementIndex>,
+) -> Diagram
+where
+    T: VM,
+{
+    let mut items: Vec<Diagram> = parser_data
+        .written_elements(rule, production)
+        .into_iter()
+        .map(|ix| element_diagram(parser_data, ix, inlined))
+        .collect();
+    match items.len() {
+        0 => Diagram::Epsilon,
+        1 => items.remove(0),
+        _ => Diagram::Sequence(items),
+    }
+}
+
+fn element_diagram<T>(
+    parser_data: &ParserData<T>,
+    ix: ElementIndex,
+    inlined: &mut HashSet<ElementIndex>,
+) -> Diagram
+where
+    T: VM,
+{
+    match parser_data.element_types.get(ix) {
+        Some(ElementType::Terminal) => Diagram::Terminal(parser_data.element_notation(ix)),
+        Some(ElementType::CharClass) => Diagram::CharClass(parser_data.element_notation(ix)),
+        _ => {
+            let name = rule_name(parser_data, ix);
+            if !is_inlined_helper(&name) || !inlined.insert(ix) {
+                return Diagram::NonTerminal(name);
+            }
+            let diagram = repetition_diagram(parser_data, ix, &name, inlined)
+                .unwrap_or_else(|| rule_body(parser_data, ix, inlined));
+            inlined.remove(&ix);
+            diagram
+        }
+    }
+}
+
+/// The loop a `*` or `+` helper rule was generated from, if it still has the shape
+/// [`ParserData::add_repetition`] gave it. `?` helpers are plain choices and need no special
+/// case.
+fn repetition_diagram<T>(
+    parser_data: &ParserData<T>,
+    helper: ElementIndex,
+    name: &str,
+    inlined: &mut HashSet<ElementIndex>,
+) -> Option<Diagram>
+where
+    T: VM,
+{
+    let repetition = Repetition::from_symbol(name.chars().last()?)?;
+    let tail = parser_data.get_element_nt_index(&format!("{name}'"))?;
+    let [production] = parser_data
+        .get_rule_by_key(helper)?
+        .possible_productions
+        .as_slice()
+    else {
+        return None;
+    };
+    let element = match (
+        repetition,
+        parser_data.written_elements(helper, production).as_slice(),
+    ) {
+        (Repetition::ZeroOrMore, [only]) if *only == tail => tail_element(parser_data, tail)?,
+        (Repetition::OneOrMore, [element, rest]) if *rest == tail => {
+            (tail_element(parser_data, tail)? == *element).then_some(*element)?
+        }
+        _ => return None,
+    };
+    let looped = Diagram::OneOrMore(Box::new(element_diagram(parser_data, element, inlined)));
+    Some(match repetition {
+        Repetition::ZeroOrMore => Diagram::Choice(vec![looped, Diagram::Epsilon]),
+        _ => looped,
+    })
+}
+
+/// The repeated element of a repetition tail `tail -> element tail | #`.
+fn tail_element<T>(parser_data: &ParserData<T>, tail: ElementIndex) -> Option<ElementIndex>
+where
+    T: VM,
+{
+    let productions = &parser_data.get_rule_by_key(tail)?.possible_productions;
+    let [repeat, empty] = productions.as_slice() else {
+        return None;
+    };
+    match (
+        parser_data.written_elements(tail, repeat).as_slice(),
+        &**empty,
+    ) {
+        ([element, rest], Production::Empty) if *rest == tail => Some(*element),
+        _ => None,
+    }
+}
+
+/// Whether a rule is a group or repetition helper, which are drawn inline instead of getting a
+/// diagram of their own.
+fn is_inlined_helper(name: &str) -> bool {
+    if !is_generated_name(name) {
+        return false;
+    }
+    let suffix = name
+        .rsplit(GENERATED_NAME_SEPARATOR)
+        .next()
+        .unwrap_or_default();
+    suffix.starts_with('(')
+        || suffix
+            .chars()
+            .last()
+            .is_some_and(|chr| chr == '\'' || Repetition::from_symbol(chr).is_some())
+}
+
+fn rule_name<T>(parser_data: &ParserData<T>, ix: ElementIndex) -> String
+where
+    T: VM,
+{
+    parser_data
+        .get_element_data(ix)
+        .map_or_else(|| ix.to_string(), |data| data.name.clone())
+}
+
+/// The file name of the SVG of a rule. Chars other than letters, digits and `_` become `-`, so
+/// generated names like `expr/tail` give valid file names.
+#[must_use]
+pub fn svg_file_name(rule_name: &str) -> String {
+    format!("{}.svg", anchor(rule_name))
+}
+
+fn anchor(rule_name: &str) -> String {
+    rule_name
+        .chars()
+        .map(|chr| {
+            if chr.is_alphanumeric() || chr == '_' {
+                chr
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// An `<svg>` element with the title and diagram of one rule.
+fn rule_svg(name: &str, diagram: &Diagram, link: &dyn Fn(&str) -> String) -> String {
+    let extent = diagram.extent();
+    let line_width = extent.width + 2 * GAP;
+    let width = line_width + 2 * MARGIN;
+    let height = TITLE_HEIGHT + extent.up + extent.down + 2 * MARGIN;
+    let y = TITLE_HEIGHT + MARGIN + extent.up;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+        viewBox=\"0 0 {width} {height}\">\n{STYLE}\n"
+    );
+    let _ = writeln!(
+        svg,
+        "<text class=\"title\" x=\"{MARGIN}\" y=\"{}\">{}</text>",
+        MARGIN + 4,
+        xml_escape(name)
+    );
+    let half = BOX_HEIGHT / 2;
+    let _ = writeln!(
+        svg,
+        "<path d=\"M{MARGIN} {}v{BOX_HEIGHT}M{MARGIN} {y}h{GAP}\"/>",
+        y - half
+    );
+    diagram.draw(&mut svg, MARGIN + GAP, y, link);
+    let end = MARGIN + GAP + extent.width;
+    let _ = writeln!(
+        svg,
+        "<path d=\"M{end} {y}h{GAP}M{} {}v{BOX_HEIGHT}\"/>",
+        end + GAP,
+        y - half
+    );
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Railroad diagrams of every rule of a grammar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RailroadDiagrams {
+    /// Rule name and diagram, in the order the rules were first mentioned. Group and repetition
+    /// helpers are drawn inline and have no diagram of their own.
+    pub diagrams: Vec<(String, Diagram)>,
+}
+
+impl RailroadDiagrams {
+    /// The diagrams of a grammar text as written, before any `$TRANSFORM`. Syntax errors are
+    /// returned as diagnostics.
+    pub fn from_text<T>(rule_text: &str, vm: &T) -> Result<RailroadDiagrams, GrammarDiagnostics>
+    where
+        T: VM,
+    {
+        let parser_data = parsed_parser_data(rule_text, vm)?;
+        Ok(RailroadDiagrams::from_parser_data(&parser_data))
+    }
+
+    #[must_use]
+    pub fn from_parser_data<T>(parser_data: &ParserData<T>) -> RailroadDiagrams
+    where
+        T: VM,
+    {
+        let mut rules: Vec<ElementIndex> = parser_data.parse_rules.rules.keys().copied().collect();
+        rules.sort_unstable();
+        let diagrams = rules
+            .into_iter()
+            .map(|rule| (rule, rule_name(parser_data, rule)))
+            .filter(|(_, name)| !is_inlined_helper(name))
+            .filter_map(|(rule, name)| Some((name, Diagram::of_rule(parser_data, rule)?)))
+            .collect();
+        RailroadDiagrams { diagrams }
+    }
+
+    /// One self-contained SVG per rule as file name and content. Non-terminal boxes link to the
+    /// file of their rule, see [`svg_file_name`].
+    #[must_use]
+    pub fn svg_files(&self) -> Vec<(String, String)> {
+        self.diagrams
+            .iter()
+            .map(|(name, diagram)| {
+                (
+                    svg_file_name(name),
+                    rule_svg(name, diagram, &|target| svg_file_name(target)),
+                )
+            })
+            .collect()
+    }
+
+    /// An HTML page with the diagrams of all rules, non-terminal boxes linking to the diagram of
+    /// their rule on the same page.
+    #[must_use]
+    pub fn html_index(&self, title: &str) -> String {
+        let title = xml_escape(title);
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+            </head>\n<body>\n<h1>{title}</h1>\n<ul>\n"
+        );
+        for (name, _) in &self.diagrams {
+            let _ = writeln!(
+                html,
+                "<li><a href=\"#{}\">{}</a></li>",
+                anchor(name),
+                xml_escape(name)
+            );
+        }
+        html.push_str("</ul>\n");
+        for (name, diagram) in &self.diagrams {
+            let _ = writeln!(html, "<section id=\"{}\">", anchor(name));
+            html.push_str(&rule_svg(name, diagram, &|target| {
+                format!("#{}", anchor(target))
+            }));
+            html.push_str("</section>\n");
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vms::NullVm;
+
+    fn terminal(text: &str) -> Diagram {
+        Diagram::Terminal(format!("{text:?}"))
+    }
+
+    #[test]
+    fn test_groups_and_repetitions_are_drawn_inline() {
+        let rules = "ws -> \" \"*;\
+            $IGNORE: ws;\
+            start -> item (\",\" item)* \";\"?;\
+            item -> [0-9]+ | name | #;";
+        let diagrams = RailroadDiagrams::from_text(rules, &NullVm::new()).unwrap();
+        let names: Vec<&str> = diagrams.diagrams.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(vec!["ws", "start", "item"], names);
+
+        let item = || Diagram::NonTerminal(String::from("item"));
+        assert_eq!(
+            Diagram::Sequence(vec![
+                item(),
+                Diagram::Choice(vec![
+                    Diagram::OneOrMore(Box::new(Diagram::Sequence(vec![terminal(","), item()]))),
+                    Diagram::Epsilon,
+                ]),
+                Diagram::Choice(vec![terminal(";"), Diagram::Epsilon]),
+            ]),
+            diagrams.diagrams[1].1
+        );
+        assert_eq!(
+            Diagram::Choice(vec![
+                Diagram::OneOrMore(Box::new(Diagram::CharClass(String::from("[0-9]")))),
+                Diagram::NonTerminal(String::from("name")),
+                Diagram::Epsilon,
+            ]),
+            diagrams.diagrams[2].1
+        );
+    }
+
+    #[test]
+    fn test_svg_files_and_html_index_link_rules() {
+        let rules = "start -> \"<\" value \">\";value -> [0-9];";
+        let diagrams = RailroadDiagrams::from_text(rules, &NullVm::new()).unwrap();
+        let files = diagrams.svg_files();
+        assert_eq!("start.svg", files[0].0);
+        assert!(files[0].1.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(files[0].1.contains("<a href=\"value.svg\"><text"));
+        assert!(files[0].1.contains(">&quot;&lt;&quot;</text>"));
+
+        let html = diagrams.html_index("Values");
+        assert!(html.contains("<title>Values</title>"));
+        assert!(html.contains("<section id=\"value\">"));
+        assert!(html.contains("<a href=\"#value\"><text"));
+        assert_eq!("expr-tail.svg", svg_file_name("expr/tail"));
+    }
+}
@@ -0,0 +1,153 @@
+//! Single char edits that get a failed parse going again.
+//!
+//! When [`Parser`](crate::script_parser::Parser) runs into a syntax error it tries inserting,
+//! deleting and replacing one char at the error position. The chars tried are the ones the
+//! next thing still to be parsed at the error can start with. The edits the parser gets furthest
+//! with on the next [`REPAIR_WINDOW`] chars of the edited input are kept as [`Repair`]s, see
+//! [`ParserError::repairs`](crate::errors::ParserError::repairs).
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::parse_funcs::SourcePosition;
+
+/// At most this many repairs are kept for an error.
+pub const MAX_REPAIRS: usize = 5;
+
+/// How many chars from the error on an edit is checked against. Edits the parser gets this far
+/// with count as good as those that get it to the end.
+pub const REPAIR_WINDOW: usize = 32;
+
+/// A change of a single char.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Insert(char),
+    Delete(char),
+    Replace { old: char, new: char },
+}
+
+/// A single char edit of the input that fixes a syntax error, at least far enough for the
+/// parser to get past it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Repair {
+    /// Where the edit applies. An inserted char goes before the char at this position, a deleted
+    /// or replaced one is the char at it.
+    pub position: SourcePosition,
+    pub edit: Edit,
+}
+
+impl Repair {
+    /// The text with the edit applied. `text` has to be the text the position belongs to.
+    #[must_use]
+    pub fn apply(&self, text: &str) -> String {
+        let at = self.position.byte_offset;
+        let (before, after) = text.split_at(at);
+        let mut edited = String::from(before);
+        match self.edit {
+            Edit::Insert(chr) => {
+                edited.push(chr);
+                edited.push_str(after);
+            }
+            Edit::Delete(old) => edited.push_str(&after[old.len_utf8()..]),
+            Edit::Replace { old, new } => {
+                edited.push(new);
+                edited.push_str(&after[old.len_utf8()..]);
+            }
+        }
+        edited
+    }
+}
+
+impl fmt::Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let at = self.position;
+        match self.edit {
+            Edit::Insert(chr) => write!(f, "insert `{}` at {at}", chr.escape_debug()),
+            Edit::Delete(chr) => write!(f, "delete `{}` at {at}", chr.escape_debug()),
+            Edit::Replace { old, new } => write!(
+                f,
+                "replace `{}` with `{}` at {at}",
+                old.escape_debug(),
+                new.escape_debug()
+            ),
+        }
+    }
+}
+
+/// The edits to try at a position: inserting each candidate and, if there is a char `next` at
+/// the position, deleting it or replacing it with each other candidate.
+pub(crate) fn candidate_edits(candidates: &BTreeSet<char>, next: Option<char>) -> Vec<Edit> {
+    let mut edits: Vec<Edit> = candidates.iter().map(|&chr| Edit::Insert(chr)).collect();
+    if let Some(old) = next {
+        edits.push(Edit::Delete(old));
+        edits.extend(
+            candidates
+                .iter()
+                .filter(|&&new| new != old)
+                .map(|&new| Edit::Replace { old, new }),
+        );
+    }
+    edits
+}
+
+/// The char of the range `lo..=hi` to try in a repair: the first printable ASCII char if the
+/// range has one, `lo` otherwise.
+pub(crate) fn representative(lo: char, hi: char) -> char {
+    if lo <= '~' && hi >= ' ' {
+        lo.max(' ')
+    } else {
+        lo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_and_display() {
+        let text = "ä = (1\n";
+        let at = |pos| SourcePosition::of(text, pos);
+        let insert = Repair {
+            position: at(6),
+            edit: Edit::Insert(')'),
+        };
+        assert_eq!("ä = (1)\n", insert.apply(text));
+        assert_eq!("insert `)` at 1:7", insert.to_string());
+        let delete = Repair {
+            position: at(4),
+            edit: Edit::Delete('('),
+        };
+        assert_eq!("ä = 1\n", delete.apply(text));
+        assert_eq!("delete `(` at 1:5", delete.to_string());
+        let replace = Repair {
+            position: at(6),
+            edit: Edit::Replace {
+                old: '\n',
+                new: ';',
+            },
+        };
+        assert_eq!("ä = (1;", replace.apply(text));
+        assert_eq!("replace `\\n` with `;` at 1:7", replace.to_string());
+    }
+
+    #[test]
+    fn test_candidate_edits() {
+        let candidates = BTreeSet::from([';', 'a']);
+        assert_eq!(
+            vec![Edit::Insert(';'), Edit::Insert('a')],
+            candidate_edits(&candidates, None)
+        );
+        assert_eq!(
+            vec![
+                Edit::Insert(';'),
+                Edit::Insert('a'),
+                Edit::Delete('a'),
+                Edit::Replace { old: 'a', new: ';' },
+            ],
+            candidate_edits(&candidates, Some('a'))
+        );
+        assert_eq!(' ', representative('\0', char::MAX));
+        assert_eq!('0', representative('0', '9'));
+        assert_eq!('\u{e9}', representative('\u{e9}', '\u{ff}'));
+    }
+}
@@ -0,0 +1,390 @@
+//! Human-readable FIRST/FOLLOW sets and parse tables of a grammar.
+//!
+//! The sets and steuer maps the parser is built from are keyed by [`ElementIndex`].
+//! [`GrammarReport`] collects them per rule name, with the parse table written as lookahead and
+//! alternative in grammar notation, and renders them as plain text, Markdown or CSV. Rules are
+//! listed in the order they were first mentioned, so reports of two versions of a grammar can be
+//! diffed.
+use std::fmt;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::diagnostics::left_recursion_diagnostics;
+use crate::errors::{GrammarDiagnostic, GrammarDiagnostics, GrammarError};
+use crate::first_sets::get_first_sets;
+use crate::follow_sets::get_follow_sets_for_entry_points;
+use crate::parser_data::{ElementIndex, ParserData, Production};
+use crate::script_parser::transformed_parser_data;
+use crate::sets::{NamedSets, NamedSetsNoEmpty, SetMember, SetMemberWithEmpty};
+use crate::steuer_map::{steuer_keys_of_production, steuermap_of_rule};
+use crate::steuer_sets::get_steuer_sets;
+use crate::vms::VM;
+
+/// The formats a [`GrammarReport`] can be rendered in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Text,
+    Markdown,
+    Csv,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ReportFormat::Text),
+            "markdown" | "md" => Ok(ReportFormat::Markdown),
+            "csv" => Ok(ReportFormat::Csv),
+            other => Err(format!(
+                "unknown report format \"{other}\", expected text, markdown or csv"
+            )),
+        }
+    }
+}
+
+/// The sets and parse table entries of one non-terminal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleReport {
+    pub name: String,
+    pub nullable: bool,
+    /// The FIRST set without the empty string, sorted.
+    pub first: Vec<String>,
+    /// The FOLLOW set, sorted.
+    pub follow: Vec<String>,
+    /// Lookahead and the alternative it selects. Terminals the parser compares as a whole are
+    /// written as a quoted terminal. A rule with an LL(1) conflict has several entries for the
+    /// same lookahead.
+    pub table: Vec<(String, String)>,
+}
+
+/// The FIRST/FOLLOW sets and LL(1) parse table of every non-terminal of a grammar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrammarReport {
+    pub rules: Vec<RuleReport>,
+}
+
+impl GrammarReport {
+    /// Builds the report of a grammar text, after the transforms it opted into. Grammars with
+    /// LL(1) conflicts still get a report, syntax errors and left recursion are returned as
+    /// diagnostics.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`GrammarDiagnostics`] for syntax errors, left recursion and errors computing the
+    /// FIRST/FOLLOW sets.
+    pub fn from_text<T>(rule_text: &str, vm: &T) -> Result<GrammarReport, GrammarDiagnostics>
+    where
+        T: VM,
+    {
+        let parser_data = transformed_parser_data(rule_text, vm)?;
+        let left_recursion = left_recursion_diagnostics(&parser_data, Some(rule_text));
+        if !left_recursion.is_empty() {
+            return Err(GrammarDiagnostics {
+                diagnostics: left_recursion,
+            });
+        }
+        let entry_points = parser_data.entry_points();
+        GrammarReport::from_parser_data(&parser_data, &entry_points).map_err(|err| {
+            GrammarDiagnostics {
+                diagnostics: vec![GrammarDiagnostic {
+                    message: err.to_string(),
+                    rule: None,
+                    location: None,
+                }],
+            }
+        })
+    }
+
+    /// Builds the report of a complete grammar parsed from `entry_points`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`GrammarError`] if the FIRST/FOLLOW sets can't be computed, e.g. for a
+    /// left-recursive grammar.
+    pub fn from_parser_data<T>(
+        parser_data: &ParserData<T>,
+        entry_points: &[ElementIndex],
+    ) -> Result<GrammarReport, GrammarError>
+    where
+        T: VM,
+    {
+        let first_sets = get_first_sets(parser_data)?;
+        let follow_sets = get_follow_sets_for_entry_points(entry_points, &first_sets, parser_data)?;
+        let mut rules: Vec<ElementIndex> = parser_data.parse_rules.rules.keys().copied().collect();
+        rules.sort_unstable();
+        let rules = rules
+            .into_iter()
+            .map(|rule| rule_report(rule, &first_sets, &follow_sets, parser_data))
+            .collect::<Result<_, _>>()?;
+        Ok(GrammarReport { rules })
+    }
+
+    #[must_use]
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Text => self.to_text(),
+            ReportFormat::Markdown => self.to_markdown(),
+            ReportFormat::Csv => self.to_csv(),
+        }
+    }
+
+    /// A block per rule with its sets and table entries.
+    #[must_use]
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for rule in &self.rules {
+            let _ = writeln!(text, "{}", rule.name);
+            let _ = writeln!(text, "  nullable: {}", yes_no(rule.nullable));
+            let _ = writeln!(text, "  FIRST:    {}", rule.first.join(" "));
+            let _ = writeln!(text, "  FOLLOW:   {}", rule.follow.join(" "));
+            for (lookahead, alternative) in &rule.table {
+                let _ = writeln!(text, "  {lookahead} -> {alternative}");
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// A table of the sets and a table of the parse table entries.
+    #[must_use]
+    pub fn to_markdown(&self) -> String {
+        let mut text = String::from("| Rule | Nullable | FIRST | FOLLOW |\n|---|---|---|---|\n");
+        for rule in &self.rules {
+            let _ = writeln!(
+                text,
+                "| {} | {} | {} | {} |",
+                markdown_cell(&rule.name),
+                yes_no(rule.nullable),
+                markdown_cell(&rule.first.join(" ")),
+                markdown_cell(&rule.follow.join(" "))
+            );
+        }
+        text.push_str("\n| Rule | Lookahead | Alternative |\n|---|---|---|\n");
+        for rule in &self.rules {
+            for (lookahead, alternative) in &rule.table {
+                let _ = writeln!(
+                    text,
+                    "| {} | {} | {} |",
+                    markdown_cell(&rule.name),
+                    markdown_cell(lookahead),
+                    markdown_cell(alternative)
+                );
+            }
+        }
+        text
+    }
+
+    /// One row per parse table entry, with the sets of its rule repeated. Rules without entries
+    /// get a row with empty lookahead and alternative.
+    #[must_use]
+    pub fn to_csv(&self) -> String {
+        let mut text = String::from("rule,nullable,first,follow,lookahead,alternative\n");
+        for rule in &self.rules {
+            let sets = [
+                csv_field(&rule.name),
+                csv_field(yes_no(rule.nullable)),
+                csv_field(&rule.first.join(" ")),
+                csv_field(&rule.follow.join(" ")),
+            ]
+            .join(",");
+            if rule.table.is_empty() {
+                let _ = writeln!(text, "{sets},,");
+            }
+            for (lookahead, alternative) in &rule.table {
+                let _ = writeln!(
+                    text,
+                    "{sets},{},{}",
+                    csv_field(lookahead),
+                    csv_field(alternative)
+                );
+            }
+        }
+        text
+    }
+}
+
+fn rule_report<T>(
+    rule: ElementIndex,
+    first_sets: &NamedSets,
+    follow_sets: &NamedSetsNoEmpty,
+    parser_data: &ParserData<T>,
+) -> Result<RuleReport, GrammarError>
+where
+    T: VM,
+{
+    let first = first_sets
+        .get(&rule)
+        .ok_or(GrammarError::MissingFirstSet { index: rule })?;
+    let follow = follow_sets
+        .get(&rule)
+        .ok_or(GrammarError::MissingFollowSet { index: rule })?;
+    let mut first_members: Vec<SetMember> = first
+        .iter()
+        .filter_map(|member| SetMember::try_from(*member).ok())
+        .collect();
+    first_members.sort_unstable();
+    let mut follow_members: Vec<SetMember> = follow.iter().copied().collect();
+    follow_members.sort_unstable();
+
+    Ok(RuleReport {
+        name: parser_data
+            .get_element_data(rule)
+            .map_or_else(|| rule.to_string(), |data| data.name.clone()),
+        nullable: first.contains(&SetMemberWithEmpty::Empty),
+        first: first_members.iter().map(SetMember::to_string).collect(),
+        follow: follow_members.iter().map(SetMember::to_string).collect(),
+        table: parse_table(rule, first_sets, follow_sets, parser_data)?,
+    })
+}
+
+/// The entries of the steuer map and terminal tries of `rule`, or every steuer key of every
+/// alternative if the rule has a conflict.
+fn parse_table<T>(
+    rule: ElementIndex,
+    first_sets: &NamedSets,
+    follow_sets: &NamedSetsNoEmpty,
+    parser_data: &ParserData<T>,
+) -> Result<Vec<(String, String)>, GrammarError>
+where
+    T: VM,
+{
+    let steuer_sets = get_steuer_sets(first_sets, follow_sets)?;
+    let notation = |production: &Production| parser_data.production_notation(rule, production);
+    let mut entries: Vec<(LookaheadKey, String)> = vec![];
+    if let Ok((steuermap, tries)) =
+        steuermap_of_rule(&steuer_sets, first_sets, follow_sets, rule, parser_data)
+    {
+        for (key, production) in &steuermap {
+            entries.push((LookaheadKey::Member(*key), notation(production)));
+        }
+        for trie in tries.values() {
+            for (terminal, production) in trie.entries() {
+                entries.push((LookaheadKey::Terminal(terminal), notation(production)));
+            }
+        }
+    } else {
+        let productions = &parser_data
+            .get_rule_by_key(rule)
+            .ok_or(GrammarError::MissingProduction { index: rule })?
+            .possible_productions;
+        for production in productions {
+            let (keys, _) = steuer_keys_of_production(
+                &steuer_sets,
+                follow_sets,
+                production,
+                rule,
+                parser_data,
+            )?;
+            for key in keys {
+                entries.push((LookaheadKey::Member(key), notation(production)));
+            }
+        }
+    }
+    entries.sort();
+    Ok(entries
+        .into_iter()
+        .map(|(key, alternative)| (key.to_string(), alternative))
+        .collect())
+}
+
+/// A lookahead of the parse table: a char or range, or a terminal compared as a whole.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum LookaheadKey {
+    Member(SetMember),
+    Terminal(String),
+}
+
+impl fmt::Display for LookaheadKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LookaheadKey::Member(member) => write!(f, "{member}"),
+            LookaheadKey::Terminal(terminal) => write!(f, "{terminal:?}"),
+        }
+    }
+}
+
+fn yes_no(flag: bool) -> &'static str {
+    if flag { "yes" } else { "no" }
+}
+
+fn markdown_cell(text: &str) -> String {
+    if text.is_empty() {
+        String::new()
+    } else {
+        format!("`{}`", text.replace('|', "\\|"))
+    }
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        String::from(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vms::NullVm;
+
+    #[test]
+    fn test_report_as_text() {
+        let rules = "start -> \"print\" value | \"pop\" | value;\
+            value -> [0-9] | #;";
+        let report = GrammarReport::from_text(rules, &NullVm::new()).unwrap();
+        assert_eq!(
+            "start\n  \
+            nullable: yes\n  \
+            FIRST:    'p' '0'-'9'\n  \
+            FOLLOW:   terminate\n  \
+            '0'-'9' -> value\n  \
+            terminate -> value\n  \
+            \"pop\" -> \"pop\"\n  \
+            \"print\" -> \"print\" value\n\
+            \n\
+            value\n  \
+            nullable: yes\n  \
+            FIRST:    '0'-'9'\n  \
+            FOLLOW:   terminate\n  \
+            '0'-'9' -> [0-9]\n  \
+            terminate -> #\n\n",
+            report.to_text()
+        );
+    }
+
+    #[test]
+    fn test_report_escapes_markdown_and_csv() {
+        let rules = "start -> \"|\" | \",\" | \"\\\"\";";
+        let report = GrammarReport::from_text(rules, &NullVm::new()).unwrap();
+        let markdown = report.render(ReportFormat::Markdown);
+        assert!(
+            markdown.contains("| `start` | `'\\|'` | `\"\\|\"` |"),
+            "{markdown}"
+        );
+        let csv = report.render(ReportFormat::Csv);
+        assert!(
+            csv.contains(r#"start,no,"'""' ',' '|'",terminate,"','",""",""""#),
+            "{csv}"
+        );
+        assert!(
+            csv.contains(r#"start,no,"'""' ',' '|'",terminate,"'""'","""\""""""#),
+            "{csv}"
+        );
+    }
+
+    #[test]
+    fn test_report_of_conflicting_rule_lists_every_alternative() {
+        let report = GrammarReport::from_text("start -> [a-c] | \"b\";", &NullVm::new()).unwrap();
+        assert_eq!(
+            vec![
+                (String::from("'b'"), String::from("\"b\"")),
+                (String::from("'a'-'c'"), String::from("[a-c]")),
+            ],
+            report.rules[0].table
+        );
+        assert_eq!(Ok(ReportFormat::Markdown), "md".parse());
+        assert!("html".parse::<ReportFormat>().is_err());
+    }
+}
@@ -10,13 +10,17 @@ use std::mem;
 use std::rc::Rc;
 use std::str::Chars;
 
+use crate::char_class::parse_char_class;
 use crate::errors::GrammarError::UnexpectedElementError;
 use crate::errors::ParserError;
 use crate::errors::ParserError::{EndOfCharsError, UnexpectedCharError};
-use crate::parse_funcs::{parse_symbol, parse_var_name, parse_whitespace};
+use crate::parse_funcs::{
+    parse_escape, parse_symbol, parse_usize, parse_var_name, parse_whitespace,
+    parse_whitespace_and_comments,
+};
 use crate::parser_data::{
     ElementIndex, ElementType, ElementVerbose, NonTerminalRules, ParseRules, ParserData,
-    PossibleProductions, Ppp, Production, RuleMap,
+    PossibleProductions, Ppp, Production, Repetition, RuleMap, generated_name,
 };
 use crate::peekables::{ParseProcess, PeekableWrapper, TPeekable};
 use crate::vms::VM;
@@ -30,6 +34,11 @@ where
     pub vm: &'vm T,
     pub parse_process: Ppp<'pp>,
     pub parser_data: ParserData<T>,
+    /// Name and ignore rule of the rule being parsed, used for generated helper rules.
+    current_rule: String,
+    current_ignore: Option<ElementIndex>,
+    /// Groups opened so far in the current rule, used to number them.
+    group_count: usize,
 }
 
 impl<'vm, 'pp, T> RuleParser<'vm, 'pp, T>
@@ -46,22 +55,80 @@ where
             vm,
             parse_process,
             parser_data,
+            current_rule: String::new(),
+            current_ignore: None,
+            group_count: 0,
         }
     }
 
     pub fn parse_rules(&mut self) -> Result<&ParseRules<T>, ParserError> {
         loop {
-            self.parse_whitespace();
-            if self.parse_process.peek().is_none() {
-                break;
+            self.parse_whitespace()?;
+            match self.parse_process.peek() {
+                None => break,
+                Some('$') => self.parse_directive()?,
+                Some(_) => {
+                    let (rule_name, rule) = self.parse_rule()?;
+                    self.merge_rule(rule, rule_name);
+                }
             }
-            let (rule_name, rule) = self.parse_rule()?;
-            self.merge_rule(rule, rule_name);
         }
         self.edit_rules()?;
         Ok(&self.parser_data.parse_rules)
     }
 
+    /// Like [`RuleParser::parse_rules`], but does not stop at the first syntax error.
+    ///
+    /// After an error the rest of the rule up to its `;` is skipped and parsing goes on with the
+    /// next rule. Returns every error together with the name of the rule it occurred in.
+    pub fn parse_rules_recovering(&mut self) -> Vec<(Option<String>, ParserError)> {
+        let mut errors = vec![];
+        loop {
+            self.current_rule.clear();
+            let result = match self.parse_whitespace() {
+                Err(err) => Err(err),
+                Ok(()) => match self.parse_process.peek() {
+                    None => break,
+                    Some('$') => self.parse_directive(),
+                    Some(_) => self
+                        .parse_rule()
+                        .map(|(rule_name, rule)| self.merge_rule(rule, rule_name)),
+                },
+            };
+            if let Err(err) = result {
+                let rule = Some(self.current_rule.clone()).filter(|name| !name.is_empty());
+                errors.push((rule, err));
+                self.skip_to_rule_end();
+            }
+        }
+        if let Err(err) = self.edit_rules() {
+            errors.push((None, err));
+        }
+        errors
+    }
+
+    /// Skips input up to and including the next `;` that is not part of a terminal, a character
+    /// class or an instruction section.
+    fn skip_to_rule_end(&mut self) {
+        let mut in_terminal = false;
+        let mut in_class = false;
+        let mut braces = 0usize;
+        while let Some(chr) = self.parse_process.next() {
+            match chr {
+                '\\' if in_terminal || in_class || braces > 0 => {
+                    self.parse_process.next();
+                }
+                '"' if !in_class && braces == 0 => in_terminal = !in_terminal,
+                '[' if !in_terminal && braces == 0 => in_class = true,
+                ']' if in_class => in_class = false,
+                '{' if !in_terminal && !in_class => braces += 1,
+                '}' if braces > 0 => braces -= 1,
+                ';' if !in_terminal && !in_class && braces == 0 => return,
+                _ => {}
+            }
+        }
+    }
+
     fn edit_rules(&mut self) -> Result<(), ParserError> {
         let mut edited_rules = RuleMap::new();
         struct Action {
@@ -110,6 +177,8 @@ where
                 possible_productions,
                 ignore: ignore_new,
                 instruction: rule.instruction,
+                flatten: rule.flatten,
+                instructions_of: rule.instructions_of,
             };
 
             edited_rules.insert(rule_name.clone(), new_rul);
@@ -131,6 +200,7 @@ where
     }
 
     pub fn parse_rule(&mut self) -> Result<(ElementIndex, NonTerminalRules<T>), ParserError> {
+        let pos = self.parse_process.cur_pos();
         let identifier = parse_var_name(&mut self.parse_process)?;
 
         let key = self
@@ -139,20 +209,26 @@ where
                 identifier.clone(),
                 ElementType::NonTerminal,
             ));
+        self.parser_data.note_definition(key, pos);
+        self.current_rule.clone_from(&identifier);
 
-        self.parse_whitespace();
+        self.parse_whitespace()?;
         self.parse_symbol('-')?;
         self.parse_symbol('>')?;
-        self.parse_whitespace();
+        self.parse_whitespace()?;
         let ignore_this_maybe = self.parse_overrides()?;
+        self.current_ignore = ignore_this_maybe;
+        self.group_count = 0;
         let productions: PossibleProductions = self.parse_possible_productions()?;
         let instruction = self.parse_instruction_section(&identifier)?;
-        self.parse_whitespace();
+        self.parse_whitespace()?;
         self.parse_symbol(';')?;
         let nt_rules = NonTerminalRules::<T> {
             possible_productions: productions,
             ignore: ignore_this_maybe,
             instruction,
+            flatten: false,
+            instructions_of: None,
         };
         Ok((key, nt_rules))
     }
@@ -192,27 +268,121 @@ where
         }
     }
 
+    /// Parses a top-level directive like `$IGNORE: whitespace;`.
+    ///
+    /// `$IGNORE` sets the global ignore rule. Every rule defined after the directive inherits it
+    /// unless it overrides it with `$[IGNORE: ...]`. `$IGNORE: #;` switches ignoring off again.
+    ///
+    /// `$START: program, expression;` declares the rules parsing can start from. The first declared
+    /// rule is the default one.
+    ///
+    /// `$LOOKAHEAD: 2;` builds an LL(k) parser that looks up to k chars ahead.
+    fn parse_directive(&mut self) -> Result<(), ParserError> {
+        self.parse_symbol('$')?;
+        let pos = self.parse_process.cur_pos();
+        let directive = parse_var_name(&mut self.parse_process)?;
+        self.parse_whitespace()?;
+        self.parse_symbol(':')?;
+        self.parse_whitespace()?;
+
+        match directive.as_str() {
+            "IGNORE" => {
+                self.parser_data.parse_rules.ignore = if self.parse_symbol('#').is_ok() {
+                    None
+                } else {
+                    let pos = self.parse_process.cur_pos();
+                    let ignore_name = parse_var_name(&mut self.parse_process)?;
+                    let ignore = self.parser_data.get_or_add_non_terminal(&ignore_name);
+                    self.parser_data.note_reference(ignore, pos, None);
+                    Some(ignore)
+                };
+            }
+            "START" => loop {
+                let pos = self.parse_process.cur_pos();
+                let name = parse_var_name(&mut self.parse_process)?;
+                let entry_point = self.parser_data.get_or_add_non_terminal(&name);
+                self.parser_data.note_reference(entry_point, pos, None);
+                if !self.parser_data.parse_rules.entry_points.contains(&entry_point) {
+                    self.parser_data.parse_rules.entry_points.push(entry_point);
+                }
+                self.parse_whitespace()?;
+                if self.parse_symbol(',').is_err() {
+                    break;
+                }
+                self.parse_whitespace()?;
+            },
+            "TRANSFORM" => loop {
+                let pos = self.parse_process.cur_pos();
+                let transform = parse_var_name(&mut self.parse_process)?;
+                match transform.as_str() {
+                    "eliminate_left_recursion" => {
+                        self.parser_data.parse_rules.eliminate_left_recursion = true;
+                    }
+                    "left_factor" => self.parser_data.parse_rules.left_factor = true,
+                    _ => {
+                        return Err(ParserError::UnknownSpecialOperation {
+                            operation: transform,
+                            pos,
+                        });
+                    }
+                }
+                self.parse_whitespace()?;
+                if self.parse_symbol(',').is_err() {
+                    break;
+                }
+                self.parse_whitespace()?;
+            },
+            "LOOKAHEAD" => {
+                let pos = self.parse_process.cur_pos();
+                let k = parse_usize(&mut self.parse_process)?;
+                if k == 0 {
+                    return Err(ParserError::UnexpectedCharError {
+                        chr: '0',
+                        pos,
+                        expected: String::from("lookahead of at least 1"),
+                        rules: vec![],
+                        repairs: vec![],
+                    });
+                }
+                self.parser_data.parse_rules.lookahead = k;
+            }
+            _ => {
+                return Err(ParserError::UnknownSpecialOperation {
+                    operation: directive,
+                    pos,
+                });
+            }
+        }
+
+        self.parse_whitespace()?;
+        self.parse_symbol(';')
+    }
+
     fn parse_overrides(&mut self) -> Result<Option<ElementIndex>, ParserError> {
         let mut ignore_this = self.parser_data.parse_rules.ignore.clone();
         if self.parse_symbol('$').is_ok() {
             self.parse_symbol('[')?;
             let varname = parse_var_name(&mut self.parse_process)?;
-            self.parse_whitespace();
+            self.parse_whitespace()?;
             self.parse_symbol(':')?;
+            self.parse_whitespace()?;
 
             if varname == "IGNORE" {
                 if self.parse_symbol('#').is_ok() {
                     ignore_this = None;
                 } else {
-                    ignore_this = Some(self.parser_data.get_or_add_element_key(
-                        &ElementVerbose::new(
-                            parse_var_name(&mut self.parse_process)?,
-                            ElementType::NonTerminal,
-                        ),
+                    let pos = self.parse_process.cur_pos();
+                    let ignore = self.parser_data.get_or_add_element_key(&ElementVerbose::new(
+                        parse_var_name(&mut self.parse_process)?,
+                        ElementType::NonTerminal,
                     ));
+                    let rule = self.parser_data.get_element_nt_index(&self.current_rule);
+                    self.parser_data.note_reference(ignore, pos, rule);
+                    ignore_this = Some(ignore);
                 }
             }
 
+            self.parse_whitespace()?;
             self.parse_symbol(']')?;
         };
 
@@ -229,8 +399,8 @@ where
                 return Ok(vec![]);
             }
         };
-        self.parse_whitespace();
-        let cur_pos = self.parse_process.cur_pos();
+        parse_whitespace(&mut self.parse_process);
+        let cur_pos = self.parse_process.source_position();
         let mut g =
             ParseProcess::new_nested(&mut self.parse_process, Some('}'), Some('\\'), cur_pos);
         let instruction = self.vm.parse_instructions(prod_name, &mut g)?;
@@ -241,11 +411,11 @@ where
     pub fn parse_possible_productions(&mut self) -> Result<PossibleProductions, ParserError> {
         let mut elements = vec![self.parse_production()?];
         loop {
-            self.parse_whitespace();
+            self.parse_whitespace()?;
             let _ = self.parse_symbol('\n');
-            self.parse_whitespace();
+            self.parse_whitespace()?;
             if self.parse_symbol('|').is_ok() {
-                self.parse_whitespace();
+                self.parse_whitespace()?;
                 elements.push(self.parse_production()?);
             } else {
                 break;
@@ -257,8 +427,20 @@ where
     pub fn parse_production(&mut self) -> Result<Rc<Production>, ParserError> {
         let mut result = vec![];
         loop {
-            parse_whitespace(&mut self.parse_process);
-            if let Ok(element) = RuleParser::<'vm, 'pp, T>::parse_element(&mut self.parse_process) {
+            self.parse_whitespace()?;
+            if self.parse_process.peek() == Some(&'(') {
+                let (index, source) = self.parse_group()?;
+                let index = self.parse_repetitions(index, source);
+                result.push(index);
+                continue;
+            }
+            let pos = self.parse_process.cur_pos();
+            let starts_element = self
+                .parse_process
+                .peek()
+                .is_some_and(|chr| matches!(chr, '#' | '"' | '[' | '.') || chr.is_alphabetic());
+            if starts_element {
+                let element = RuleParser::<'vm, 'pp, T>::parse_element(&mut self.parse_process)?;
                 match element {
                     None => {
                         if !result.is_empty() {
@@ -275,6 +457,11 @@ where
                     }
                     Some(el) => {
                         let index = self.parser_data.get_or_add_element_key(&el);
+                        if el.et == ElementType::NonTerminal {
+                            let rule = self.parser_data.get_element_nt_index(&self.current_rule);
+                            self.parser_data.note_reference(index, pos, rule);
+                        }
+                        let index = self.parse_repetitions(index, element_source(&el));
                         result.push(index);
                     }
                 }
@@ -296,6 +483,57 @@ where
         })
     }
 
+    /// Parses a group `( ... | ... )` into an anonymous rule and returns it with its source name.
+    ///
+    /// Groups are numbered by the position of their opening parenthesis in the enclosing rule,
+    /// so the second group of `expr` becomes the rule `expr/(2)`. A group keeps its own tree node.
+    fn parse_group(&mut self) -> Result<(ElementIndex, String), ParserError> {
+        let pos = self.parse_process.cur_pos();
+        self.parse_symbol('(')?;
+        let (index, source) = loop {
+            self.group_count += 1;
+            let source = format!("({})", self.group_count);
+            let name = generated_name(&self.current_rule, &source);
+            if self.parser_data.get_element_nt_index(&name).is_none() {
+                break (self.parser_data.get_or_add_non_terminal(&name), source);
+            }
+        };
+        self.parser_data.note_definition(index, pos);
+        self.parse_whitespace()?;
+        let productions = self.parse_possible_productions()?;
+        self.parse_whitespace()?;
+        self.parse_symbol(')')?;
+        self.merge_rule(
+            NonTerminalRules::new(productions, self.current_ignore, vec![]),
+            index,
+        );
+        Ok((index, source))
+    }
+
+    /// Parses postfix `*`, `+` and `?` after an element and desugars them into helper rules.
+    ///
+    /// Operators can be stacked, `"a"+?` is an optional list of `"a"`s.
+    fn parse_repetitions(&mut self, mut index: ElementIndex, mut source: String) -> ElementIndex {
+        while let Some(repetition) = self
+            .parse_process
+            .peek()
+            .and_then(|chr| Repetition::from_symbol(*chr))
+        {
+            let pos = self.parse_process.cur_pos();
+            self.parse_process.next();
+            source.push(repetition.symbol());
+            let name = generated_name(&self.current_rule, &source);
+            index = self
+                .parser_data
+                .add_repetition(&name, index, repetition, self.current_ignore);
+            self.parser_data.note_definition(index, pos);
+            if let Some(tail) = self.parser_data.get_element_nt_index(&format!("{name}'")) {
+                self.parser_data.note_definition(tail, pos);
+            }
+        }
+        index
+    }
+
     pub fn parse_element(
         to_parse: &mut ParseProcess<PeekableWrapper<Chars>>,
     ) -> Result<Option<ElementVerbose>, ParserError> {
@@ -308,6 +546,9 @@ where
                 parse_terminal(to_parse)?,
                 ElementType::Terminal,
             ))),
+            Some('[' | '.') => Ok(Some(ElementVerbose::new_class(
+                parse_char_class(to_parse)?.0,
+            ))),
             Some(x) if x.is_alphabetic() => Ok(Some(ElementVerbose::new(
                 parse_var_name(to_parse)?,
                 ElementType::NonTerminal,
@@ -316,11 +557,15 @@ where
                 chr: *x,
                 pos: to_parse.cur_pos(),
                 expected: String::from(
-                    "char # for empty, \" for terminal ort alphabetic for element",
+                    "char # for empty, \" for terminal, [ or . for a character class, ( for a group ort alphabetic for element",
                 ),
+                rules: vec![],
+                repairs: vec![],
             }),
             _ => Err(EndOfCharsError {
                 pos: to_parse.cur_pos(),
+                rules: vec![],
+                repairs: vec![],
             }),
         }
     }
@@ -329,8 +574,9 @@ where
         parse_symbol(&mut self.parse_process, sym)
     }
 
-    fn parse_whitespace(&mut self) {
-        parse_whitespace(&mut self.parse_process);
+    /// Skips whitespace and comments in grammar text.
+    fn parse_whitespace(&mut self) -> Result<(), ParserError> {
+        parse_whitespace_and_comments(&mut self.parse_process)
     }
 }
 
@@ -338,23 +584,45 @@ pub fn parse_terminal(
     to_parse: &mut ParseProcess<PeekableWrapper<Chars>>,
 ) -> Result<String, ParserError> {
     parse_symbol(to_parse, '"')?;
-    let mut literal = "".to_owned();
-    let mut escape = false;
-    let mut pos = to_parse.cur_pos();
-    let mut cur_char = to_parse.peek().ok_or(EndOfCharsError { pos })?;
-    while *cur_char != '"' || escape {
-        if *cur_char == '\\' && !escape {
-            escape = true
-        } else {
-            escape = false
+    let mut literal = String::new();
+    loop {
+        let pos = to_parse.cur_pos();
+        match to_parse.next() {
+            None => {
+                return Err(EndOfCharsError {
+                    pos,
+                    rules: vec![],
+                    repairs: vec![],
+                });
+            }
+            Some('"') => return Ok(literal),
+            Some('\\') => match parse_escape(to_parse, pos, &[]) {
+                Ok(chr) => literal.push(chr),
+                Err(err) => {
+                    // skip the rest of the terminal so parsing can go on after it
+                    while let Some(chr) = to_parse.next() {
+                        match chr {
+                            '"' => break,
+                            '\\' => {
+                                to_parse.next();
+                            }
+                            _ => {}
+                        }
+                    }
+                    return Err(err);
+                }
+            },
+            Some(chr) => literal.push(chr),
         }
-        pos = to_parse.cur_pos();
-        literal.push(to_parse.next().ok_or(EndOfCharsError { pos })?);
-        pos = to_parse.cur_pos();
-        cur_char = to_parse.peek().ok_or(EndOfCharsError { pos })?
     }
-    to_parse.next(); //discard trailing quote
-    Ok(literal)
+}
+
+/// How an element is written in grammar text, used to name generated rules.
+fn element_source(element: &ElementVerbose) -> String {
+    match element.et {
+        ElementType::Terminal => format!("{:?}", element.name),
+        ElementType::NonTerminal | ElementType::CharClass => element.name.clone(),
+    }
 }
 
 fn check_is_derivative<T>(
@@ -379,16 +647,14 @@ fn _check_is_derivative<T>(
 where
     T: VM,
 {
-    if let ElementType::Terminal = element_types.get(left).unwrap() {
+    if ElementType::NonTerminal != *element_types.get(left).unwrap() {
         return Ok(false);
     }
-    let prods = &parse_rules
-        .rules
-        .get(&left)
-        .ok_or(ParserError::InternalError {
-            message: format!("could not find production for index {left}"),
-        })?
-        .possible_productions;
+    // undefined non-terminals derive nothing here, the diagnostics report them
+    let Some(rule) = parse_rules.rules.get(&left) else {
+        return Ok(left == right);
+    };
+    let prods = &rule.possible_productions;
 
     if left == right {
         return Ok(true);
@@ -443,7 +709,7 @@ mod tests {
         let mut peekable = PeekableWrapper::<Chars>::new("\"cont\\\"ents\"".chars().peekable());
         let mut to_parse = ParseProcess::<PeekableWrapper<Chars>>::new(&mut peekable, None, None);
         let result = parse_terminal(&mut to_parse);
-        assert_eq!("cont\\\"ents", result.unwrap());
+        assert_eq!("cont\"ents", result.unwrap());
         assert_eq!("", to_parse.collect::<String>())
     }
 
@@ -461,10 +727,29 @@ mod tests {
         let mut peekable = PeekableWrapper::<Chars>::new("\"asdf\\n\\\"sdf\"".chars().peekable());
         let mut to_parse = ParseProcess::<PeekableWrapper<Chars>>::new(&mut peekable, None, None);
         let result = parse_terminal(&mut to_parse);
-        assert_eq!("asdf\\n\\\"sdf", result.unwrap());
+        assert_eq!("asdf\n\"sdf", result.unwrap());
         assert_eq!("", to_parse.collect::<String>())
     }
 
+    #[test]
+    fn test_parse_terminal_escapes() {
+        let mut peekable = PeekableWrapper::from_str("\"\\t\\r\\\\\\u{41}\"");
+        let mut to_parse = ParseProcess::new(&mut peekable, None, None);
+        assert_eq!("\t\r\\A", parse_terminal(&mut to_parse).unwrap());
+    }
+
+    #[test]
+    fn test_parse_terminal_unknown_escape() {
+        let mut peekable = PeekableWrapper::from_str("\"ab\\xc\"");
+        let mut to_parse = ParseProcess::new(&mut peekable, None, None);
+        match parse_terminal(&mut to_parse) {
+            Err(ParserError::GramError {
+                err: UnexpectedElementError { pos, .. },
+            }) => assert_eq!(3, pos),
+            _ => panic!("\\x is no escape sequence"),
+        }
+    }
+
     #[test]
     #[should_panic]
     fn test_parse_terminal_fail_on_missing_trailing_quote() {
@@ -543,6 +828,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_production_char_classes() {
+        let mut peekable = PeekableWrapper::from_str("[a-z] . \"x\"");
+
+        let vm = NullVm::new();
+        let mut parser = RuleParser::new(&mut peekable, &vm);
+        let result = &*parser.parse_production().unwrap();
+        let Production::NotEmpty(result) = result else {
+            panic!()
+        };
+        assert_eq!(
+            ElementVerbose::new_class("[a-z]".to_string()),
+            parser.parser_data.get_element_verbose(result[0]).unwrap()
+        );
+        assert_eq!(
+            ElementVerbose::new_class(".".to_string()),
+            parser.parser_data.get_element_verbose(result[1]).unwrap()
+        );
+        assert_eq!(
+            ElementVerbose::new_t("x".to_string()),
+            parser.parser_data.get_element_verbose(result[2]).unwrap()
+        );
+    }
+
     #[test]
     fn test_parse_production2() {
         let mut peekable = PeekableWrapper::<Chars>::new(
@@ -954,6 +1263,203 @@ rule2 -> \"b_terminal\"\
         assert_eq!(1, whitespace_prod.len());
     }
 
+    #[test]
+    fn test_rule_parser_unknown_directive() {
+        let to_parse = "$IGNOER: whitespace; \
+            start      -> \"a\";\
+";
+
+        let mut peekable = PeekableWrapper::from_str(to_parse);
+        let vm = NullVm::new();
+        let mut parser = RuleParser::new(&mut peekable, &vm);
+        match parser.parse_rules() {
+            Err(ParserError::UnknownSpecialOperation { operation, pos }) => {
+                assert_eq!("IGNOER", operation);
+                assert_eq!(1, pos);
+            }
+            _ => panic!("expected an unknown directive error"),
+        }
+    }
+
+    #[test]
+    fn test_parse_rules_repetitions() {
+        let to_parse = "number -> [0-9]+ \".\"? digits*;\
+            other -> [0-9]+;\
+            digits -> [0-9];";
+
+        let mut peekable = PeekableWrapper::from_str(to_parse);
+        let vm = NullVm::new();
+        let mut parser = RuleParser::new(&mut peekable, &vm);
+        parser.parse_rules().unwrap();
+        let pd = &parser.parser_data;
+
+        let number = pd.get_rule_by_element_verbose("number").unwrap();
+        let Production::NotEmpty(elements) = &*number.possible_productions[0] else {
+            panic!()
+        };
+        let names: Vec<String> = elements
+            .iter()
+            .map(|ix| pd.get_element_data(*ix).unwrap().name.clone())
+            .collect();
+        assert_eq!(
+            vec!["number/[0-9]+", "number/\".\"?", "number/digits*"],
+            names
+        );
+
+        let plus = pd.get_rule_by_element_verbose("number/[0-9]+").unwrap();
+        assert!(!plus.flatten);
+        let tail = pd.get_rule_by_element_verbose("number/[0-9]+'").unwrap();
+        assert!(tail.flatten);
+        assert_eq!(2, tail.possible_productions.len());
+        assert_eq!(2, pd.get_rule_by_element_verbose("number/\".\"?").unwrap().possible_productions.len());
+        assert!(pd.get_rule_by_element_verbose("other/[0-9]+").is_some());
+    }
+
+    #[test]
+    fn test_parse_rules_stacked_repetitions() {
+        let mut peekable = PeekableWrapper::from_str("start -> \"a\"+?;");
+        let vm = NullVm::new();
+        let mut parser = RuleParser::new(&mut peekable, &vm);
+        parser.parse_rules().unwrap();
+        let optional = parser
+            .parser_data
+            .get_rule_by_element_verbose("start/\"a\"+?")
+            .unwrap();
+        let Production::NotEmpty(elements) = &*optional.possible_productions[0] else {
+            panic!()
+        };
+        assert_eq!(
+            Some(elements[0]),
+            parser.parser_data.get_element_nt_index("start/\"a\"+")
+        );
+    }
+
+    #[test]
+    fn test_parse_rules_groups() {
+        let to_parse = "expr -> term ((\"+\" | \"-\") term)*;\
+            term -> \"x\" (\"!\" | #);\
+            expr -> \"(\" expr (\")\");";
+
+        let mut peekable = PeekableWrapper::from_str(to_parse);
+        let vm = NullVm::new();
+        let mut parser = RuleParser::new(&mut peekable, &vm);
+        parser.parse_rules().unwrap();
+        let pd = &parser.parser_data;
+
+        let outer = pd.get_rule_by_element_verbose("expr/(1)").unwrap();
+        let Production::NotEmpty(elements) = &*outer.possible_productions[0] else {
+            panic!()
+        };
+        assert_eq!(
+            vec![
+                pd.get_element_nt_index("expr/(2)").unwrap(),
+                pd.get_element_nt_index("term").unwrap()
+            ],
+            *elements
+        );
+        assert_eq!(
+            2,
+            pd.get_rule_by_element_verbose("expr/(2)")
+                .unwrap()
+                .possible_productions
+                .len()
+        );
+        assert!(pd.get_rule_by_element_verbose("expr/(1)*").is_some());
+        assert!(matches!(
+            *pd.get_rule_by_element_verbose("term/(1)").unwrap().possible_productions[1],
+            Production::Empty
+        ));
+        // the second definition of expr continues the numbering
+        assert!(pd.get_rule_by_element_verbose("expr/(3)").is_some());
+    }
+
+    #[test]
+    fn test_parse_rules_unclosed_group() {
+        let mut peekable = PeekableWrapper::from_str("start -> (\"a\" | \"b\";");
+        let vm = NullVm::new();
+        let mut parser = RuleParser::new(&mut peekable, &vm);
+        assert!(parser.parse_rules().is_err());
+    }
+
+    #[test]
+    fn test_parse_rules_comments() {
+        let to_parse = "// a calculator
+            /* the entry
+               point */ start -> // after the arrow
+                term /* between elements */ \"//\" term // trailing
+                | #; /* between rules */
+            term -> \"/*\" { // handed to the vm
+            };";
+
+        let mut peekable = PeekableWrapper::from_str(to_parse);
+        let vm = NullVm::new();
+        let mut parser = RuleParser::new(&mut peekable, &vm);
+        parser.parse_rules().unwrap();
+        let pd = &parser.parser_data;
+        let start = pd.get_rule_by_element_verbose("start").unwrap();
+        assert_eq!(2, start.possible_productions.len());
+        assert!(pd.get_element_t_index("//").is_some());
+        assert!(pd.get_element_t_index("/*").is_some());
+        assert_eq!(
+            1,
+            pd.get_rule_by_element_verbose("term").unwrap().instruction.len()
+        );
+    }
+
+    #[test]
+    fn test_rule_parser_start_directive() {
+        let to_parse = "$START: program;\
+            $START : expression , program;\
+            program -> expression;\
+            expression -> \"x\";";
+
+        let mut peekable = PeekableWrapper::from_str(to_parse);
+        let vm = NullVm::new();
+        let mut parser = RuleParser::new(&mut peekable, &vm);
+        parser.parse_rules().unwrap();
+        let pd = &parser.parser_data;
+        assert_eq!(
+            vec![
+                pd.get_element_nt_index("program").unwrap(),
+                pd.get_element_nt_index("expression").unwrap()
+            ],
+            pd.entry_points()
+        );
+    }
+
+    #[test]
+    fn test_rule_parser_transform_directive() {
+        let mut peekable =
+            PeekableWrapper::from_str("$TRANSFORM: eliminate_left_recursion;start -> \"x\";");
+        let vm = NullVm::new();
+        let mut parser = RuleParser::new(&mut peekable, &vm);
+        parser.parse_rules().unwrap();
+        assert!(parser.parser_data.parse_rules.eliminate_left_recursion);
+
+        let mut peekable = PeekableWrapper::from_str("$TRANSFORM: fold;start -> \"x\";");
+        let mut parser = RuleParser::new(&mut peekable, &vm);
+        match parser.parse_rules() {
+            Err(ParserError::UnknownSpecialOperation { operation, pos }) => {
+                assert_eq!("fold", operation);
+                assert_eq!(12, pos);
+            }
+            other => panic!("expected an unknown transform, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_rule_parser_lookahead_directive() {
+        let mut peekable = PeekableWrapper::from_str("$LOOKAHEAD: 3;start -> \"x\";");
+        let vm = NullVm::new();
+        let mut parser = RuleParser::new(&mut peekable, &vm);
+        parser.parse_rules().unwrap();
+        assert_eq!(3, parser.parser_data.parse_rules.lookahead);
+
+        let mut peekable = PeekableWrapper::from_str("$LOOKAHEAD: 0;start -> \"x\";");
+        let mut parser = RuleParser::new(&mut peekable, &vm);
+        assert!(parser.parse_rules().is_err());
+    }
+
     #[test]
     fn test_parse_rules_list() {
         let to_parse = "start      -> list;\
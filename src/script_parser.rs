@@ -3,25 +3,41 @@
 //! The Parser type orchestrates rule parsing, FIRST/FOLLOW computation, and
 //! drives a VM by turning matched productions into instructions.
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::rc::Rc;
 use std::str::Chars;
 
-use crate::parser_data::{ElementIndex, ElementType, ElementVerbose, ParserData, Production};
-use crate::tree::{NodeId, Tree};
+use crate::parser_data::{
+    ElementIndex, ElementType, ElementVerbose, GENERATED_NAME_SEPARATOR, ParserData, Production,
+    is_generated_name,
+};
+use crate::tree::{NodeId, Tree, TreeError};
 use crate::vms::VM;
 
-use crate::errors::GrammarError::MissingProduction;
-use crate::errors::ParserError;
+use crate::char_class::CharClass;
+use crate::parse_funcs::{SourcePosition, Span};
+use crate::errors::GrammarError::{MissingElementForIndex, MissingProduction};
+use crate::diagnostics::{
+    ValidationLevels, conflict_diagnostics, grammar_error_diagnostic, left_recursion_diagnostics,
+    missing_entry_point, syntax_diagnostics, validate,
+};
 use crate::errors::ParserError::{EndOfCharsError, UnexpectedCharError};
+use crate::expected::{Expected, describe};
+use crate::errors::{GrammarDiagnostic, GrammarDiagnostics, GrammarError, ParserError};
 use crate::first_sets::get_first_sets;
-use crate::follow_sets::get_follow_sets;
+use crate::follow_sets::get_follow_sets_for_entry_points;
+use crate::left_factoring::{LeftFactoring, left_factor};
+use crate::conflicts::find_conflicts;
+use crate::lookahead::{
+    MAX_TESTED_LOOKAHEAD, find_in_lookahead_table_as_prefix, find_lookahead_conflicts,
+    get_lookahead_rules, minimal_lookahead,
+};
+use crate::left_recursion::eliminate_left_recursion;
 use crate::peekables::{ParseProcess, PeekableWrapper, TPeekable};
+use crate::repair::{Edit, MAX_REPAIRS, REPAIR_WINDOW, Repair, candidate_edits, representative};
 use crate::rule_parsing::RuleParser;
-use crate::sets::SetMember;
-use crate::steuer_map::{NTRules, get_steuermaps};
-
-//TODO detect left recursive rules that lead to nonterminating of get_first_sets
+use crate::sets::{NamedSetsNoEmpty, SetMember};
+use crate::steuer_map::{NTRules, find_steuer_conflicts, get_steuermaps};
 
 /// The Parser is the core component of the library, responsible for parsing input text
 /// according to grammar rules and executing associated VM instructions.
@@ -60,6 +76,16 @@ where
     rules_with_steuermaps: HashMap<ElementIndex, NTRules<T>>,
     /// List of all grammar elements (terminals and non-terminals)
     elements: Vec<ElementVerbose>,
+    /// Parsed character classes for all class elements
+    char_classes: HashMap<ElementIndex, CharClass>,
+    /// Rules parsing can start from. The first one is used by [`Parser::parse`].
+    entry_points: Vec<ElementIndex>,
+    /// What `$TRANSFORM: left_factor` rewrote while building the parser
+    left_factorings: Vec<LeftFactoring>,
+    /// Grammar problems that did not keep the parser from being built
+    warnings: Vec<GrammarDiagnostic>,
+    /// FOLLOW sets of the rules, used to get back in sync after a syntax error
+    follow_sets: NamedSetsNoEmpty,
 }
 
 impl<'a, T> Parser<'a, T>
@@ -69,7 +95,8 @@ where
     /// Creates a new Parser from a string containing grammar rules.
     ///
     /// This method parses the rules, computes first and follow sets, and creates steuer maps
-    /// for efficient LL(1) parsing.
+    /// for efficient LL(1) parsing. The entry points are the rules declared with `$START`, or the
+    /// rule named `start` if there is no such directive.
     ///
     /// # Arguments
     ///
@@ -90,30 +117,88 @@ where
     /// let vm = NullVm::new();
     /// let parser = Parser::new_from_text(rules, &vm);
     /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the grammar has problems. Use [`Parser::try_from_text`] to get them as an error.
     pub fn new_from_text(rule_text: &str, vm: &'a T) -> Parser<'a, T> {
+        Parser::try_from_text(rule_text, vm).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Creates a new Parser from a string containing grammar rules, collecting every problem.
+    ///
+    /// Syntax errors, undefined non-terminals and a missing entry point are all reported
+    /// together. LL(1) conflicts can only be computed for a complete grammar, so they are
+    /// reported once there are no problems of the first kind left. Every diagnostic carries the
+    /// rule name and the line and column in `rule_text`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use henriks_parsing_project::script_parser::Parser;
+    /// use henriks_parsing_project::vms::NullVm;
+    ///
+    /// let rules = "start -> \"a\" b;\nc -> \"x\" | \"x\";";
+    /// let vm = NullVm::new();
+    /// let err = Parser::try_from_text(rules, &vm).err().unwrap();
+    /// // `b` is undefined, the unreachable rule `c` and its repeated alternative are warnings
+    /// assert_eq!(3, err.diagnostics.len());
+    /// assert_eq!(Some(String::from("start")), err.diagnostics[0].rule);
+    /// ```
+    pub fn try_from_text(rule_text: &str, vm: &'a T) -> Result<Parser<'a, T>, GrammarDiagnostics> {
+        Parser::try_from_text_with_levels(rule_text, vm, ValidationLevels::default())
+    }
+
+    /// Like [`Parser::try_from_text`], with the severity of the grammar checks chosen by the
+    /// caller. Findings of checks set to [`Severity::Warning`](crate::diagnostics::Severity) don't
+    /// stop the parser from being built and are kept in [`Parser::warnings`]. If the parser can't
+    /// be built, they are returned after the errors.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use henriks_parsing_project::diagnostics::{Severity, ValidationLevels};
+    /// use henriks_parsing_project::script_parser::Parser;
+    /// use henriks_parsing_project::vms::NullVm;
+    ///
+    /// let rules = "start -> \"a\";\nunused -> \"b\";";
+    /// let vm = NullVm::new();
+    /// let parser = Parser::try_from_text(rules, &vm).unwrap();
+    /// assert_eq!(1, parser.warnings().len());
+    ///
+    /// let levels = ValidationLevels {
+    ///     unreachable: Severity::Error,
+    ///     ..ValidationLevels::default()
+    /// };
+    /// assert!(Parser::try_from_text_with_levels(rules, &vm, levels).is_err());
+    /// ```
+    pub fn try_from_text_with_levels(
+        rule_text: &str,
+        vm: &'a T,
+        levels: ValidationLevels,
+    ) -> Result<Parser<'a, T>, GrammarDiagnostics> {
         let mut peekable = PeekableWrapper::from_str(rule_text);
         let mut rule_parser = RuleParser::new(&mut peekable, vm);
-        let _ = rule_parser.parse_rules().unwrap();
-        let RuleParser {
-            vm: _,
-            parse_process: _parse_process,
-            parser_data,
-        } = rule_parser;
-        let elements = parser_data.get_elements_verbose();
-        let first_dict = get_first_sets(&parser_data).unwrap();
-        let follow_dict = get_follow_sets(
-            parser_data.get_element_nt_index("start").unwrap(),
-            &first_dict,
-            &parser_data,
-        )
-        .unwrap();
-
-        let rules_with_steuermaps = get_steuermaps(&first_dict, &follow_dict, parser_data).unwrap();
-        Parser {
-            vm,
-            rules_with_steuermaps,
-            elements,
+        let syntax_errors = rule_parser.parse_rules_recovering();
+        let RuleParser { parser_data, .. } = rule_parser;
+
+        let broken: Vec<String> = syntax_errors
+            .iter()
+            .filter_map(|(rule, _)| rule.clone())
+            .collect();
+        let mut diagnostics = syntax_diagnostics(rule_text, syntax_errors);
+        let validation = validate(&parser_data, Some(rule_text), &broken, levels);
+        diagnostics.extend(validation.errors);
+        let entry_points = parser_data.entry_points();
+        if entry_points.is_empty() {
+            diagnostics.push(missing_entry_point());
         }
+        let result = if diagnostics.is_empty() {
+            Parser::build(parser_data, entry_points, Some(rule_text), vm)
+        } else {
+            Err(GrammarDiagnostics { diagnostics })
+        };
+        Parser::with_warnings(result, validation.warnings)
     }
 
     /// Creates a new Parser from pre-parsed ParserData.
@@ -124,30 +209,155 @@ where
     /// # Arguments
     ///
     /// * `parser_data` - The pre-parsed grammar data
-    /// * `start_idx` - The index of the starting non-terminal
+    /// * `start_idx` - The index of the starting non-terminal. Entry points declared in the
+    ///   parser data stay usable with [`Parser::parse_from`].
     /// * `vm` - A reference to a virtual machine that implements the VM trait
     ///
     /// # Returns
     ///
     /// A new Parser instance configured with the provided parser data and VM
+    ///
+    /// # Panics
+    ///
+    /// Panics if the grammar has problems. Use [`Parser::try_from_parser_data`] to get them as
+    /// an error.
     pub fn new_from_parser_data(
         parser_data: ParserData<T>,
         start_idx: ElementIndex,
         vm: &'a T,
     ) -> Parser<'a, T> {
-        let first_dict = get_first_sets(&parser_data).unwrap();
-        let follow_dict = get_follow_sets(start_idx, &first_dict, &parser_data).unwrap();
+        Parser::try_from_parser_data(parser_data, start_idx, vm)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Like [`Parser::new_from_parser_data`], but returns the grammar's problems instead of
+    /// panicking. The diagnostics have no line and column since there is no grammar text.
+    pub fn try_from_parser_data(
+        parser_data: ParserData<T>,
+        start_idx: ElementIndex,
+        vm: &'a T,
+    ) -> Result<Parser<'a, T>, GrammarDiagnostics> {
+        let mut entry_points = vec![start_idx];
+        entry_points.extend(
+            parser_data
+                .entry_points()
+                .into_iter()
+                .filter(|entry_point| *entry_point != start_idx),
+        );
+        let validation = validate(&parser_data, None, &[], ValidationLevels::default());
+        let result = if validation.errors.is_empty() {
+            Parser::build(parser_data, entry_points, None, vm)
+        } else {
+            Err(GrammarDiagnostics {
+                diagnostics: validation.errors,
+            })
+        };
+        Parser::with_warnings(result, validation.warnings)
+    }
 
-        let elements_verbose = parser_data.get_elements_verbose();
-        let rules_with_steuermaps = get_steuermaps(&first_dict, &follow_dict, parser_data).unwrap();
+    /// Keeps the findings reported as warnings in the parser, or adds them to the problems that
+    /// stopped it from being built.
+    fn with_warnings(
+        result: Result<Parser<'a, T>, GrammarDiagnostics>,
+        warnings: Vec<GrammarDiagnostic>,
+    ) -> Result<Parser<'a, T>, GrammarDiagnostics> {
+        match result {
+            Ok(mut parser) => {
+                parser.warnings = warnings;
+                Ok(parser)
+            }
+            Err(mut err) => {
+                err.diagnostics.extend(warnings);
+                Err(err)
+            }
+        }
+    }
 
-        let parser = Parser::new(rules_with_steuermaps, elements_verbose, &vm);
-        parser
+    /// Runs the LL(1) analysis of a complete grammar and builds the parser from it.
+    fn build(
+        mut parser_data: ParserData<T>,
+        entry_points: Vec<ElementIndex>,
+        rule_text: Option<&str>,
+        vm: &'a T,
+    ) -> Result<Parser<'a, T>, GrammarDiagnostics> {
+        let single = |err: GrammarError, parser_data: &ParserData<T>| GrammarDiagnostics {
+            diagnostics: vec![grammar_error_diagnostic(parser_data, &err, rule_text)],
+        };
+        let left_factorings = apply_transforms(&mut parser_data, &entry_points);
+        let left_recursion = left_recursion_diagnostics(&parser_data, rule_text);
+        if !left_recursion.is_empty() {
+            return Err(GrammarDiagnostics {
+                diagnostics: left_recursion,
+            });
+        }
+        let k = parser_data.parse_rules.lookahead;
+        let (conflicts, report) = if k > 1 {
+            let conflicts = find_lookahead_conflicts(&parser_data, &entry_points, k)
+                .map_err(|err| single(err, &parser_data))?;
+            (conflicts, vec![])
+        } else {
+            let first_dict =
+                get_first_sets(&parser_data).map_err(|err| single(err, &parser_data))?;
+            let follow_dict =
+                get_follow_sets_for_entry_points(&entry_points, &first_dict, &parser_data)
+                    .map_err(|err| single(err, &parser_data))?;
+            let conflicts = find_steuer_conflicts(&first_dict, &follow_dict, &parser_data)
+                .map_err(|err| single(err, &parser_data))?;
+            let report = if conflicts.is_empty() {
+                vec![]
+            } else {
+                find_conflicts(&first_dict, &follow_dict, &parser_data, &entry_points)
+                    .map_err(|err| single(err, &parser_data))?
+            };
+            (conflicts, report)
+        };
+        if !conflicts.is_empty() {
+            let max_k = k.max(MAX_TESTED_LOOKAHEAD);
+            let rules: Vec<ElementIndex> = conflicts.iter().map(|(rule, _)| *rule).collect();
+            let (needed, tested_k) =
+                minimal_lookahead(&parser_data, &entry_points, &rules, max_k)
+                    .map_err(|err| single(err, &parser_data))?;
+            return Err(GrammarDiagnostics {
+                diagnostics: conflict_diagnostics(
+                    &parser_data,
+                    conflicts,
+                    &report,
+                    &needed,
+                    tested_k,
+                    rule_text,
+                ),
+            });
+        }
+
+        let elements = parser_data.get_elements_verbose();
+        let rules = get_first_sets(&parser_data).and_then(|first_dict| {
+            let follow_dict =
+                get_follow_sets_for_entry_points(&entry_points, &first_dict, &parser_data)?;
+            let rules = if k > 1 {
+                get_lookahead_rules(parser_data, &entry_points, k)?
+            } else {
+                get_steuermaps(&first_dict, &follow_dict, parser_data)?
+            };
+            Ok((rules, follow_dict))
+        });
+        let (rules_with_steuermaps, follow_sets) = rules.map_err(|err| GrammarDiagnostics {
+            diagnostics: vec![GrammarDiagnostic {
+                message: err.to_string(),
+                rule: None,
+                location: None,
+            }],
+        })?;
+        let mut parser = Parser::new(rules_with_steuermaps, elements, vm);
+        parser.entry_points = entry_points;
+        parser.left_factorings = left_factorings;
+        parser.follow_sets = follow_sets;
+        Ok(parser)
     }
 
     /// Creates a new Parser from pre-computed components.
     ///
     /// This is a low-level constructor that takes already computed steuer maps and elements.
+    /// The rule named `start` is the only entry point.
     ///
     /// # Arguments
     ///
@@ -163,17 +373,53 @@ where
         elements: Vec<ElementVerbose>,
         vm: &'a T,
     ) -> Parser<'a, T> {
+        let char_classes = elements
+            .iter()
+            .enumerate()
+            .filter(|(_, element)| element.et == ElementType::CharClass)
+            .filter_map(|(ix, element)| {
+                CharClass::from_source(&element.name)
+                    .ok()
+                    .map(|class| (ix, class))
+            })
+            .collect();
+        let entry_points = elements
+            .iter()
+            .position(|element| {
+                *element == ElementVerbose::new(String::from("start"), ElementType::NonTerminal)
+            })
+            .into_iter()
+            .collect();
         Parser {
             vm,
             rules_with_steuermaps,
             elements,
+            char_classes,
+            entry_points,
+            left_factorings: vec![],
+            warnings: vec![],
+            follow_sets: HashMap::new(),
         }
     }
 
+    /// The rewrites done by `$TRANSFORM: left_factor` while the parser was built. Empty if the
+    /// grammar did not opt in.
+    pub fn left_factorings(&self) -> &[LeftFactoring] {
+        &self.left_factorings
+    }
+
+    /// The findings of the grammar checks that were reported as warnings, like rules that can't
+    /// be reached from an entry point.
+    pub fn warnings(&self) -> &[GrammarDiagnostic] {
+        &self.warnings
+    }
+
     /// Parses a string according to the grammar rules and executes VM instructions.
     ///
     /// This method takes a string to parse and a mutable reference to a VM state,
-    /// parses the string according to the grammar rules, and returns a parse tree.
+    /// parses the string starting from the default entry point, and returns a parse tree.
+    /// The whole string has to match: input left after the start rule is a
+    /// `ParserError::TrailingInputError`. Use [`Parser::parse_prefix`] to parse only the start.
     ///
     /// # Arguments
     ///
@@ -188,7 +434,7 @@ where
     ///
     /// ```
     /// use henriks_parsing_project::script_parser::Parser;
-    /// use henriks_parsing_project::vms::NullVm;
+    /// use henriks_parsing_project::vms::{NullVm, VM};
     ///
     /// let rules = "start -> \"hello\" \"world\";";
     /// let vm = NullVm::new();
@@ -196,83 +442,416 @@ where
     /// let mut parser = Parser::new_from_text(rules, &vm);
     ///
     /// let result = parser.parse("helloworld", &mut state);
+    /// assert!(result.is_ok());
     /// ```
     pub fn parse(
         &mut self,
         to_parse: &'a str,
         state: &mut T::Tstate,
     ) -> Result<Tree<String>, ParserError> {
-        let mut peekable = PeekableWrapper::<Chars>::new(to_parse.chars().peekable());
-        let mut to_parse = ParseProcess::<PeekableWrapper<Chars>>::new(&mut peekable, None, None);
+        let start_index = *self
+            .entry_points
+            .first()
+            .ok_or_else(|| ParserError::UnknownEntryPoint {
+                name: String::from("start"),
+            })?;
+        let mut run = ParseRun::new(to_parse, start_index);
+        let (tree, consumed) = self.parse_entry_point(&mut run, state)?;
+        require_end(to_parse, consumed)?;
+        Ok(tree)
+    }
+
+    /// Parses a string up to where the start rule ends and returns the tree together with the
+    /// number of chars consumed, which includes ignored input after the start rule. Wherever the
+    /// input could end, a char that doesn't continue the parse is treated as its end and left
+    /// alone, so this is the mode for fragments embedded in other text.
+    ///
+    /// The parse doesn't back off to an earlier point where the input could have ended. Once the
+    /// next char starts another repetition or alternative, that one has to be completed, so the
+    /// text after the fragment must not start one. With `start -> item*;` and
+    /// `item -> [a-z] ";";`, `"a;b"` fails at its end instead of returning the two chars of `a;`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use henriks_parsing_project::script_parser::Parser;
+    /// use henriks_parsing_project::vms::{NullVm, VM};
+    ///
+    /// let rules = "start -> \"hello\" \"world\";";
+    /// let vm = NullVm::new();
+    /// let mut state = NullVm::create_new_state();
+    /// let mut parser = Parser::new_from_text(rules, &vm);
+    ///
+    /// assert!(parser.parse("helloworld!", &mut state).is_err());
+    /// let (_tree, consumed) = parser.parse_prefix("helloworld!", &mut state).unwrap();
+    /// assert_eq!(10, consumed);
+    /// ```
+    pub fn parse_prefix(
+        &mut self,
+        to_parse: &'a str,
+        state: &mut T::Tstate,
+    ) -> Result<(Tree<String>, usize), ParserError> {
+        let start_index = *self
+            .entry_points
+            .first()
+            .ok_or_else(|| ParserError::UnknownEntryPoint {
+                name: String::from("start"),
+            })?;
+        let mut run = ParseRun::new(to_parse, start_index);
+        run.prefix = true;
+        self.parse_entry_point(&mut run, state)
+    }
+
+    /// Parses a string starting from the entry point `rule_name` instead of the default one.
+    ///
+    /// The rule must be declared with `$START` (or be the start index given to
+    /// [`Parser::new_from_parser_data`]), otherwise `ParserError::UnknownEntryPoint` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use henriks_parsing_project::script_parser::Parser;
+    /// use henriks_parsing_project::vms::{NullVm, VM};
+    ///
+    /// let rules = "$START: file, expression; file -> expression \";\"; expression -> \"x\";";
+    /// let vm = NullVm::new();
+    /// let mut state = NullVm::create_new_state();
+    /// let mut parser = Parser::new_from_text(rules, &vm);
+    ///
+    /// assert!(parser.parse("x;", &mut state).is_ok());
+    /// assert!(parser.parse_from("expression", "x", &mut state).is_ok());
+    /// ```
+    pub fn parse_from(
+        &mut self,
+        rule_name: &str,
+        to_parse: &'a str,
+        state: &mut T::Tstate,
+    ) -> Result<Tree<String>, ParserError> {
+        let entry_point = ElementVerbose::new(String::from(rule_name), ElementType::NonTerminal);
         let start_index = self
-            .elements
+            .entry_points
             .iter()
-            .position(|r| {
-                *r == ElementVerbose::new(String::from("start"), ElementType::NonTerminal)
-            })
-            .unwrap();
+            .copied()
+            .find(|ix| self.elements.get(*ix) == Some(&entry_point))
+            .ok_or_else(|| ParserError::UnknownEntryPoint {
+                name: String::from(rule_name),
+            })?;
+        let mut run = ParseRun::new(to_parse, start_index);
+        let (tree, consumed) = self.parse_entry_point(&mut run, state)?;
+        require_end(to_parse, consumed)?;
+        Ok(tree)
+    }
+
+    /// Like [`Parser::parse`], but doesn't stop at the first syntax error.
+    ///
+    /// After an error the rule it occurred in is cut short: the input is skipped up to a char in
+    /// the rule's FOLLOW set, the skipped input is added to the rule's node as an error node (see
+    /// [`Node::is_error`](crate::tree::Node::is_error)) and parsing goes on after the rule. If a
+    /// char that starts the rule comes first, the rule is parsed again from there instead. Input
+    /// left after the start rule becomes an error node of the root. The instructions of rules
+    /// that were cut short are not run. Errors at the position the input got back in sync are
+    /// taken for consequences of the previous error and not reported.
+    ///
+    /// Only unexpected chars and a premature end of the input are recovered from, other errors
+    /// are returned as `Err`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use henriks_parsing_project::script_parser::Parser;
+    /// use henriks_parsing_project::vms::{NullVm, VM};
+    ///
+    /// let rules = "start -> item*; item -> [a-z] \";\";";
+    /// let vm = NullVm::new();
+    /// let mut state = NullVm::create_new_state();
+    /// let mut parser = Parser::new_from_text(rules, &vm);
+    ///
+    /// let parsed = parser.parse_recovering("a;1;b2;c;", &mut state).unwrap();
+    /// assert_eq!(2, parsed.errors.len());
+    /// assert_eq!(Some(2), parsed.errors[0].pos());
+    /// ```
+    pub fn parse_recovering(
+        &mut self,
+        to_parse: &'a str,
+        state: &mut T::Tstate,
+    ) -> Result<RecoveredParse, ParserError> {
+        let start_index = *self
+            .entry_points
+            .first()
+            .ok_or_else(|| ParserError::UnknownEntryPoint {
+                name: String::from("start"),
+            })?;
+        let mut run = ParseRun::new(to_parse, start_index);
+        run.recovery = Some(Recovery::default());
+        let (tree, _) = self.parse_entry_point(&mut run, state)?;
+        Ok(RecoveredParse {
+            tree,
+            errors: run.recovery.map(|recovery| recovery.errors).unwrap_or_default(),
+        })
+    }
+
+    /// Parses the input of `run` and returns the tree with the number of chars consumed. A
+    /// syntax error that stops the parse gets its rules and repairs filled in.
+    fn parse_entry_point(
+        &self,
+        run: &mut ParseRun<'_>,
+        state: &mut T::Tstate,
+    ) -> Result<(Tree<String>, usize), ParserError> {
+        self.parse_input(run, state).map_err(|mut err| {
+            self.complete_error(run, &mut err);
+            err
+        })
+    }
+
+    fn parse_input(
+        &self,
+        run: &mut ParseRun<'_>,
+        state: &mut T::Tstate,
+    ) -> Result<(Tree<String>, usize), ParserError> {
+        let start_index = run.start;
+        let mut peekable = PeekableWrapper::<Chars>::new(run.input.chars().peekable());
+        let mut to_parse = ParseProcess::<PeekableWrapper<Chars>>::new(&mut peekable, None, None);
         let mut tree = Tree::new();
 
-        self.parse_production(&mut to_parse, start_index, state, &mut tree, None)?;
-        Ok(tree)
+        let ignore = self
+            .rules_with_steuermaps
+            .get(&start_index)
+            .and_then(|rule| rule.ignore);
+        if let Some(ignore) = ignore {
+            self.skip_ignored(&mut to_parse, ignore, state, run)?;
+        }
+        self.parse_production(&mut to_parse, start_index, state, &mut tree, None, run)?;
+        if let Some(ignore) = ignore {
+            self.skip_ignored(&mut to_parse, ignore, state, run)?;
+        }
+        if let Some(recovery) = &mut run.recovery
+            && let Some(&chr) = to_parse.peek()
+        {
+            let pos = to_parse.cur_pos();
+            recovery.report(ParserError::TrailingInputError { chr, pos });
+            let root = NodeId::new(0, 0);
+            skip_to_sync(&mut to_parse, &mut tree, root, |_| false)?;
+            recovery.synced_at = Some(to_parse.cur_pos());
+        }
+        Ok((tree, to_parse.cur_pos()))
+    }
+
+    /// Parses the ignore rule if the next char can start it. Used around the start symbol.
+    fn skip_ignored(
+        &self,
+        to_parse: &mut ParseProcess<PeekableWrapper<Chars<'_>>>,
+        ignore: ElementIndex,
+        state: &mut T::Tstate,
+        run: &mut ParseRun<'_>,
+    ) -> Result<(), ParserError> {
+        let ignore_rule = self
+            .rules_with_steuermaps
+            .get(&ignore)
+            .ok_or(MissingProduction { index: ignore })?;
+        if ignore_rule.find_production(to_parse).is_some() {
+            self.parse_ignored(to_parse, ignore, state, run)?;
+        }
+        Ok(())
+    }
+
+    /// Parses the ignore rule into a throwaway tree so ignored input leaves no nodes behind.
+    fn parse_ignored(
+        &self,
+        to_parse: &mut ParseProcess<PeekableWrapper<Chars<'_>>>,
+        ignore: ElementIndex,
+        state: &mut T::Tstate,
+        run: &mut ParseRun<'_>,
+    ) -> Result<(), ParserError> {
+        let mut ignored_tree = Tree::new();
+        self.parse_production(to_parse, ignore, state, &mut ignored_tree, None, run)
     }
 
-    fn get_fitting_production(
+    /// The production of `nt_rule` for the upcoming input. In `prefix` mode the production taken
+    /// at the end of the input also fits wherever no other one does, if the rule can end there.
+    fn choose_production<'r, P>(
+        to_parse: &mut P,
+        nt_rule: &'r NTRules<T>,
+        prefix: bool,
+    ) -> Option<&'r Rc<Production>>
+    where
+        P: TPeekable<Item = char>,
+    {
+        nt_rule.find_production(to_parse).or_else(|| {
+            if !prefix {
+                return None;
+            }
+            nt_rule
+                .steuermap
+                .get(&SetMember::Terminate)
+                .or_else(|| find_in_lookahead_table_as_prefix(&nt_rule.lookahead, to_parse))
+        })
+    }
+
+    fn get_fitting_production<'r>(
+        &self,
+        to_parse: &mut ParseProcess<PeekableWrapper<Chars<'_>>>,
+        el_index: ElementIndex,
+        nt_rule: &'r NTRules<T>,
+        prefix: bool,
+    ) -> Result<&'r Rc<Production>, ParserError> {
+        if let Some(fp) = Self::choose_production(to_parse, nt_rule, prefix) {
+            return Ok(fp);
+        }
+        // the error goes where the input stopped fitting, which can be some chars ahead
+        let (ahead, next) = nt_rule.mismatch(to_parse);
+        let pos = to_parse.cur_pos() + ahead;
+        let expected = if ahead == 0 {
+            self.expected_items(el_index, nt_rule)
+        } else {
+            next.into_iter().map(Expected::from).collect()
+        };
+        match to_parse.peek_nth(ahead) {
+            None if ahead > 0 => Err(EndOfCharsError {
+                pos,
+                rules: vec![],
+                repairs: vec![],
+            }),
+            chr => Err(UnexpectedCharError {
+                chr: *chr.unwrap_or(&'#'),
+                pos,
+                expected: describe(&expected),
+                rules: vec![],
+                repairs: vec![],
+            }),
+        }
+    }
+    fn parse_production(
         &self,
-        to_parse: &mut ParseProcess<PeekableWrapper<Chars<'a>>>,
+        to_parse: &mut ParseProcess<PeekableWrapper<Chars<'_>>>,
         el_index: ElementIndex,
-        cur: &SetMember,
-    ) -> Result<&Rc<Production>, ParserError> {
+        state: &mut T::Tstate,
+        tree: &mut Tree<String>,
+        current_node: Option<NodeId>,
+        run: &mut ParseRun<'_>,
+    ) -> Result<(), ParserError> {
         let nt_rule = self
             .rules_with_steuermaps
             .get(&el_index)
             .ok_or(MissingProduction { index: el_index })?;
-        let fitting_production: Option<&Rc<Production>> = nt_rule.steuermap.get(cur);
-        match fitting_production {
-            None => Err(UnexpectedCharError {
-                chr: *to_parse.peek().unwrap_or(&'#'),
-                pos: to_parse.cur_pos(),
-                expected: nt_rule
-                    .steuermap
-                    .keys()
-                    .cloned()
-                    .map(|x| x.into())
-                    .collect::<Vec<String>>()
-                    .join(";"),
-            }),
-            Some(fp) => Ok(fp),
+
+        let start = to_parse.source_position();
+        let (id, own_node) = match current_node {
+            Some(parent) if nt_rule.flatten => (parent, false),
+            _ => (tree.add_node(String::from(""), current_node)?, true),
+        };
+
+        let depth = run.rule_stack.len();
+        run.rule_stack.push(ActiveRule {
+            rule: el_index,
+            production: None,
+            element: 0,
+            element_start: to_parse.cur_pos(),
+        });
+        let mut attempt_start = to_parse.cur_pos();
+        let mut parsed = self.parse_elements(to_parse, el_index, state, tree, id, run);
+        let mut cut_short = false;
+        while let Err(mut err) = parsed {
+            let recoverable = matches!(err, UnexpectedCharError { .. } | EndOfCharsError { .. });
+            let follow_up = match &run.recovery {
+                Some(recovery) if recoverable => recovery.is_follow_up(&err),
+                _ => return Err(err),
+            };
+            if !follow_up {
+                self.complete_error(run, &mut err);
+            }
+            run.rule_stack.truncate(depth + 1);
+            cut_short = true;
+            // In sync again at a char that can follow the rule, or one that starts it again if
+            // that is not where the failed attempt started.
+            skip_to_sync(to_parse, tree, id, |to_parse| match to_parse.peek() {
+                Some(&chr) if self.can_follow(el_index, chr) => true,
+                Some(_) => {
+                    to_parse.cur_pos() > attempt_start
+                        && nt_rule.find_production(to_parse).is_some()
+                }
+                None => true,
+            })?;
+            if let Some(recovery) = &mut run.recovery {
+                if !follow_up {
+                    recovery.errors.push(err);
+                }
+                recovery.synced_at = Some(to_parse.cur_pos());
+            }
+            let restart = to_parse
+                .peek()
+                .copied()
+                .is_some_and(|chr| !self.can_follow(el_index, chr));
+            if !restart {
+                break;
+            }
+            attempt_start = to_parse.cur_pos();
+            parsed = self.parse_elements(to_parse, el_index, state, tree, id, run);
         }
+        run.rule_stack.truncate(depth);
+        if own_node {
+            let end = to_parse.source_position();
+            tree.set_span(id, Span { start, end })?;
+        }
+
+        if !cut_short {
+            let instructions = nt_rule
+                .instructions_of
+                .and_then(|rule| self.rules_with_steuermaps.get(&rule))
+                .map_or(&nt_rule.instruction, |rule| &rule.instruction);
+            self.run_instructions(tree, id, instructions, state);
+        }
+
+        Ok(())
     }
-    fn parse_production(
+
+    /// Chooses the production of the rule `el_index` and parses its elements as children of `id`.
+    fn parse_elements(
         &self,
-        to_parse: &mut ParseProcess<PeekableWrapper<Chars<'a>>>,
+        to_parse: &mut ParseProcess<PeekableWrapper<Chars<'_>>>,
         el_index: ElementIndex,
         state: &mut T::Tstate,
         tree: &mut Tree<String>,
-        current_node: Option<NodeId>,
+        id: NodeId,
+        run: &mut ParseRun<'_>,
     ) -> Result<(), ParserError> {
-        let cur = SetMember::from(to_parse.peek());
         let nt_rule = self
             .rules_with_steuermaps
             .get(&el_index)
             .ok_or(MissingProduction { index: el_index })?;
+        run.choosing(to_parse.cur_pos());
         let fitting_production: &Rc<Production> =
-            self.get_fitting_production(to_parse, el_index, &cur)?;
-
-        let id = tree.add_node(String::from(""), current_node)?;
+            self.get_fitting_production(to_parse, el_index, nt_rule, run.prefix)?;
+        run.chose(fitting_production);
 
         let prod = &**fitting_production;
         match prod {
             Production::NotEmpty(prod_not_empty) => {
-                for next_element_index in prod_not_empty {
+                for (element, next_element_index) in prod_not_empty.iter().enumerate() {
+                    run.at_element(element, to_parse.cur_pos());
                     let element_next = self.elements.get(*next_element_index).unwrap().clone();
 
                     match element_next.et {
                         ElementType::Terminal => {
-                            let _ = tree.add_node(
-                                self.parse_terminal(to_parse, element_next.name.as_str())?,
-                                Some(id),
-                            );
+                            let start = to_parse.source_position();
+                            let terminal =
+                                self.parse_terminal(to_parse, element_next.name.as_str())?;
+                            let span = Span {
+                                start,
+                                end: to_parse.source_position(),
+                            };
+                            let _ = tree.add_node_with_span(terminal, Some(id), span);
+                        }
+                        ElementType::CharClass => {
+                            let start = to_parse.source_position();
+                            let chr = self.parse_char_class(to_parse, *next_element_index)?;
+                            let span = Span {
+                                start,
+                                end: to_parse.source_position(),
+                            };
+                            let _ = tree.add_node_with_span(chr, Some(id), span);
+                        }
+                        ElementType::NonTerminal if nt_rule.ignore == Some(*next_element_index) => {
+                            self.parse_ignored(to_parse, *next_element_index, state, run)?;
                         }
                         ElementType::NonTerminal => {
                             self.parse_production(
@@ -281,6 +860,7 @@ where
                                 state,
                                 tree,
                                 Some(id),
+                                run,
                             )?;
                         }
                     }
@@ -288,59 +868,626 @@ where
             }
             Production::Empty => {}
         };
+        Ok(())
+    }
 
-        self.run_instructions(tree, id, &nt_rule.instruction, state);
+    /// Fills in the rules and repairs of a syntax error, see [`ParserError::rules`] and
+    /// [`ParserError::repairs`].
+    fn complete_error(&self, run: &ParseRun<'_>, err: &mut ParserError) {
+        let (UnexpectedCharError {
+            pos,
+            rules,
+            repairs,
+            ..
+        }
+        | EndOfCharsError {
+            pos,
+            rules,
+            repairs,
+        }) = err
+        else {
+            return;
+        };
+        *rules = self.rule_names(&run.rule_stack);
+        // a rule that found no production is repaired where it starts, not where the input
+        // stopped fitting it
+        let repair_pos = match run.rule_stack.last() {
+            Some(active) if active.production.is_none() => active.element_start,
+            _ => *pos,
+        };
+        *repairs = self.find_repairs(run, repair_pos);
+    }
 
-        Ok(())
+    /// The names of the rules on the stack, innermost first. Generated rules go by the rule
+    /// they were generated for, so a rule is named once for all of its helpers.
+    fn rule_names(&self, rule_stack: &[ActiveRule]) -> Vec<String> {
+        let mut names: Vec<String> = vec![];
+        for active in rule_stack.iter().rev() {
+            let Some(element) = self.elements.get(active.rule) else {
+                continue;
+            };
+            let name = element.name.split(GENERATED_NAME_SEPARATOR).next();
+            let name = name.unwrap_or(&element.name);
+            if names.last().is_none_or(|last| last != name) {
+                names.push(String::from(name));
+            }
+        }
+        names
     }
 
-    fn run_instructions(
-        &self,
-        tree: &mut Tree<String>,
-        cur_node: NodeId,
-        instructions: &Vec<<T as VM>::Tinstrution>,
-        state: &mut T::Tstate,
-    ) {
-        for instruction in instructions {
-            self.vm
-                .execute_instruction(tree, cur_node, &instruction, state);
+    /// What could have come where no production of the rule `el_index` fits: what its
+    /// productions start with and, if it can be empty, the chars that can follow it.
+    fn expected_items(&self, el_index: ElementIndex, nt_rule: &NTRules<T>) -> Vec<Expected> {
+        let mut items = vec![];
+        let mut can_be_empty = false;
+        for production in nt_rule.productions() {
+            can_be_empty |= !self.add_first_items(production, nt_rule.ignore, &mut items);
+        }
+        if can_be_empty {
+            let follow = self.follow_sets.get(&el_index).into_iter().flatten();
+            items.extend(follow.copied().map(Expected::from));
         }
+        items
     }
 
-    fn parse_terminal(
+    /// Adds the terminals, rules and char classes `production` can start with to `items`.
+    /// Generated rules are looked into, the `ignore` rule is skipped. Returns false if the
+    /// production can be empty.
+    fn add_first_items(
         &self,
-        to_parse: &mut ParseProcess<PeekableWrapper<Chars<'a>>>,
-        terminal: &str,
-    ) -> Result<String, ParserError> {
-        for chr in terminal.chars() {
-            let pos = to_parse.cur_pos();
-            let char_to_parse: &char = to_parse.peek().ok_or(EndOfCharsError { pos })?;
-            if *char_to_parse != chr {
-                return Err(UnexpectedCharError {
-                    chr: *char_to_parse,
-                    pos: to_parse.cur_pos(),
-                    expected: String::from(chr),
-                });
+        production: &Production,
+        ignore: Option<ElementIndex>,
+        items: &mut Vec<Expected>,
+    ) -> bool {
+        let Production::NotEmpty(el_ixs) = production else {
+            return false;
+        };
+        for &ix in el_ixs {
+            let Some(element) = self.elements.get(ix).filter(|_| ignore != Some(ix)) else {
+                continue;
+            };
+            match element.et {
+                ElementType::Terminal if element.name.is_empty() => {}
+                ElementType::Terminal => {
+                    items.push(Expected::Terminal(element.name.clone()));
+                    return true;
+                }
+                ElementType::CharClass => {
+                    if let Some(class) = self.char_classes.get(&ix) {
+                        items.push(Expected::Chars(class.ranges().to_vec()));
+                    }
+                    return true;
+                }
+                ElementType::NonTerminal => {
+                    let Some(rule) = self.rules_with_steuermaps.get(&ix) else {
+                        continue;
+                    };
+                    if is_generated_name(&element.name) {
+                        let mut not_empty = true;
+                        for production in rule.productions() {
+                            not_empty &= self.add_first_items(production, rule.ignore, items);
+                        }
+                        if not_empty {
+                            return true;
+                        }
+                    } else {
+                        items.push(Expected::Rule(element.name.clone()));
+                        if !self.can_be_empty(ix) {
+                            return true;
+                        }
+                    }
+                }
             }
-            to_parse.next();
         }
-        Ok(String::from(terminal))
+        false
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use crate::script_parser::Parser;
-    use crate::vms::counting_vm::CountingVm;
-    use crate::vms::{NullVm, VM};
 
-    use crate::errors::ParserError;
-    use crate::tree::NodeId;
+    /// True if the rule `el_index` has a production that can be empty.
+    fn can_be_empty(&self, el_index: ElementIndex) -> bool {
+        let Some(rule) = self.rules_with_steuermaps.get(&el_index) else {
+            return false;
+        };
+        rule.productions()
+            .into_iter()
+            .any(|production| match &**production {
+                Production::Empty => true,
+                Production::NotEmpty(el_ixs) => el_ixs.iter().all(|&ix| {
+                    match self.elements.get(ix).map(|element| &element.et) {
+                        Some(ElementType::NonTerminal) => self.can_be_empty(ix),
+                        Some(ElementType::Terminal) => self.elements[ix].name.is_empty(),
+                        _ => false,
+                    }
+                }),
+            })
+    }
 
-    #[test]
-    fn test_script_parser() {
-        let rules = "start      -> identifier1 identifier2;\
-            identifier1 -> \"a_terminal\"| #;
+    /// Tries the single char edits at `pos` and returns the ones the parser gets furthest with.
+    ///
+    /// The parse isn't repeated for this. Each edit is checked by going on from the error with
+    /// what the rules on the stack still have to parse, over at most [`REPAIR_WINDOW`] chars of
+    /// the edited input. It gets the parser past the error if the char after the edit is read.
+    fn find_repairs(&self, run: &ParseRun<'_>, pos: usize) -> Vec<Repair> {
+        let position = SourcePosition::of(run.input, pos);
+        let rest = &run.input[position.byte_offset..];
+        let pending = self.pending_at(run, pos);
+        let mut candidates = BTreeSet::new();
+        if let Some(next) = pending.last() {
+            self.add_first_chars(next, &mut candidates);
+        }
+        let mut repairs = vec![];
+        for edit in candidate_edits(&candidates, rest.chars().next()) {
+            let repair = Repair { position, edit };
+            let window: String = rest.chars().take(REPAIR_WINDOW + 1).collect();
+            let edited = Repair {
+                position: SourcePosition::of(&window, 0),
+                edit,
+            }
+            .apply(&window);
+            let after_edit = match edit {
+                Edit::Delete(_) => 0,
+                Edit::Insert(_) | Edit::Replace { .. } => 1,
+            };
+            let is_end = window.len() == rest.len();
+            let reached = self.chars_read(pending.clone(), &edited, is_end, run.prefix);
+            if reached > after_edit {
+                repairs.push((repair, reached));
+            }
+        }
+        let furthest = repairs.iter().map(|(_, reached)| *reached).max();
+        repairs
+            .into_iter()
+            .filter(|(_, reached)| Some(*reached) == furthest)
+            .take(MAX_REPAIRS)
+            .map(|(repair, _)| repair)
+            .collect()
+    }
+
+    /// What is left to parse when the parse stopped at `pos` with the rules of the run's stack,
+    /// the next one last.
+    fn pending_at(&self, run: &ParseRun<'_>, pos: usize) -> Vec<Pending> {
+        let mut pending = vec![];
+        if run
+            .rule_stack
+            .first()
+            .is_some_and(|active| active.rule == run.start)
+        {
+            let ignore = self
+                .rules_with_steuermaps
+                .get(&run.start)
+                .and_then(|rule| rule.ignore);
+            pending.extend(ignore.map(Pending::Element));
+        }
+        let innermost = run.rule_stack.len().saturating_sub(1);
+        for (depth, active) in run.rule_stack.iter().enumerate() {
+            let Some(production) = &active.production else {
+                pending.push(Pending::Element(active.rule));
+                continue;
+            };
+            let Production::NotEmpty(el_ixs) = &**production else {
+                continue;
+            };
+            pending.extend(
+                el_ixs[active.element + 1..]
+                    .iter()
+                    .rev()
+                    .map(|&ix| Pending::Element(ix)),
+            );
+            if depth < innermost {
+                continue;
+            }
+            let current = el_ixs[active.element];
+            let read = pos - active.element_start;
+            match self.elements.get(current) {
+                Some(element) if element.et == ElementType::Terminal && read > 0 => {
+                    pending.push(Pending::Text(element.name.chars().skip(read).collect()));
+                }
+                _ => pending.push(Pending::Element(current)),
+            }
+        }
+        pending
+    }
+
+    /// Adds the chars `next` can start with to `chars`, one per range of a char class. For a rule
+    /// these are the keys of its steuer map and so include what can follow it if it can be empty.
+    fn add_first_chars(&self, next: &Pending, chars: &mut BTreeSet<char>) {
+        let el_index = match next {
+            Pending::Text(text) => {
+                chars.extend(text.chars().next());
+                return;
+            }
+            Pending::Element(el_index) => *el_index,
+        };
+        match self.elements.get(el_index).map(|element| &element.et) {
+            Some(ElementType::Terminal) => {
+                chars.extend(self.elements[el_index].name.chars().next());
+            }
+            Some(ElementType::CharClass) => {
+                let ranges = self.char_classes.get(&el_index).map(CharClass::ranges);
+                chars.extend(
+                    ranges
+                        .into_iter()
+                        .flatten()
+                        .map(|&(lo, hi)| representative(lo, hi)),
+                );
+            }
+            Some(ElementType::NonTerminal) => {
+                let Some(nt_rule) = self.rules_with_steuermaps.get(&el_index) else {
+                    return;
+                };
+                let lookaheads = nt_rule.steuermap.keys().chain(
+                    nt_rule
+                        .lookahead
+                        .iter()
+                        .filter_map(|(lookahead, _)| lookahead.first()),
+                );
+                for member in lookaheads {
+                    match *member {
+                        SetMember::Char(chr) => {
+                            chars.insert(chr);
+                        }
+                        SetMember::Range(lo, hi) => {
+                            chars.insert(representative(lo, hi));
+                        }
+                        SetMember::Terminate => {}
+                    }
+                }
+                chars.extend(nt_rule.tries.keys());
+            }
+            None => {}
+        }
+    }
+
+    /// How many chars of `text` are read going on with `pending`, without building a tree or
+    /// running instructions. `usize::MAX` if `text` is all `is_end` of the input and it is read
+    /// completely, or up to where the rules end in `prefix` mode. Stops after [`REPAIR_WINDOW`]
+    /// chars.
+    fn chars_read(
+        &self,
+        mut pending: Vec<Pending>,
+        text: &str,
+        is_end: bool,
+        prefix: bool,
+    ) -> usize {
+        let mut peekable = PeekableWrapper::<Chars>::new(text.chars().peekable());
+        let mut to_parse = ParseProcess::<PeekableWrapper<Chars>>::new(&mut peekable, None, None);
+        let read_chars = |to_parse: &mut ParseProcess<PeekableWrapper<Chars>>, chars: &str| {
+            chars
+                .chars()
+                .all(|chr| to_parse.next_if_eq(&chr).is_some())
+        };
+        while to_parse.cur_pos() < REPAIR_WINDOW {
+            let Some(next) = pending.pop() else {
+                let done = is_end && (prefix || to_parse.peek().is_none());
+                return if done { usize::MAX } else { to_parse.cur_pos() };
+            };
+            let el_index = match next {
+                Pending::Text(text) if read_chars(&mut to_parse, &text) => continue,
+                Pending::Text(_) => break,
+                Pending::Element(el_index) => el_index,
+            };
+            let Some(element) = self.elements.get(el_index) else {
+                break;
+            };
+            let read = match element.et {
+                ElementType::Terminal => read_chars(&mut to_parse, &element.name),
+                ElementType::CharClass => {
+                    let class = self.char_classes.get(&el_index);
+                    to_parse
+                        .next_if(|&chr| class.is_some_and(|class| class.contains(chr)))
+                        .is_some()
+                }
+                ElementType::NonTerminal => {
+                    let production =
+                        self.rules_with_steuermaps
+                            .get(&el_index)
+                            .and_then(|nt_rule| {
+                                Self::choose_production(&mut to_parse, nt_rule, prefix)
+                            });
+                    if let Some(Production::NotEmpty(el_ixs)) = production.map(|p| &**p) {
+                        pending.extend(el_ixs.iter().rev().map(|&ix| Pending::Element(ix)));
+                    }
+                    production.is_some()
+                }
+            };
+            if !read {
+                break;
+            }
+        }
+        to_parse.cur_pos()
+    }
+
+    /// True if `chr` is in the FOLLOW set of the rule `el_index`.
+    fn can_follow(&self, el_index: ElementIndex, chr: char) -> bool {
+        self.follow_sets.get(&el_index).is_some_and(|follow| {
+            follow
+                .iter()
+                .any(|member| member.overlaps(&SetMember::Char(chr)))
+        })
+    }
+
+    fn run_instructions(
+        &self,
+        tree: &mut Tree<String>,
+        cur_node: NodeId,
+        instructions: &Vec<<T as VM>::Tinstrution>,
+        state: &mut T::Tstate,
+    ) {
+        for instruction in instructions {
+            self.vm
+                .execute_instruction(tree, cur_node, &instruction, state);
+        }
+    }
+
+    fn parse_terminal(
+        &self,
+        to_parse: &mut ParseProcess<PeekableWrapper<Chars<'_>>>,
+        terminal: &str,
+    ) -> Result<String, ParserError> {
+        for (i, chr) in terminal.chars().enumerate() {
+            let pos = to_parse.cur_pos();
+            let char_to_parse: &char = to_parse.peek().ok_or(EndOfCharsError {
+                pos,
+                rules: vec![],
+                repairs: vec![],
+            })?;
+            if *char_to_parse != chr {
+                return Err(UnexpectedCharError {
+                    chr: *char_to_parse,
+                    pos: to_parse.cur_pos(),
+                    expected: if i == 0 {
+                        describe(&[Expected::Terminal(String::from(terminal))])
+                    } else {
+                        format!(
+                            "`{}` to complete `{}`",
+                            chr.escape_debug(),
+                            terminal.escape_debug()
+                        )
+                    },
+                    rules: vec![],
+                    repairs: vec![],
+                });
+            }
+            to_parse.next();
+        }
+        Ok(String::from(terminal))
+    }
+
+    /// Parses a single char out of a character class and returns it.
+    fn parse_char_class(
+        &self,
+        to_parse: &mut ParseProcess<PeekableWrapper<Chars<'_>>>,
+        el_index: ElementIndex,
+    ) -> Result<String, ParserError> {
+        let class = self
+            .char_classes
+            .get(&el_index)
+            .ok_or(MissingElementForIndex { index: el_index })?;
+        let pos = to_parse.cur_pos();
+        let chr = *to_parse.peek().ok_or(EndOfCharsError {
+            pos,
+            rules: vec![],
+            repairs: vec![],
+        })?;
+        if !class.contains(chr) {
+            return Err(UnexpectedCharError {
+                chr,
+                pos,
+                expected: describe(&[Expected::Chars(class.ranges().to_vec())]),
+                rules: vec![],
+                repairs: vec![],
+            });
+        }
+        to_parse.next();
+        Ok(String::from(chr))
+    }
+}
+
+/// The result of [`Parser::parse_recovering`].
+#[derive(Debug)]
+pub struct RecoveredParse {
+    /// The parse tree, with error nodes where rules were cut short
+    pub tree: Tree<String>,
+    /// The syntax errors in the order they were found
+    pub errors: Vec<ParserError>,
+}
+
+/// The state of one run of the parser over an input.
+struct ParseRun<'t> {
+    input: &'t str,
+    /// The rule parsing starts from
+    start: ElementIndex,
+    /// Collects the syntax errors instead of stopping at the first, see
+    /// [`Parser::parse_recovering`]
+    recovery: Option<Recovery>,
+    /// The rules being parsed, innermost last. After a failed parse the rules the error
+    /// occurred in.
+    rule_stack: Vec<ActiveRule>,
+    /// Set by [`Parser::parse_prefix`]: a char no production can start ends the input
+    prefix: bool,
+}
+
+impl<'t> ParseRun<'t> {
+    fn new(input: &'t str, start: ElementIndex) -> ParseRun<'t> {
+        ParseRun {
+            input,
+            start,
+            recovery: None,
+            rule_stack: vec![],
+            prefix: false,
+        }
+    }
+
+    /// Notes that a production is chosen for the innermost rule at `pos`.
+    fn choosing(&mut self, pos: usize) {
+        if let Some(active) = self.rule_stack.last_mut() {
+            active.production = None;
+            active.element = 0;
+            active.element_start = pos;
+        }
+    }
+
+    /// Notes the production chosen for the innermost rule.
+    fn chose(&mut self, production: &Rc<Production>) {
+        if let Some(active) = self.rule_stack.last_mut() {
+            active.production = Some(Rc::clone(production));
+            active.element = 0;
+        }
+    }
+
+    /// Notes that the innermost rule goes on with its element `element`, starting at `pos`.
+    fn at_element(&mut self, element: usize, pos: usize) {
+        if let Some(active) = self.rule_stack.last_mut() {
+            active.element = element;
+            active.element_start = pos;
+        }
+    }
+}
+
+/// A rule being parsed and how far it got.
+struct ActiveRule {
+    rule: ElementIndex,
+    /// `None` until a production was chosen
+    production: Option<Rc<Production>>,
+    /// The element of the production being parsed
+    element: usize,
+    /// The char offset that element started at
+    element_start: usize,
+}
+
+/// Something still to be parsed after a syntax error, see [`Parser::find_repairs`].
+#[derive(Clone)]
+enum Pending {
+    Element(ElementIndex),
+    /// The rest of a terminal that was read partly
+    Text(String),
+}
+
+/// The state of error recovery during a [`Parser::parse_recovering`] run.
+#[derive(Default)]
+struct Recovery {
+    errors: Vec<ParserError>,
+    /// The char offset the input got back in sync at after the last error
+    synced_at: Option<usize>,
+}
+
+impl Recovery {
+    /// True if `err` occurred where the last error was recovered from, which makes it most
+    /// likely a consequence of that one.
+    fn is_follow_up(&self, err: &ParserError) -> bool {
+        err.pos().is_some() && err.pos() == self.synced_at
+    }
+
+    /// Records `err` unless it is a follow-up error.
+    fn report(&mut self, err: ParserError) {
+        if !self.is_follow_up(&err) {
+            self.errors.push(err);
+        }
+    }
+}
+
+/// Skips input until `sync` accepts the position or the input ends, and adds the skipped input
+/// as an error node of `parent`.
+fn skip_to_sync<'a>(
+    to_parse: &mut ParseProcess<PeekableWrapper<Chars<'a>>>,
+    tree: &mut Tree<String>,
+    parent: NodeId,
+    mut sync: impl FnMut(&mut ParseProcess<PeekableWrapper<Chars<'a>>>) -> bool,
+) -> Result<(), TreeError> {
+    let start = to_parse.source_position();
+    let mut skipped = String::new();
+    while !sync(to_parse) {
+        let Some(chr) = to_parse.next() else {
+            break;
+        };
+        skipped.push(chr);
+    }
+    let span = Span {
+        start,
+        end: to_parse.source_position(),
+    };
+    tree.add_error_node(skipped, Some(parent), span)?;
+    Ok(())
+}
+
+/// Fails with `TrailingInputError` if `input` goes on after the first `consumed` chars.
+fn require_end(input: &str, consumed: usize) -> Result<(), ParserError> {
+    match input.chars().nth(consumed) {
+        Some(chr) => Err(ParserError::TrailingInputError { chr, pos: consumed }),
+        None => Ok(()),
+    }
+}
+
+/// Runs the transforms the grammar opted into with `$TRANSFORM`. Returns what left factoring
+/// rewrote.
+pub(crate) fn apply_transforms<T>(
+    parser_data: &mut ParserData<T>,
+    entry_points: &[ElementIndex],
+) -> Vec<LeftFactoring>
+where
+    T: VM,
+{
+    if parser_data.parse_rules.eliminate_left_recursion {
+        eliminate_left_recursion(parser_data, entry_points);
+    }
+    if parser_data.parse_rules.left_factor {
+        left_factor(parser_data)
+    } else {
+        vec![]
+    }
+}
+
+/// Parses a grammar text and runs its transforms, for tools that look at a grammar without
+/// building a parser from it. Syntax errors are returned as diagnostics.
+pub(crate) fn transformed_parser_data<T>(
+    rule_text: &str,
+    vm: &T,
+) -> Result<ParserData<T>, GrammarDiagnostics>
+where
+    T: VM,
+{
+    let mut parser_data = parsed_parser_data(rule_text, vm)?;
+    let entry_points = parser_data.entry_points();
+    apply_transforms(&mut parser_data, &entry_points);
+    Ok(parser_data)
+}
+
+/// Parses a grammar text as written, without its transforms. Syntax errors are returned as
+/// diagnostics.
+pub(crate) fn parsed_parser_data<T>(
+    rule_text: &str,
+    vm: &T,
+) -> Result<ParserData<T>, GrammarDiagnostics>
+where
+    T: VM,
+{
+    let mut peekable = PeekableWrapper::from_str(rule_text);
+    let mut rule_parser = RuleParser::new(&mut peekable, vm);
+    let syntax_errors = rule_parser.parse_rules_recovering();
+    if !syntax_errors.is_empty() {
+        return Err(GrammarDiagnostics {
+            diagnostics: syntax_diagnostics(rule_text, syntax_errors),
+        });
+    }
+    Ok(rule_parser.parser_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::script_parser::Parser;
+    use crate::vms::counting_vm::CountingVm;
+    use crate::vms::{NullVm, VM};
+
+    use crate::errors::ParserError;
+    use crate::parse_funcs::{SourcePosition, Span};
+    use crate::peekables::{ParseProcess, PeekableWrapper, TPeekable};
+    use crate::repair::Edit;
+    use crate::rule_parsing::RuleParser;
+    use crate::tree::{NodeId, Tree};
+
+    #[test]
+    fn test_script_parser() {
+        let rules = "start      -> identifier1 identifier2;\
+            identifier1 -> \"a_terminal\"| #;
             identifier2 -> \"b_terminal\"| #;
 ";
         let vm = NullVm::new();
@@ -367,6 +1514,7 @@ mod tests {
                 chr,
                 pos,
                 expected: _,
+                ..
             } = x
             {
                 assert_eq!(5, pos);
@@ -588,6 +1736,658 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_char_classes() {
+        let rules = "start      -> letter rest;\
+            rest -> [a-zA-Z0-9_] rest | #;\
+            letter -> [a-zA-Z_];\
+";
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::new_from_text(rules, &vm);
+
+        let tree = parser.parse("x_9", &mut state).unwrap();
+        let first = &tree
+            .get_by_path_or_none(NodeId::new(0, 0), vec![0, 0].into_iter())
+            .unwrap()
+            .unwrap()
+            .data;
+        let third = &tree
+            .get_by_path_or_none(NodeId::new(0, 0), vec![1, 1, 0].into_iter())
+            .unwrap()
+            .unwrap()
+            .data;
+        assert_eq!("x", first);
+        assert_eq!("9", third);
+    }
+
+    #[test]
+    fn test_negated_char_class_and_any_char() {
+        let rules = "start      -> \"'\" chars \"'\" .;\
+            chars -> [^'\\n] chars | #;\
+";
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::new_from_text(rules, &vm);
+
+        parser.parse("'a b'!", &mut state).unwrap();
+        match parser.parse("'a\nb'!", &mut state) {
+            Err(ParserError::UnexpectedCharError { chr, pos, .. }) => {
+                assert_eq!('\n', chr);
+                assert_eq!(2, pos);
+            }
+            _ => panic!("newline must not match [^'\\n]"),
+        }
+    }
+
+    #[test]
+    fn test_repetitions_are_flattened() {
+        let rules = "start      -> \"-\"? digit+ \";\"*;\
+            digit -> [0-9];\
+";
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::new_from_text(rules, &vm);
+
+        let tree = parser.parse("123", &mut state).unwrap();
+        let node = |path: Vec<usize>| {
+            tree.get_by_path_or_none(NodeId::new(0, 0), path.into_iter())
+                .unwrap()
+                .map(|node| node.data.clone())
+        };
+        assert_eq!(None, node(vec![0, 0]));
+        assert_eq!(Some(String::from("3")), node(vec![1, 2, 0]));
+        assert_eq!(None, node(vec![1, 3]));
+
+        let tree = parser.parse("-4;;", &mut state).unwrap();
+        let node = |path: Vec<usize>| {
+            tree.get_by_path_or_none(NodeId::new(0, 0), path.into_iter())
+                .unwrap()
+                .map(|node| node.data.clone())
+        };
+        assert_eq!(Some(String::from("-")), node(vec![0, 0]));
+        assert_eq!(Some(String::from(";")), node(vec![2, 1]));
+        assert_eq!(None, node(vec![2, 2]));
+    }
+
+    #[test]
+    fn test_repetitions_with_ignore() {
+        let rules = "$IGNORE: whitespace;\
+            start      -> word+;\
+            word -> $[IGNORE: #] [a-z]+;\
+            $IGNORE: #;\
+            whitespace -> \" \"+;\
+";
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::new_from_text(rules, &vm);
+
+        let tree = parser.parse(" ab  cd e ", &mut state).unwrap();
+        let node = |path: Vec<usize>| {
+            tree.get_by_path_or_none(NodeId::new(0, 0), path.into_iter())
+                .unwrap()
+                .map(|node| node.data.clone())
+        };
+        assert_eq!(Some(String::from("d")), node(vec![0, 1, 0, 1]));
+        assert_eq!(Some(String::from("e")), node(vec![0, 2, 0, 0]));
+        assert_eq!(None, node(vec![0, 3]));
+    }
+
+    #[test]
+    fn test_groups() {
+        let rules = "start      -> digit ((\"+\" | \"-\") digit)*;\
+            digit -> [0-9];\
+";
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::new_from_text(rules, &vm);
+
+        let tree = parser.parse("1+2-3", &mut state).unwrap();
+        let node = |path: Vec<usize>| {
+            tree.get_by_path_or_none(NodeId::new(0, 0), path.into_iter())
+                .unwrap()
+                .map(|node| node.data.clone())
+        };
+        assert_eq!(Some(String::from("-")), node(vec![1, 1, 0, 0]));
+        assert_eq!(Some(String::from("3")), node(vec![1, 1, 1, 0]));
+        assert!(parser.parse("1*2", &mut state).is_err());
+    }
+
+    #[test]
+    fn test_escaped_terminals_match_control_chars() {
+        let rules = "$IGNORE: whitespace;\
+            start      -> \"a\"+;\
+            $IGNORE: #;\
+            whitespace -> (\" \" | \"\\n\" | \"\\t\")*;\
+";
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::new_from_text(rules, &vm);
+
+        parser.parse("a\n\ta \na", &mut state).unwrap();
+    }
+
+    #[test]
+    fn test_parse_from_entry_points() {
+        let rules = "$IGNORE: space;\
+            $START: file, expression;\
+            file -> expression \";\";\
+            expression -> [0-9] \"+\" [0-9];\
+            $IGNORE: #;\
+            space -> \" \"*;\
+";
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::new_from_text(rules, &vm);
+
+        parser.parse(" 1 + 2 ; ", &mut state).unwrap();
+        parser.parse_from("expression", " 1 + 2 ", &mut state).unwrap();
+        assert!(parser.parse_from("file", "1+2", &mut state).is_err());
+        match parser.parse_from("space", " ", &mut state) {
+            Err(ParserError::UnknownEntryPoint { name }) => assert_eq!("space", name),
+            _ => panic!("space is no entry point"),
+        }
+    }
+
+    #[test]
+    fn test_trailing_input_is_an_error() {
+        let rules = "start -> \"hello\" \"world\";";
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::new_from_text(rules, &vm);
+
+        parser.parse("helloworld", &mut state).unwrap();
+        match parser.parse("helloworldGARBAGE", &mut state) {
+            Err(err @ ParserError::TrailingInputError { .. }) => {
+                assert_eq!(Some(10), err.pos());
+                assert!(err.to_string().contains("\"G\" at pos 10"), "{err}");
+            }
+            _ => panic!("GARBAGE is trailing input"),
+        }
+    }
+
+    #[test]
+    fn test_parse_prefix() {
+        let rules = "$IGNORE: space;\
+            $START: start, expression;\
+            start -> expression;\
+            expression -> [0-9] \"+\" [0-9];\
+            $IGNORE: #;\
+            space -> \" \"*;\
+";
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::new_from_text(rules, &vm);
+
+        let (tree, consumed) = parser.parse_prefix("1 + 2 ) + 3", &mut state).unwrap();
+        assert_eq!(6, consumed);
+        let span = tree.span(NodeId::new(0, 0)).unwrap().unwrap();
+        assert_eq!("1 + 2", span.text("1 + 2 ) + 3"));
+        assert_eq!(5, parser.parse_prefix("1 + 2", &mut state).unwrap().1);
+        match parser.parse_from("expression", "1 + 2)", &mut state) {
+            Err(ParserError::TrailingInputError { chr, pos }) => {
+                assert_eq!(')', chr);
+                assert_eq!(5, pos);
+            }
+            _ => panic!(") is trailing input"),
+        }
+    }
+
+    #[test]
+    fn test_parse_prefix_with_lookahead() {
+        let rules = "$LOOKAHEAD: 2;\
+            start -> \"a\" rest;\
+            rest -> \"b\" rest | #;";
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::try_from_text(rules, &vm).unwrap();
+
+        assert_eq!(3, parser.parse_prefix("abbX", &mut state).unwrap().1);
+        assert_eq!(1, parser.parse_prefix("aX", &mut state).unwrap().1);
+        assert_eq!(3, parser.parse_prefix("abb", &mut state).unwrap().1);
+        assert!(parser.parse("abbX", &mut state).is_err());
+        assert!(parser.parse_prefix("X", &mut state).is_err());
+    }
+
+    #[test]
+    fn test_parse_prefix_does_not_back_off() {
+        let rules = "start -> item*; item -> [a-z] \";\";";
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::new_from_text(rules, &vm);
+
+        assert_eq!(2, parser.parse_prefix("a;.b", &mut state).unwrap().1);
+        // `b` starts another item, which the input ends in
+        match parser.parse_prefix("a;b", &mut state) {
+            Err(ParserError::EndOfCharsError { pos, .. }) => assert_eq!(3, pos),
+            other => panic!("unexpected result {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_try_from_text_collects_syntax_errors() {
+        let rules = "start -> a b c;\n\
+            a -> \"x\" \"\\q\";\n\
+            b -> \"y\" );\n\
+            c -> \"z\" d;\n";
+        let vm = NullVm::new();
+        let Err(err) = Parser::try_from_text(rules, &vm) else {
+            panic!("the grammar is broken")
+        };
+        let found: Vec<(Option<String>, Option<String>)> = err
+            .diagnostics
+            .iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.rule.clone(),
+                    diagnostic.location.map(|location| location.to_string()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (Some(String::from("a")), Some(String::from("2:11"))),
+                (Some(String::from("b")), Some(String::from("3:10"))),
+                (Some(String::from("c")), Some(String::from("4:10"))),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn test_try_from_text_collects_conflicts() {
+        let rules = "start -> a | b;\n\
+            a -> \"x\" | \"x\" \"y\";\n\
+            b -> \"z\" (\"1\" | [0-9]);\n";
+        let vm = NullVm::new();
+        let Err(err) = Parser::try_from_text(rules, &vm) else {
+            panic!("the grammar is not LL(1)")
+        };
+        let rules: Vec<Option<String>> = err
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.rule.clone())
+            .collect();
+        assert_eq!(
+            vec![Some(String::from("a")), Some(String::from("b/(1)"))],
+            rules
+        );
+        assert_eq!("3:10", err.diagnostics[1].location.unwrap().to_string());
+        assert_eq!(
+            "FIRST/FIRST conflict between \"1\" and [0-9] on '1', e.g. input \"z1\"; \
+            not LL(k) for any k up to 5",
+            err.diagnostics[1].message
+        );
+    }
+
+    #[test]
+    fn test_try_from_text_validates_rules() {
+        let rules = "start -> \"x\" loop | \"y\";\n\
+            loop -> \"z\" loop;\n\
+            unused -> \"u\";\n";
+        let vm = NullVm::new();
+        let Err(err) = Parser::try_from_text(rules, &vm) else {
+            panic!("loop never ends")
+        };
+        assert_eq!(
+            vec![
+                "2:1: in rule \"loop\": rule can never derive a string of terminals",
+                "3:1: in rule \"unused\": rule can't be reached from an entry point",
+            ],
+            err.diagnostics
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+
+        let parser = Parser::try_from_text(&rules.replace("\"z\" loop;", "\"z\";"), &vm).unwrap();
+        assert_eq!(
+            vec!["3:1: in rule \"unused\": rule can't be reached from an entry point"],
+            parser
+                .warnings()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_try_from_text_reports_left_recursion() {
+        let rules = "start -> expr;\n\
+            expr -> term \"+\" | \"x\";\n\
+            term -> expr \"*\";\n";
+        let vm = NullVm::new();
+        let Err(err) = Parser::try_from_text(rules, &vm) else {
+            panic!("the grammar is left recursive")
+        };
+        assert_eq!(1, err.diagnostics.len());
+        assert_eq!(
+            "2:1: in rule \"expr\": left recursion: expr -> term -> expr",
+            err.diagnostics[0].to_string()
+        );
+    }
+
+    #[test]
+    fn test_try_from_text_keeps_warnings_on_errors() {
+        let vm = NullVm::new();
+        let Err(err) = Parser::try_from_text("start -> \"a\" | \"a\";", &vm) else {
+            panic!("the alternatives conflict")
+        };
+        let messages: Vec<String> = err.diagnostics.iter().map(ToString::to_string).collect();
+        assert_eq!(2, messages.len());
+        assert!(messages[0].contains("FIRST/FIRST"));
+        assert!(messages[1].contains("more than once"));
+
+        let mut peekable = PeekableWrapper::from_str("start -> \"a\" | \"a\";");
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+        let parser_data = rule_parser.parser_data;
+        let start = parser_data.get_element_nt_index("start").unwrap();
+        let Err(err) = Parser::try_from_parser_data(parser_data, start, &vm) else {
+            panic!("the alternatives conflict")
+        };
+        assert_eq!(2, err.diagnostics.len());
+        assert!(err.diagnostics[1].to_string().contains("more than once"));
+    }
+
+    #[test]
+    fn test_try_from_text_with_undefined_ignore_rule() {
+        let vm = NullVm::new();
+        let Err(err) = Parser::try_from_text("$IGNORE: ws;\nstart -> \"a\" \"b\";", &vm) else {
+            panic!("ws is not defined")
+        };
+        assert_eq!(1, err.diagnostics.len());
+        assert!(err.diagnostics[0].to_string().contains("ws"));
+        assert!(err.diagnostics[0].location.is_some());
+    }
+
+    #[test]
+    fn test_try_from_text_with_empty_terminals() {
+        let vm = NullVm::new();
+        let mut parser = Parser::try_from_text("start -> \"\" \"a\";", &vm).unwrap();
+        assert!(parser.parse("a", &mut 0).is_ok());
+
+        let mut parser = Parser::try_from_text("start -> \"a\" | \"\";", &vm).unwrap();
+        assert!(parser.parse("a", &mut 0).is_ok());
+        assert!(parser.parse("", &mut 0).is_ok());
+        assert!(parser.parse("b", &mut 0).is_err());
+    }
+
+    #[test]
+    fn test_try_from_text_without_entry_point() {
+        let vm = NullVm::new();
+        let Err(err) = Parser::try_from_text("program -> \"x\";", &vm) else {
+            panic!("there is no start rule")
+        };
+        assert_eq!(1, err.diagnostics.len());
+        assert_eq!(None, err.diagnostics[0].rule);
+    }
+
+    #[test]
+    fn test_eliminated_left_recursion_keeps_instructions() {
+        let rules = "$TRANSFORM: eliminate_left_recursion;\
+            start -> expr;\
+            expr -> expr \"+\" num | expr \"-\" num | num {};\
+            num -> [0-9] {};\
+";
+        let vm = CountingVm {};
+        let mut state = CountingVm::create_new_state();
+        let mut parser = Parser::new_from_text(rules, &vm);
+
+        let tree = parser.parse("1+2-3", &mut state).unwrap();
+        let node = |path: Vec<usize>| {
+            tree.get_by_path_or_none(NodeId::new(0, 0), path.into_iter())
+                .unwrap()
+                .map(|node| node.data.clone())
+        };
+        assert_eq!(Some(String::from("+")), node(vec![0, 1]));
+        assert_eq!(Some(String::from("-")), node(vec![0, 3]));
+        assert_eq!(Some(String::from("3")), node(vec![0, 4, 0]));
+        assert_eq!(6, state);
+    }
+
+    /// Records the rule of every instruction it runs.
+    struct RuleNameVm {}
+
+    impl VM for RuleNameVm {
+        type Tstate = Vec<String>;
+        type Tinstrution = String;
+
+        fn parse_instructions<T>(
+            &self,
+            prod_name: &str,
+            _to_parse: &mut ParseProcess<T>,
+        ) -> Result<Vec<Self::Tinstrution>, ParserError>
+        where
+            T: TPeekable<Item = char>,
+        {
+            Ok(vec![String::from(prod_name)])
+        }
+
+        fn execute_instruction(
+            &self,
+            _tree: &mut Tree<String>,
+            _cur_node: NodeId,
+            instruction: &Self::Tinstrution,
+            state: &mut Vec<String>,
+        ) {
+            state.push(instruction.clone());
+        }
+
+        fn create_new_state() -> Self::Tstate {
+            vec![]
+        }
+    }
+
+    #[test]
+    fn test_eliminated_indirect_left_recursion_keeps_instructions() {
+        let rules = "$TRANSFORM: eliminate_left_recursion;\
+            b -> a \"z\" | \"w\" {};\
+            a -> b \"x\" | \"y\" {};\
+            $START: a;";
+        let vm = RuleNameVm {};
+        let mut state = RuleNameVm::create_new_state();
+        let mut parser = Parser::try_from_text(rules, &vm).unwrap();
+
+        let tree = parser.parse("wxzx", &mut state).unwrap();
+        let node = |path: Vec<usize>| {
+            tree.get_by_path_or_none(NodeId::new(0, 0), path.into_iter())
+                .unwrap()
+                .map(|node| node.data.clone())
+        };
+        assert_eq!(Some(String::from("w")), node(vec![0, 0]));
+        assert_eq!(Some(String::from("x")), node(vec![1]));
+        assert_eq!(Some(String::from("z")), node(vec![2, 0]));
+        assert_eq!(Some(String::from("x")), node(vec![3]));
+        assert_eq!(vec!["b", "a", "b", "a"], state);
+        assert!(parser.parse("wxzxzx", &mut state).is_ok());
+        assert!(parser.parse("yzx", &mut state).is_ok());
+        assert!(parser.parse("zx", &mut state).is_err());
+
+        let hand_written = "a -> \"w\" \"x\" t | \"y\" t;\
+            t -> \"z\" \"x\" t | #;\
+            $START: a;";
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::try_from_text(hand_written, &vm).unwrap();
+        parser.parse("wxzx", &mut state).unwrap();
+    }
+
+    #[test]
+    fn test_left_recursion_without_transform_fails() {
+        let rules = "start -> start \"a\" | \"b\";";
+        let vm = NullVm::new();
+        assert!(Parser::try_from_text(rules, &vm).is_err());
+
+        let rules = "$TRANSFORM: eliminate_left_recursion;\
+            start -> start \"a\" | \"b\";";
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::try_from_text(rules, &vm).unwrap();
+        parser.parse("baa", &mut state).unwrap();
+    }
+
+    #[test]
+    fn test_left_factored_grammar() {
+        let rules = "$IGNORE: space;\
+            $TRANSFORM: left_factor;\
+            start -> stmt;\
+            stmt -> \"if\" cond \"then\" body \"end\"\
+                | \"if\" cond \"then\" body \"else\" body \"end\"\
+                | \"print\" | \"pop\";\
+            cond -> [a-z];\
+            body -> stmt;\
+            $IGNORE: #;\
+            space -> \" \"*;\
+";
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::new_from_text(rules, &vm);
+        assert_eq!(3, parser.left_factorings().len());
+
+        parser.parse("if x then print end", &mut state).unwrap();
+        parser
+            .parse("if x then pop else if y then print end end", &mut state)
+            .unwrap();
+        let tree = parser.parse("pop", &mut state).unwrap();
+        let node = |path: Vec<usize>| {
+            tree.get_by_path_or_none(NodeId::new(0, 0), path.into_iter())
+                .unwrap()
+                .map(|node| node.data.clone())
+        };
+        assert_eq!(Some(String::from("p")), node(vec![0, 0]));
+        assert_eq!(Some(String::from("op")), node(vec![0, 1]));
+    }
+
+    #[test]
+    fn test_terminals_with_shared_first_char() {
+        let rules = "$IGNORE: space;\
+            start -> stmt+;\
+            stmt -> \"print\" | \"pop\" | \"p\" | \"push\" [0-9];\
+            $IGNORE: #;\
+            space -> \" \"*;\
+";
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::new_from_text(rules, &vm);
+
+        let tree = parser.parse("push 1 pop print p", &mut state).unwrap();
+        let node = |path: Vec<usize>| {
+            tree.get_by_path_or_none(NodeId::new(0, 0), path.into_iter())
+                .unwrap()
+                .map(|node| node.data.clone())
+        };
+        assert_eq!(Some(String::from("push")), node(vec![0, 0, 0]));
+        assert_eq!(Some(String::from("pop")), node(vec![0, 1, 0]));
+        assert_eq!(Some(String::from("print")), node(vec![0, 2, 0]));
+        assert_eq!(Some(String::from("p")), node(vec![0, 3, 0]));
+        assert!(parser.parse("pull", &mut state).is_err());
+    }
+
+    #[test]
+    fn test_terminal_mismatch_is_reported_where_it_happens() {
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::new_from_text("start -> \"print\" | \"pop\";", &vm);
+        match parser.parse("prx", &mut state) {
+            Err(ParserError::UnexpectedCharError {
+                chr, pos, expected, ..
+            }) => {
+                assert_eq!(('x', 2), (chr, pos));
+                assert_eq!("`i`", expected);
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+        match parser.parse("px", &mut state) {
+            Err(ParserError::UnexpectedCharError { chr, pos, .. }) => {
+                assert_eq!(('x', 1), (chr, pos));
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+        match parser.parse("pr", &mut state) {
+            Err(ParserError::EndOfCharsError { pos, .. }) => assert_eq!(2, pos),
+            other => panic!("unexpected result {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_shorter_terminal_followed_by_rest_of_longer_conflicts() {
+        let vm = NullVm::new();
+        let Err(err) = Parser::try_from_text("start -> op \"=\"; op -> \"=\" | \"==\";", &vm)
+        else {
+            panic!("\"=\" followed by \"=\" can't be told apart from \"==\"")
+        };
+        assert_eq!(Some(String::from("op")), err.diagnostics[0].rule);
+
+        let rules = "start -> kw \"x\" | \"pr\" \"y\"; kw -> \"p\" \"rint\";";
+        let Err(err) = Parser::try_from_text(rules, &vm) else {
+            panic!("only the first terminal of an alternative is used to choose it")
+        };
+        assert_eq!(Some(String::from("start")), err.diagnostics[0].rule);
+
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::new_from_text("start -> op \"=\"; op -> \"=\" | \"=>\";", &vm);
+        parser.parse("==", &mut state).unwrap();
+        parser.parse("=>=", &mut state).unwrap();
+        let mut parser = Parser::new_from_text("start -> \"print\" \"x\" | \"pr\" \"y\";", &vm);
+        parser.parse("printx", &mut state).unwrap();
+        parser.parse("pry", &mut state).unwrap();
+    }
+
+    #[test]
+    fn test_same_terminal_in_two_alternatives_conflicts() {
+        let rules = "start -> a | b;\
+            a -> \"pop\" \"1\";\
+            b -> \"pop\" \"2\";";
+        let vm = NullVm::new();
+        let Err(err) = Parser::try_from_text(rules, &vm) else {
+            panic!("both alternatives start with pop")
+        };
+        assert_eq!(Some(String::from("start")), err.diagnostics[0].rule);
+        assert!(
+            err.diagnostics[0]
+                .message
+                .ends_with("; needs $LOOKAHEAD: 4"),
+            "{}",
+            err.diagnostics[0]
+        );
+    }
+
+    #[test]
+    fn test_lookahead_directive() {
+        let rules = "$LOOKAHEAD: 2;\
+            start -> item+;\
+            item -> one | two;\
+            one -> \"x\" \"1\";\
+            two -> \"x\" \"2\";";
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::try_from_text(rules, &vm).unwrap();
+
+        let tree = parser.parse("x2x1", &mut state).unwrap();
+        let node = |path: Vec<usize>| {
+            tree.get_by_path_or_none(NodeId::new(0, 0), path.into_iter())
+                .unwrap()
+                .map(|node| node.data.clone())
+        };
+        assert_eq!(Some(String::from("2")), node(vec![0, 0, 0, 1]));
+        assert_eq!(Some(String::from("1")), node(vec![0, 1, 0, 1]));
+        match parser.parse("x2x3", &mut state) {
+            Err(ParserError::UnexpectedCharError { chr, pos, .. }) => {
+                assert_eq!(('3', 3), (chr, pos));
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+
+        let Err(err) = Parser::try_from_text(&rules.replace("\"2\"", "\"1\""), &vm) else {
+            panic!("one and two are the same")
+        };
+        assert_eq!(
+            "not LL(2): more than one alternative can start with \"x1\"; \
+            not LL(k) for any k up to 5",
+            err.diagnostics[0].message
+        );
+    }
+
     #[test]
     fn test_counting_vm() {
         let rules = "start      -> count count count ;\
@@ -602,4 +2402,283 @@ mod tests {
         let _graph = parser.parse(text_to_parse, &mut state).unwrap();
         assert_eq!(3, state);
     }
+
+    /// Records the span of every node it runs an instruction on.
+    struct SpanVm {}
+
+    impl VM for SpanVm {
+        type Tstate = Vec<Span>;
+        type Tinstrution = ();
+
+        fn parse_instructions<T>(
+            &self,
+            _prod_name: &str,
+            _to_parse: &mut ParseProcess<T>,
+        ) -> Result<Vec<Self::Tinstrution>, ParserError>
+        where
+            T: TPeekable<Item = char>,
+        {
+            Ok(vec![()])
+        }
+
+        fn execute_instruction(
+            &self,
+            tree: &mut Tree<String>,
+            cur_node: NodeId,
+            _instruction: &Self::Tinstrution,
+            state: &mut Vec<Span>,
+        ) {
+            state.extend(tree.span(cur_node).unwrap());
+        }
+
+        fn create_new_state() -> Self::Tstate {
+            vec![]
+        }
+    }
+
+    #[test]
+    fn test_tree_nodes_have_spans() {
+        let rules = "$IGNORE: space;\
+            start -> word \",\" word;\
+            word -> $[IGNORE: #] [a-zä]+ {};\
+            $IGNORE: #;\
+            space -> (\" \" | \"\\n\")*;\
+";
+        let vm = SpanVm {};
+        let mut state = SpanVm::create_new_state();
+        let mut parser = Parser::new_from_text(rules, &vm);
+
+        let text = " äb ,\n cd";
+        let tree = parser.parse(text, &mut state).unwrap();
+        let span = |path: Vec<usize>| {
+            tree.get_by_path_or_none(NodeId::new(0, 0), path.into_iter())
+                .unwrap()
+                .unwrap()
+                .span()
+                .unwrap()
+        };
+        assert_eq!("äb ,\n cd", span(vec![]).text(text));
+        assert_eq!("äb", span(vec![0]).text(text));
+        assert_eq!("b", span(vec![0, 0, 1]).text(text));
+        let comma = span(vec![1]);
+        assert_eq!(
+            SourcePosition {
+                char_offset: 4,
+                byte_offset: 5,
+                line: 1,
+                column: 5,
+            },
+            comma.start
+        );
+        assert_eq!("1:5-1:6", comma.to_string());
+        assert_eq!("2:2-2:4", span(vec![2]).to_string());
+        assert_eq!(vec![span(vec![0]), span(vec![2])], state);
+    }
+
+    #[test]
+    fn test_parse_recovering_collects_errors() {
+        let rules = "$IGNORE: space;\
+            start -> stmt*;\
+            stmt -> name \"=\" value \";\" {};\
+            name -> $[IGNORE: #] [a-z]+;\
+            value -> $[IGNORE: #] [0-9]+;\
+            $IGNORE: #;\
+            space -> (\" \" | \"\\n\")*;\
+";
+        let vm = SpanVm {};
+        let mut state = SpanVm::create_new_state();
+        let mut parser = Parser::new_from_text(rules, &vm);
+
+        let text = "a = 1;\nb = x;\nc 2;\nd = 4;";
+        assert!(parser.parse(text, &mut state).is_err());
+        state.clear();
+        let parsed = parser.parse_recovering(text, &mut state).unwrap();
+        let positions: Vec<Option<usize>> = parsed.errors.iter().map(ParserError::pos).collect();
+        assert_eq!(vec![Some(11), Some(16)], positions, "{:?}", parsed.errors);
+        match &parsed.errors[0] {
+            ParserError::UnexpectedCharError { chr, .. } => assert_eq!('x', *chr),
+            other => panic!("unexpected error {other:?}"),
+        }
+
+        let tree = &parsed.tree;
+        let error_nodes: Vec<(String, String)> = tree
+            .get_descendants(NodeId::new(0, 0))
+            .into_iter()
+            .map(|id| tree.get_node(id).unwrap())
+            .filter(|node| node.is_error())
+            .map(|node| (node.data.clone(), node.span().unwrap().to_string()))
+            .collect();
+        assert_eq!(
+            vec![
+                (String::from("x"), String::from("2:5-2:6")),
+                (String::from("2;"), String::from("3:3-3:5")),
+            ],
+            error_nodes
+        );
+        // `value` recovered from the first error inside the statement, the second one cut the
+        // statement itself short.
+        let complete: Vec<&str> = state.iter().map(|span| span.text(text)).collect();
+        assert_eq!(vec!["a = 1;", "b = x;", "d = 4;"], complete);
+    }
+
+    #[test]
+    fn test_parse_recovering_reports_follow_up_errors_once() {
+        let rules = "start -> \"hello\" \"world\";";
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::new_from_text(rules, &vm);
+
+        let parsed = parser.parse_recovering("helloworld", &mut state).unwrap();
+        assert!(parsed.errors.is_empty());
+
+        let parsed = parser.parse_recovering("hello", &mut state).unwrap();
+        match parsed.errors.as_slice() {
+            [ParserError::EndOfCharsError { pos, .. }] => assert_eq!(5, *pos),
+            other => panic!("expected one end of input error, got {other:?}"),
+        }
+
+        let parsed = parser.parse_recovering("helloworldGARBAGE", &mut state).unwrap();
+        match parsed.errors.as_slice() {
+            [ParserError::TrailingInputError { chr, pos }] => assert_eq!(('G', 10), (*chr, *pos)),
+            other => panic!("expected trailing input, got {other:?}"),
+        }
+        let root = parsed.tree.get_node(NodeId::new(0, 0)).unwrap();
+        let last = parsed.tree.get_nth_child(root, 2).unwrap();
+        assert!(last.is_error());
+        assert_eq!("GARBAGE", last.data);
+    }
+
+    #[test]
+    fn test_syntax_errors_suggest_repairs() {
+        let rules = "$IGNORE: space;\
+            start -> stmt*;\
+            stmt -> name \"=\" value \";\";\
+            name -> $[IGNORE: #] [a-z]+;\
+            value -> $[IGNORE: #] [0-9]+;\
+            $IGNORE: #;\
+            space -> (\" \" | \"\\n\")*;\
+";
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::new_from_text(rules, &vm);
+
+        let repairs = |parser: &mut Parser<NullVm>, text: &'static str| {
+            let err = parser
+                .parse(text, &mut NullVm::create_new_state())
+                .unwrap_err();
+            err.repairs()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec!["insert `;` at 2:1"],
+            repairs(&mut parser, "a = 1\nb = 2;")
+        );
+        assert_eq!(vec!["insert `;` at 1:6"], repairs(&mut parser, "a = 1"));
+        assert_eq!(
+            vec!["replace `:` with `;` at 1:6"],
+            repairs(&mut parser, "a = 1:")
+        );
+
+        let err = parser.parse("a = 1:", &mut state).unwrap_err();
+        assert_eq!(Edit::Replace { old: ':', new: ';' }, err.repairs()[0].edit);
+        assert!(
+            err.to_string()
+                .ends_with(". Possible fixes: replace `:` with `;` at 1:6")
+        );
+        assert!(err.to_string().ends_with(". Possible fixes: replace `:` with `;` at 1:6"));
+
+        let text = "a = 1;\nb = x;\nc 2;\nd = 4;";
+        let parsed = parser.parse_recovering(text, &mut state).unwrap();
+        let repairs: Vec<String> = parsed
+            .errors
+            .iter()
+            .map(|err| err.repairs()[0].to_string())
+            .collect();
+        assert_eq!(
+            vec!["replace `x` with `0` at 2:5", "insert `=` at 3:3"],
+            repairs
+        );
+    }
+
+    #[test]
+    fn test_errors_name_rules_and_expected_items() {
+        let rules = "$START: terms;\
+            terms -> term (\",\" term)*;\
+            term -> \"print\" number | \"+\" number | \"-\" number | [0-9] | \"(\" terms \")\";\
+            number -> [0-9]+;\
+";
+        let vm = NullVm::new();
+        let mut state = NullVm::create_new_state();
+        let mut parser = Parser::new_from_text(rules, &vm);
+
+        let err = parser.parse("1,x", &mut state).unwrap_err();
+        assert_eq!(vec!["term", "terms"], err.rules());
+        assert_eq!(
+            " \"x\" at pos 2 was not expected while parsing `term` in `terms`. \
+             Expected `print`, `+`, `-`, `(` or a digit. Possible fixes: replace `x` with `0` at 1:3",
+            err.to_string()
+        );
+
+        let err = parser.parse("(+1,print-)", &mut state).unwrap_err();
+        assert_eq!(
+            vec!["number", "term", "terms", "term", "terms"],
+            err.rules()
+        );
+        match &err {
+            ParserError::UnexpectedCharError { expected, .. } => assert_eq!("a digit", expected),
+            other => panic!("unexpected error {other:?}"),
+        }
+
+        let err = parser.parse("1,prin", &mut state).unwrap_err();
+        assert!(matches!(err, ParserError::EndOfCharsError { .. }));
+        assert_eq!(vec!["term", "terms"], err.rules());
+        let err = parser.parse("1,prinx", &mut state).unwrap_err();
+        match &err {
+            ParserError::UnexpectedCharError { expected, .. } => {
+                assert_eq!("`t` to complete `print`", expected);
+            }
+            other => panic!("unexpected error {other:?}"),
+        }
+
+        let err = parser.parse("(1,2]", &mut state).unwrap_err();
+        match &err {
+            ParserError::UnexpectedCharError { expected, .. } => {
+                assert_eq!("`,`, `)` or the end of the input", expected);
+            }
+            other => panic!("unexpected error {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_repairs_on_large_input_with_late_errors() {
+        // Rules are parsed recursively, one level per statement here, which needs more stack
+        // than test threads have.
+        let large_stack = std::thread::Builder::new().stack_size(256 << 20);
+        let test = large_stack.spawn(|| {
+            let rules = "start -> stmt+;\
+                stmt -> \"let \" name \"=\" num \";\" | \"print \" name \";\";\
+                name -> [a-z]+;\
+                num -> [0-9]+;\
+";
+            let vm = NullVm::new();
+            let mut state = NullVm::create_new_state();
+            let mut parser = Parser::new_from_text(rules, &vm);
+
+            let mut text = "let abc=123;print abc;".repeat(1200);
+            text.push_str("let x=1print x;let y=2;");
+            let started = std::time::Instant::now();
+            let err = parser.parse(&text, &mut state).unwrap_err();
+            assert_eq!(Some(26_407), err.pos());
+            assert_eq!(Edit::Insert(';'), err.repairs()[0].edit);
+
+            let text = "let abc=123;print ab c;".repeat(1000);
+            let parsed = parser.parse_recovering(&text, &mut state).unwrap();
+            assert_eq!(1000, parsed.errors.len());
+            assert!(parsed.errors.iter().all(|err| !err.repairs().is_empty()));
+            assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        });
+        test.unwrap().join().unwrap();
+    }
 }
@@ -1,15 +1,18 @@
 //! Shared set types used in FIRST/FOLLOW/Steuer computations.
 //!
 //! Provides SetMember and SetMemberWithEmpty and helpers to convert and
-//! collect them in hash maps/sets.
+//! collect them in hash maps/sets. Character classes contribute inclusive
+//! `Range` members, so membership has to be checked with `overlaps`/`matches`
+//! instead of plain hash lookups whenever ranges can be involved.
 use crate::parser_data::ElementIndex;
 use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fmt;
 
-#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, PartialOrd, Ord)]
 pub enum SetMemberWithEmpty {
     Char(char),
+    Range(char, char),
     Empty,
     Terminate,
 }
@@ -20,6 +23,9 @@ impl fmt::Display for SetMemberWithEmpty {
             SetMemberWithEmpty::Char(x) => {
                 write!(f, "'{x}'")
             }
+            SetMemberWithEmpty::Range(lo, hi) => {
+                write!(f, "'{lo}'-'{hi}'")
+            }
             SetMemberWithEmpty::Empty => {
                 write!(f, "empty")
             }
@@ -34,15 +40,17 @@ impl Into<String> for SetMemberWithEmpty {
     fn into(self) -> String {
         match self {
             SetMemberWithEmpty::Char(x) => String::from(x),
+            SetMemberWithEmpty::Range(lo, hi) => format!("{lo}-{hi}"),
             SetMemberWithEmpty::Empty => String::from("empty"),
             SetMemberWithEmpty::Terminate => String::from("terminate"),
         }
     }
 }
 
-#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, PartialOrd, Ord)]
 pub enum SetMember {
     Char(char),
+    Range(char, char),
     Terminate,
 }
 impl fmt::Display for SetMember {
@@ -51,6 +59,9 @@ impl fmt::Display for SetMember {
             SetMember::Char(x) => {
                 write!(f, "'{x}'")
             }
+            SetMember::Range(lo, hi) => {
+                write!(f, "'{lo}'-'{hi}'")
+            }
             SetMember::Terminate => {
                 write!(f, "terminate")
             }
@@ -58,10 +69,70 @@ impl fmt::Display for SetMember {
     }
 }
 
+impl SetMember {
+    /// A `Char` for single-char ranges, a `Range` otherwise.
+    pub fn from_range(lo: char, hi: char) -> SetMember {
+        if lo == hi {
+            SetMember::Char(lo)
+        } else {
+            SetMember::Range(lo, hi)
+        }
+    }
+
+    /// The inclusive char bounds of this member, `None` for Terminate.
+    pub fn bounds(&self) -> Option<(char, char)> {
+        match self {
+            SetMember::Char(x) => Some((*x, *x)),
+            SetMember::Range(lo, hi) => Some((*lo, *hi)),
+            SetMember::Terminate => None,
+        }
+    }
+
+    /// True if some lookahead is matched by both members.
+    pub fn overlaps(&self, other: &SetMember) -> bool {
+        match (self.bounds(), other.bounds()) {
+            (Some((lo1, hi1)), Some((lo2, hi2))) => lo1 <= hi2 && lo2 <= hi1,
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    /// The lookaheads matched by both members, `None` if they don't overlap.
+    pub fn intersection(&self, other: &SetMember) -> Option<SetMember> {
+        match (self.bounds(), other.bounds()) {
+            (Some((lo1, hi1)), Some((lo2, hi2))) if self.overlaps(other) => {
+                Some(SetMember::from_range(lo1.max(lo2), hi1.min(hi2)))
+            }
+            (None, None) => Some(SetMember::Terminate),
+            _ => None,
+        }
+    }
+
+    /// The smallest char matched by both members, used to name a conflict.
+    pub fn first_shared_char(&self, other: &SetMember) -> Option<char> {
+        match (self.bounds(), other.bounds()) {
+            (Some((lo1, _)), Some((lo2, _))) if self.overlaps(other) => Some(lo1.max(lo2)),
+            _ => None,
+        }
+    }
+}
+
+impl SetMemberWithEmpty {
+    /// A `Char` for single-char ranges, a `Range` otherwise.
+    pub fn from_range(lo: char, hi: char) -> SetMemberWithEmpty {
+        if lo == hi {
+            SetMemberWithEmpty::Char(lo)
+        } else {
+            SetMemberWithEmpty::Range(lo, hi)
+        }
+    }
+}
+
 impl Into<String> for SetMember {
     fn into(self) -> String {
         match self {
             SetMember::Char(x) => String::from(x),
+            SetMember::Range(lo, hi) => format!("{lo}-{hi}"),
             SetMember::Terminate => String::from("terminate"),
         }
     }
@@ -77,6 +148,7 @@ impl TryFrom<SetMemberWithEmpty> for SetMember {
     fn try_from(value: SetMemberWithEmpty) -> Result<Self, Self::Error> {
         match value {
             SetMemberWithEmpty::Char(x) => Ok(SetMember::Char(x)),
+            SetMemberWithEmpty::Range(lo, hi) => Ok(SetMember::Range(lo, hi)),
             SetMemberWithEmpty::Empty => Err("was empty"),
             SetMemberWithEmpty::Terminate => Ok(SetMember::Terminate),
         }
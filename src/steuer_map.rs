@@ -2,17 +2,21 @@
 //!
 //! A steuer map links a lookahead symbol to a specific production. This module
 //! turns FIRST/FOLLOW/Steuer information into executable tables used by Parser.
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::rc::Rc;
 
-use crate::parser_data::{ElementData, ElementIndex, ElementType, ParserData};
+use crate::parser_data::{ElementIndex, ElementType, ParserData};
 use crate::vms::VM;
 
 use crate::errors::GrammarError;
 use crate::errors::GrammarError::{MissingFollowSet, MissingSteuerSet, SteuerSetsNotDistinct};
+use crate::first_sets::first_set_of_partial;
 use crate::parser_data::Production;
-use crate::sets::{NamedSets, NamedSetsNoEmpty, SetMember};
+use crate::lookahead::{LookaheadTable, deepest_lookahead_match, find_in_lookahead_table};
+use crate::peekables::TPeekable;
+use crate::sets::{NamedSets, NamedSetsNoEmpty, SetMember, SetMemberWithEmpty};
 use crate::steuer_sets::get_steuer_sets;
+use crate::terminal_trie::TerminalTrie;
 
 /// Forgot what NT means here. NonTerminal?
 pub struct NTRules<T>
@@ -22,9 +26,95 @@ where
     pub steuermap: Steuermap,
     pub ignore: Option<ElementIndex>,
     pub instruction: Vec<T::Tinstrution>,
+    pub flatten: bool,
+    /// Run the instructions of this rule instead, see
+    /// [`NonTerminalRules::instructions_of`](crate::parser_data::NonTerminalRules::instructions_of).
+    pub instructions_of: Option<ElementIndex>,
+    /// Tries for the chars that start terminals of more than one production.
+    pub tries: TerminalTries,
+    /// Used instead of the steuer map by LL(k) parsers, see [`crate::lookahead`].
+    pub lookahead: LookaheadTable,
+}
+
+impl<T> NTRules<T>
+where
+    T: VM,
+{
+    /// Finds the production for the upcoming input. If the next char starts terminals of several
+    /// productions, the longest matching terminal decides, otherwise the steuer map.
+    pub fn find_production<P>(&self, to_parse: &mut P) -> Option<&Rc<Production>>
+    where
+        P: TPeekable<Item = char>,
+    {
+        if !self.lookahead.is_empty() {
+            return find_in_lookahead_table(&self.lookahead, to_parse);
+        }
+        let cur = SetMember::from(to_parse.peek());
+        if let SetMember::Char(chr) = cur
+            && let Some(trie) = self.tries.get(&chr)
+        {
+            return trie.longest_match(to_parse);
+        }
+        find_in_steuermap(&self.steuermap, &cur)
+    }
+
+    /// Where the upcoming input stops fitting the rule when no production fits it: how many
+    /// chars still fit and what could have come next instead. Only tries and LL(k) lookaheads
+    /// read more than one char, otherwise this is `(0, [])`.
+    pub fn mismatch<P>(&self, to_parse: &mut P) -> (usize, Vec<SetMember>)
+    where
+        P: TPeekable<Item = char>,
+    {
+        if !self.lookahead.is_empty() {
+            return deepest_lookahead_match(&self.lookahead, to_parse);
+        }
+        if let Some(trie) = to_parse.peek().and_then(|chr| self.tries.get(chr)) {
+            let (ahead, next_chars) = trie.deepest_match(to_parse);
+            return (ahead, next_chars.into_iter().map(SetMember::Char).collect());
+        }
+        (0, vec![])
+    }
+
+    /// Every production of the rule once, ordered by their elements with the empty one last.
+    pub fn productions(&self) -> Vec<&Rc<Production>> {
+        let trie_entries = self.tries.values().flat_map(TerminalTrie::entries);
+        let mut productions: Vec<_> = self
+            .steuermap
+            .values()
+            .chain(self.lookahead.iter().map(|(_, production)| production))
+            .chain(trie_entries.map(|(_, production)| production))
+            .map(|production| match &**production {
+                Production::NotEmpty(el_ixs) => ((false, el_ixs.clone()), production),
+                Production::Empty => ((true, vec![]), production),
+            })
+            .collect();
+        productions.sort_by(|(first, _), (second, _)| first.cmp(second));
+        productions.dedup_by(|(first, _), (second, _)| first == second);
+        productions
+            .into_iter()
+            .map(|(_, production)| production)
+            .collect()
+    }
 }
 
 pub type Steuermap = HashMap<SetMember, Rc<Production>>;
+pub type TerminalTries = HashMap<char, TerminalTrie>;
+
+/// Looks up the production for the current lookahead.
+///
+/// Exact keys are found by hashing. Keys contributed by character classes are ranges and are
+/// scanned if there is no exact hit.
+pub fn find_in_steuermap<'m>(
+    steuermap: &'m Steuermap,
+    cur: &SetMember,
+) -> Option<&'m Rc<Production>> {
+    steuermap.get(cur).or_else(|| {
+        steuermap
+            .iter()
+            .find(|(key, _)| key.overlaps(cur))
+            .map(|(_, production)| production)
+    })
+}
 
 pub fn get_steuermaps<T>(
     first_sets: &NamedSets,
@@ -36,101 +126,335 @@ where
 {
     let steuer_sets = get_steuer_sets(first_sets, follow_sets)?;
     let mut steuer_maps = HashMap::new();
+    for &rule_name in parser_data.parse_rules.rules.keys() {
+        let steuermap = steuermap_of_rule(
+            &steuer_sets,
+            first_sets,
+            follow_sets,
+            rule_name,
+            &parser_data,
+        )?;
+        steuer_maps.insert(rule_name, steuermap);
+    }
+    let mut rules_with_steuermaps = HashMap::new();
     for (rule_name, productions) in parser_data.parse_rules.rules.into_iter() {
-        let mut steuermap = Steuermap::new();
-
-        for prod in productions.possible_productions.into_iter() {
-            steuermap_of_production(
-                &steuer_sets,
-                follow_sets,
-                &mut steuermap,
-                prod,
-                rule_name,
-                &parser_data.element_types,
-                &parser_data.element_data,
-            )?;
-        }
-        steuer_maps.insert(
+        let (steuermap, tries) = steuer_maps.remove(&rule_name).unwrap_or_default();
+        rules_with_steuermaps.insert(
             rule_name,
             NTRules {
                 steuermap,
                 ignore: productions.ignore,
                 instruction: productions.instruction,
+                flatten: productions.flatten,
+                instructions_of: productions.instructions_of,
+                tries,
+                lookahead: vec![],
             },
         );
     }
-    Ok(steuer_maps)
+    Ok(rules_with_steuermaps)
 }
 
-fn steuermap_of_production(
+/// Builds the steuer maps of all rules like [`get_steuermaps`], but does not stop at the first
+/// rule that fails. Returns the failing rules with their error, at most one per rule.
+pub fn find_steuer_conflicts<T>(
+    first_sets: &NamedSets,
+    follow_sets: &NamedSetsNoEmpty,
+    parser_data: &ParserData<T>,
+) -> Result<Vec<(ElementIndex, GrammarError)>, GrammarError>
+where
+    T: VM,
+{
+    let steuer_sets = get_steuer_sets(first_sets, follow_sets)?;
+    let mut rule_indexes: Vec<ElementIndex> = parser_data.parse_rules.rules.keys().copied().collect();
+    rule_indexes.sort_unstable();
+    Ok(rule_indexes
+        .into_iter()
+        .filter_map(|rule_name| {
+            steuermap_of_rule(
+                &steuer_sets,
+                first_sets,
+                follow_sets,
+                rule_name,
+                parser_data,
+            )
+            .err()
+            .map(|err| (rule_name, err))
+        })
+        .collect())
+}
+
+pub(crate) fn steuermap_of_rule<T>(
     steuer_sets: &NamedSetsNoEmpty,
+    first_sets: &NamedSets,
     follow_sets: &NamedSetsNoEmpty,
-    steuer_map: &mut Steuermap,
-    prod: Rc<Production>,
+    rule_name: ElementIndex,
+    parser_data: &ParserData<T>,
+) -> Result<(Steuermap, TerminalTries), GrammarError>
+where
+    T: VM,
+{
+    let rule = parser_data
+        .get_rule_by_key(rule_name)
+        .ok_or(GrammarError::MissingProduction { index: rule_name })?;
+    let keys = rule
+        .possible_productions
+        .iter()
+        .map(|prod| steuer_keys_of_production(steuer_sets, follow_sets, prod, rule_name, parser_data))
+        .collect::<Result<Vec<_>, _>>()?;
+    let tries = terminal_tries(
+        &rule.possible_productions,
+        &keys,
+        rule_name,
+        first_sets,
+        follow_sets,
+        parser_data,
+    )?;
+
+    let name = rule_display_name(rule_name, parser_data);
+    let mut steuermap = Steuermap::new();
+    for (prod, (prod_keys, steuer_terminal)) in rule.possible_productions.iter().zip(keys) {
+        for key in prod_keys {
+            if let SetMember::Char(chr) = key
+                && tries.contains_key(&chr)
+            {
+                continue;
+            }
+            insert_steuer_key(&mut steuermap, key, prod, &steuer_terminal, &name)?;
+        }
+    }
+    Ok((steuermap, tries))
+}
+
+fn rule_display_name<T>(rule_name: ElementIndex, parser_data: &ParserData<T>) -> String
+where
+    T: VM,
+{
+    parser_data
+        .get_element_data(rule_name)
+        .map_or_else(|| rule_name.to_string(), |data| data.name.clone())
+}
+
+/// The lookahead keys that select a production, and what they come from: the first element of
+/// the production, or `follow_set` for the empty production. Empty terminals match without
+/// reading a char, so they are skipped, and a production of only empty terminals is treated
+/// like the empty production.
+pub(crate) fn steuer_keys_of_production<T>(
+    steuer_sets: &NamedSetsNoEmpty,
+    follow_sets: &NamedSetsNoEmpty,
+    prod: &Production,
     cur_rule_name: ElementIndex,
-    el_types: &[ElementType],
-    el_data: &[ElementData],
-) -> Result<(), GrammarError> {
-    let prod_ref = Rc::clone(&prod);
-    let prod = &*prod;
-    match prod {
-        Production::NotEmpty(el) => {
-            let first = el[0];
-            let et = el_types
-                .get(first)
-                .ok_or(GrammarError::MissingElementForIndex { index: first })?;
-            match et {
-                ElementType::Terminal => {
-                    let name = el_data.get(first).unwrap().name.clone();
-                    let old_key =
-                        steuer_map.insert(SetMember::Char(name.chars().next().unwrap()), prod_ref);
-                    if old_key.is_some() {
-                        return Err(SteuerSetsNotDistinct {
-                            steuer_terminal: name.clone(),
-                            steuer_char: name.chars().next().unwrap(),
-                            rule_name: cur_rule_name.to_string(),
-                        });
-                    }
+    parser_data: &ParserData<T>,
+) -> Result<(Vec<SetMember>, String), GrammarError>
+where
+    T: VM,
+{
+    let first = match prod {
+        Production::NotEmpty(el) => el.iter().copied().find(|&ix| {
+            parser_data.element_types.get(ix) != Some(&ElementType::Terminal)
+                || parser_data
+                    .get_element_data(ix)
+                    .is_some_and(|data| !data.name.is_empty())
+        }),
+        Production::Empty => None,
+    };
+    let Some(first) = first else {
+        let follow_set = follow_sets.get(&cur_rule_name).ok_or(MissingFollowSet {
+            index: cur_rule_name,
+        })?;
+        return Ok((follow_set.iter().copied().collect(), String::from("follow_set")));
+    };
+    let et = parser_data
+        .element_types
+        .get(first)
+        .ok_or(GrammarError::MissingElementForIndex { index: first })?;
+    let name = parser_data.element_data[first].name.clone();
+    let keys = match et {
+        ElementType::Terminal => name
+            .chars()
+            .next()
+            .map(SetMember::Char)
+            .into_iter()
+            .collect(),
+        ElementType::CharClass => parser_data
+            .get_char_class(first)
+            .ok_or(GrammarError::MissingElementForIndex { index: first })?
+            .set_members(),
+        ElementType::NonTerminal => steuer_sets
+            .get(&first)
+            .ok_or(MissingSteuerSet { index: first })?
+            .iter()
+            .copied()
+            .collect(),
+    };
+    Ok((keys, name))
+}
+
+/// Builds a [`TerminalTrie`] for every char that more than one production can start with, as long
+/// as all of those productions start with terminals. Fails if two of the terminals clash, see
+/// [`terminals_clash`]. Chars shared with other productions are left to the steuer map, which
+/// reports them as conflicts.
+fn terminal_tries<T>(
+    productions: &[Rc<Production>],
+    keys: &[(Vec<SetMember>, String)],
+    rule_name: ElementIndex,
+    first_sets: &NamedSets,
+    follow_sets: &NamedSetsNoEmpty,
+    parser_data: &ParserData<T>,
+) -> Result<TerminalTries, GrammarError>
+where
+    T: VM,
+{
+    let mut shared: Vec<char> = keys
+        .iter()
+        .enumerate()
+        .flat_map(|(ix, (prod_keys, _))| {
+            prod_keys.iter().filter_map(move |key| match key {
+                SetMember::Char(chr)
+                    if keys[ix + 1..]
+                        .iter()
+                        .any(|(other, _)| other.iter().any(|other| other.overlaps(key))) =>
+                {
+                    Some(*chr)
                 }
-                ElementType::NonTerminal => {
-                    let steuer_menge = steuer_sets
-                        .get(&first)
-                        .ok_or(MissingSteuerSet { index: first })?;
-                    fill_with_steuer_set(steuer_menge, steuer_map, &prod_ref, cur_rule_name)?;
+                _ => None,
+            })
+        })
+        .collect();
+    shared.sort_unstable();
+    shared.dedup();
+
+    let mut tries = TerminalTries::new();
+    for chr in shared {
+        let key = SetMember::Char(chr);
+        let contenders: Option<Vec<(String, &Rc<Production>)>> = (0..productions.len())
+            .filter(|&ix| keys[ix].0.iter().any(|other| other.overlaps(&key)))
+            .map(|ix| {
+                leading_terminal(&productions[ix], parser_data).map(|t| (t, &productions[ix]))
+            })
+            .collect();
+        let Some(contenders) = contenders else {
+            continue;
+        };
+        let mut trie = TerminalTrie::new();
+        for (ix, (terminal, production)) in contenders.iter().enumerate() {
+            for (other, other_production) in &contenders[ix + 1..] {
+                let clash = terminals_clash(
+                    (terminal, production),
+                    (other, other_production),
+                    rule_name,
+                    first_sets,
+                    follow_sets,
+                    parser_data,
+                )?;
+                if clash {
+                    return Err(SteuerSetsNotDistinct {
+                        steuer_terminal: terminal.clone().max(other.clone()),
+                        steuer_char: chr,
+                        rule_name: rule_display_name(rule_name, parser_data),
+                    });
                 }
             }
-            Ok(())
+            trie.insert(terminal, production)
+                .map_err(|_| SteuerSetsNotDistinct {
+                    steuer_terminal: terminal.clone(),
+                    steuer_char: chr,
+                    rule_name: rule_display_name(rule_name, parser_data),
+                })?;
         }
-        Production::Empty => {
-            let follow_set = follow_sets.get(&cur_rule_name).ok_or(MissingFollowSet {
-                index: cur_rule_name,
-            })?;
-            fill_with_steuer_set(follow_set, steuer_map, &prod_ref, cur_rule_name)?;
+        tries.insert(chr, trie);
+    }
+    Ok(tries)
+}
 
-            Ok(())
-        }
+/// The terminal a production starts with, if its first element is a non-empty terminal.
+pub(crate) fn leading_terminal<T>(
+    production: &Production,
+    parser_data: &ParserData<T>,
+) -> Option<String>
+where
+    T: VM,
+{
+    let Production::NotEmpty(el_ixs) = production else {
+        return None;
+    };
+    let first = el_ixs[0];
+    if parser_data.element_types[first] != ElementType::Terminal {
+        return None;
     }
+    let name = &parser_data.get_element_data(first)?.name;
+    (!name.is_empty()).then(|| name.clone())
 }
 
-fn fill_with_steuer_set(
-    set_no_empty: &HashSet<SetMember>,
+/// True if the longest matching terminal can't choose between two productions of `rule` that
+/// start with the given terminals: the terminals are the same, or one is a prefix of the other
+/// and the production of the shorter one can go on with the next char of the longer one, either
+/// in the rest of the production or, if that can be empty, in the FOLLOW set of the rule. The
+/// longest match would take the longer terminal for input meant for the shorter one then.
+pub(crate) fn terminals_clash<T>(
+    (first, first_production): (&str, &Production),
+    (second, second_production): (&str, &Production),
+    rule: ElementIndex,
+    first_sets: &NamedSets,
+    follow_sets: &NamedSetsNoEmpty,
+    parser_data: &ParserData<T>,
+) -> Result<bool, GrammarError>
+where
+    T: VM,
+{
+    let (shorter, production, longer) = if first.len() <= second.len() {
+        (first, first_production, second)
+    } else {
+        (second, second_production, first)
+    };
+    let Some(rest) = longer.strip_prefix(shorter) else {
+        return Ok(false);
+    };
+    let Some(next) = rest.chars().next() else {
+        return Ok(true);
+    };
+    let Production::NotEmpty(el_ixs) = production else {
+        return Ok(false);
+    };
+    let next = SetMember::Char(next);
+    let after = first_set_of_partial(&el_ixs[1..], first_sets, parser_data)?;
+    let rest_can_start = after.iter().any(|member| match *member {
+        SetMemberWithEmpty::Char(chr) => next.overlaps(&SetMember::Char(chr)),
+        SetMemberWithEmpty::Range(lo, hi) => next.overlaps(&SetMember::Range(lo, hi)),
+        SetMemberWithEmpty::Empty | SetMemberWithEmpty::Terminate => false,
+    });
+    let rest_can_be_empty = el_ixs.len() == 1 || after.contains(&SetMemberWithEmpty::Empty);
+    if rest_can_start || !rest_can_be_empty {
+        return Ok(rest_can_start);
+    }
+    let follow = follow_sets
+        .get(&rule)
+        .ok_or(MissingFollowSet { index: rule })?;
+    Ok(follow.iter().any(|member| member.overlaps(&next)))
+}
+
+/// Inserts a key into a steuer map. Fails if the key overlaps a key of a different production.
+fn insert_steuer_key(
     steuer_map: &mut Steuermap,
+    key: SetMember,
     prod: &Rc<Production>,
-    cur_rule_name: ElementIndex,
+    steuer_terminal: &str,
+    rule_name: &str,
 ) -> Result<(), GrammarError> {
-    for follow_char in set_no_empty {
-        let old_key = steuer_map.insert(*follow_char, Rc::clone(&prod));
-        if old_key.is_some() {
-            return Err(SteuerSetsNotDistinct {
-                steuer_terminal: String::from("follow_set"),
-                steuer_char: match follow_char {
-                    SetMember::Char(x) => *x,
-                    SetMember::Terminate => '#',
-                },
-                rule_name: cur_rule_name.to_string(),
-            });
-        }
+    let clash = steuer_map
+        .iter()
+        .find(|(existing, existing_prod)| {
+            existing.overlaps(&key) && !Rc::ptr_eq(existing_prod, prod)
+        })
+        .map(|(existing, _)| *existing);
+    if let Some(existing) = clash {
+        return Err(SteuerSetsNotDistinct {
+            steuer_terminal: String::from(steuer_terminal),
+            steuer_char: existing.first_shared_char(&key).unwrap_or('#'),
+            rule_name: String::from(rule_name),
+        });
     }
+    steuer_map.insert(key, Rc::clone(prod));
     Ok(())
 }
 
@@ -141,8 +465,12 @@ mod tests {
 
     use crate::first_sets::get_first_sets;
     use crate::follow_sets::get_follow_sets;
+    use crate::errors::GrammarError;
+    use crate::parser_data::Production;
     use crate::rule_parsing::RuleParser;
-    use crate::steuer_map::get_steuermaps;
+    use crate::sets::SetMember;
+    use crate::steuer_map::{Steuermap, find_in_steuermap, get_steuermaps};
+    use std::rc::Rc;
 
     //TODO think how to bring this test back
 
@@ -215,6 +543,72 @@ a_a -> \"a\";\
         let _steuer_maps = get_steuermaps(&first_dict, &follow_dict, parser_data).unwrap();
     }
 
+    #[test]
+    fn test_char_class_overlapping_terminal() {
+        let to_parse = "start -> a|b;\
+a -> \"5\";\
+b -> [0-9];\
+";
+
+        let mut peekable = PeekableWrapper::from_str(to_parse);
+        let vm = NullVm::new();
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+        let parser_data = rule_parser.parser_data;
+        let first_dict = get_first_sets(&parser_data).unwrap();
+        let follow_dict = get_follow_sets(
+            parser_data.get_element_nt_index("start").unwrap(),
+            &first_dict,
+            &parser_data,
+        )
+        .unwrap();
+        match get_steuermaps(&first_dict, &follow_dict, parser_data) {
+            Err(GrammarError::SteuerSetsNotDistinct { steuer_char, .. }) => {
+                assert_eq!('5', steuer_char);
+            }
+            _ => panic!("overlapping class and terminal must conflict"),
+        }
+    }
+
+    #[test]
+    fn test_conflict_in_group_names_enclosing_rule() {
+        let to_parse = "start -> \"a\" (\"b\" \"c\" | (\"d\" | \"b\"));";
+
+        let mut peekable = PeekableWrapper::from_str(to_parse);
+        let vm = NullVm::new();
+        let mut rule_parser = RuleParser::new(&mut peekable, &vm);
+        rule_parser.parse_rules().unwrap();
+        let parser_data = rule_parser.parser_data;
+        let first_dict = get_first_sets(&parser_data).unwrap();
+        let follow_dict = get_follow_sets(
+            parser_data.get_element_nt_index("start").unwrap(),
+            &first_dict,
+            &parser_data,
+        )
+        .unwrap();
+        match get_steuermaps(&first_dict, &follow_dict, parser_data) {
+            Err(GrammarError::SteuerSetsNotDistinct {
+                steuer_char,
+                rule_name,
+                ..
+            }) => {
+                assert_eq!('b', steuer_char);
+                assert_eq!("start/(1)", rule_name);
+            }
+            _ => panic!("both alternatives of the group start with b"),
+        }
+    }
+
+    #[test]
+    fn test_find_in_steuermap_by_range() {
+        let production = Rc::new(Production::Empty);
+        let mut steuermap = Steuermap::new();
+        steuermap.insert(SetMember::Range('a', 'z'), Rc::clone(&production));
+        assert!(find_in_steuermap(&steuermap, &SetMember::Char('q')).is_some());
+        assert!(find_in_steuermap(&steuermap, &SetMember::Char('Q')).is_none());
+        assert!(find_in_steuermap(&steuermap, &SetMember::Terminate).is_none());
+    }
+
     #[test]
     fn test_steuer_map_list() {
         let rules = "start      -> list ;\
@@ -255,7 +649,8 @@ whitespace -> \" \";";
         let mut peekable =
             PeekableWrapper::from_str(to_parse);
         let mut vm = NullVm::new();
-        let rule_parser = RuleParser::new(&mut peekable, &mut vm);
+        let mut rule_parser = RuleParser::new(&mut peekable, &mut vm);
+        rule_parser.parse_rules().unwrap();
         let parser_data = rule_parser.parser_data;
         let first_dict = get_first_sets(&parser_data).unwrap();
         let follow_dict = get_follow_sets(
@@ -291,7 +686,8 @@ whitespace -> \" \";";
         let mut peekable =
             PeekableWrapper::from_str(to_parse);
         let mut vm = NullVm::new();
-        let rule_parser = RuleParser::new(&mut peekable, &mut vm);
+        let mut rule_parser = RuleParser::new(&mut peekable, &mut vm);
+        rule_parser.parse_rules().unwrap();
         let parser_data = rule_parser.parser_data;
         let first_dict = get_first_sets(&parser_data).unwrap();
         let follow_dict = get_follow_sets(
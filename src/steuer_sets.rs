@@ -228,7 +228,7 @@ whitespace -> \" \";";
                 .clone()
         );
         assert_eq!(
-            make_memberset_no_empty("p+-0123456789! "),
+            make_memberset_no_empty("p+-0123456789!"),
             steuer_dict
                 .get(&parser_data.get_element_nt_index("terms_s").unwrap())
                 .unwrap()
@@ -250,7 +250,7 @@ whitespace -> \" \";";
                 .clone()
         );
         assert_eq!(
-            make_memberset_no_empty("0123456789p+- "),
+            make_memberset_no_empty("0123456789 "),
             steuer_dict
                 .get(&parser_data.get_element_nt_index("number_s").unwrap())
                 .unwrap()
@@ -0,0 +1,183 @@
+//! Choose between alternatives by whole terminals instead of their first char.
+//!
+//! If several alternatives of a rule start with terminals that share their first char, like
+//! `"print" | "pop"`, one char of lookahead can't pick between them. The steuer map then hands
+//! that char to a [`TerminalTrie`], which reads as far ahead as needed and picks the alternative
+//! with the longest matching terminal.
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::parser_data::Production;
+use crate::peekables::TPeekable;
+
+/// The terminals alternatives of a rule start with, keyed by their chars.
+#[derive(Debug, Default)]
+pub struct TerminalTrie {
+    root: TrieNode,
+}
+
+#[derive(Debug, Default)]
+struct TrieNode {
+    children: HashMap<char, TrieNode>,
+    production: Option<Rc<Production>>,
+}
+
+impl TerminalTrie {
+    #[must_use]
+    pub fn new() -> TerminalTrie {
+        TerminalTrie::default()
+    }
+
+    /// Adds a terminal that starts `production`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the other production if a different production already starts with the same
+    /// terminal; then the next chars can't tell them apart.
+    pub fn insert(
+        &mut self,
+        terminal: &str,
+        production: &Rc<Production>,
+    ) -> Result<(), Rc<Production>> {
+        let mut node = &mut self.root;
+        for chr in terminal.chars() {
+            node = node.children.entry(chr).or_default();
+        }
+        match &node.production {
+            Some(existing) if !Rc::ptr_eq(existing, production) => Err(Rc::clone(existing)),
+            _ => {
+                node.production = Some(Rc::clone(production));
+                Ok(())
+            }
+        }
+    }
+
+    /// The production of the longest terminal the upcoming input starts with.
+    pub fn longest_match<P>(&self, to_parse: &mut P) -> Option<&Rc<Production>>
+    where
+        P: TPeekable<Item = char>,
+    {
+        let mut node = &self.root;
+        let mut found = None;
+        let mut ahead = 0;
+        while let Some(chr) = to_parse.peek_nth(ahead) {
+            let Some(next) = node.children.get(chr) else {
+                break;
+            };
+            node = next;
+            if node.production.is_some() {
+                found = node.production.as_ref();
+            }
+            ahead += 1;
+        }
+        found
+    }
+
+    /// How many of the upcoming chars follow a path of the trie, and the chars that could have
+    /// come next there, sorted. Tells where the input stopped matching every terminal.
+    pub fn deepest_match<P>(&self, to_parse: &mut P) -> (usize, Vec<char>)
+    where
+        P: TPeekable<Item = char>,
+    {
+        let mut node = &self.root;
+        let mut ahead = 0;
+        while let Some(next) = to_parse
+            .peek_nth(ahead)
+            .and_then(|chr| node.children.get(chr))
+        {
+            node = next;
+            ahead += 1;
+        }
+        let mut next_chars: Vec<char> = node.children.keys().copied().collect();
+        next_chars.sort_unstable();
+        (ahead, next_chars)
+    }
+
+    /// All terminals in the trie, sorted. Used to tell what was expected.
+    #[must_use]
+    pub fn terminals(&self) -> Vec<String> {
+        self.entries()
+            .into_iter()
+            .map(|(terminal, _)| terminal)
+            .collect()
+    }
+
+    /// All terminals in the trie with the production they start, sorted by terminal.
+    #[must_use]
+    pub fn entries(&self) -> Vec<(String, &Rc<Production>)> {
+        let mut entries = vec![];
+        collect_entries(&self.root, &mut String::new(), &mut entries);
+        entries.sort_by(|(first, _), (second, _)| first.cmp(second));
+        entries
+    }
+}
+
+fn collect_entries<'t>(
+    node: &'t TrieNode,
+    prefix: &mut String,
+    entries: &mut Vec<(String, &'t Rc<Production>)>,
+) {
+    if let Some(production) = &node.production {
+        entries.push((prefix.clone(), production));
+    }
+    for (chr, child) in &node.children {
+        prefix.push(*chr);
+        collect_entries(child, prefix, entries);
+        prefix.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peekables::PeekableWrapper;
+
+    #[test]
+    fn test_longest_match() {
+        let print = Rc::new(Production::NotEmpty(vec![0]));
+        let pop = Rc::new(Production::NotEmpty(vec![1]));
+        let p = Rc::new(Production::NotEmpty(vec![2]));
+        let mut trie = TerminalTrie::new();
+        trie.insert("print", &print).unwrap();
+        trie.insert("pop", &pop).unwrap();
+        trie.insert("p", &p).unwrap();
+
+        let mut input = PeekableWrapper::from_str("pop 1");
+        assert!(Rc::ptr_eq(&pop, trie.longest_match(&mut input).unwrap()));
+        assert_eq!(Some('p'), input.next());
+
+        let mut input = PeekableWrapper::from_str("pri");
+        assert!(Rc::ptr_eq(&p, trie.longest_match(&mut input).unwrap()));
+
+        let mut input = PeekableWrapper::from_str("x");
+        assert!(trie.longest_match(&mut input).is_none());
+        assert_eq!(vec!["p", "pop", "print"], trie.terminals());
+    }
+
+    #[test]
+    fn test_deepest_match() {
+        let print = Rc::new(Production::NotEmpty(vec![0]));
+        let pop = Rc::new(Production::NotEmpty(vec![1]));
+        let mut trie = TerminalTrie::new();
+        trie.insert("print", &print).unwrap();
+        trie.insert("pop", &pop).unwrap();
+
+        let mut input = PeekableWrapper::from_str("px");
+        assert_eq!((1, vec!['o', 'r']), trie.deepest_match(&mut input));
+        let mut input = PeekableWrapper::from_str("prx");
+        assert_eq!((2, vec!['i']), trie.deepest_match(&mut input));
+        let mut input = PeekableWrapper::from_str("x");
+        assert_eq!((0, vec!['p']), trie.deepest_match(&mut input));
+    }
+
+    #[test]
+    fn test_same_terminal_for_different_productions() {
+        let first = Rc::new(Production::NotEmpty(vec![0]));
+        let second = Rc::new(Production::NotEmpty(vec![1]));
+        let mut trie = TerminalTrie::new();
+        trie.insert("pop", &first).unwrap();
+        trie.insert("pop", &first).unwrap();
+        let existing = trie.insert("pop", &second).unwrap_err();
+        assert!(Rc::ptr_eq(&first, &existing));
+    }
+}
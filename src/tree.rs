@@ -1,3 +1,10 @@
+//! The parse tree built by [`Parser`](crate::script_parser::Parser).
+//!
+//! Nodes live in one vector and are addressed by [`NodeId`]. Every node the parser adds knows
+//! the [`Span`] of input it was parsed from, so a VM can point at the source of a node. Trees of
+//! [`Parser::parse_recovering`](crate::script_parser::Parser::parse_recovering) also contain
+//! error nodes, see [`Node::is_error`].
+use crate::parse_funcs::Span;
 use crate::tree::TreeError::{ChildDoesNotExists, NodeDoesNotExist, NodeWasRemoved};
 use std::fmt;
 use std::sync::atomic::AtomicUsize;
@@ -40,6 +47,8 @@ pub struct Node<T> {
     first_child: Option<NodePtr>,
     last_child: Option<NodePtr>,
     pk: NodePk,
+    span: Option<Span>,
+    error: bool,
     pub data: T,
 }
 impl<T> Node<T> {
@@ -56,9 +65,22 @@ impl<T> Node<T> {
             previous_sibling,
             next_sibling: None,
             pk,
+            span: None,
+            error: false,
             data,
         }
     }
+
+    /// The input the node was parsed from. `None` for nodes that were not added by the parser.
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    /// True for the nodes error recovery adds to a rule that was cut short by a syntax error.
+    /// Their data is the input that was skipped.
+    pub fn is_error(&self) -> bool {
+        self.error
+    }
 }
 
 impl<T> Tree<T> {
@@ -126,6 +148,40 @@ impl<T> Tree<T> {
         Ok(NodeId::new(next_index, new_pk))
     }
 
+    /// Adds a node that was parsed from `span`.
+    pub fn add_node_with_span(
+        &mut self,
+        data: T,
+        parent: Option<NodeId>,
+        span: Span,
+    ) -> Result<NodeId, TreeError> {
+        let id = self.add_node(data, parent)?;
+        self.set_span(id, span)?;
+        Ok(id)
+    }
+
+    /// Adds an error node, see [`Node::is_error`].
+    pub fn add_error_node(
+        &mut self,
+        data: T,
+        parent: Option<NodeId>,
+        span: Span,
+    ) -> Result<NodeId, TreeError> {
+        let id = self.add_node_with_span(data, parent, span)?;
+        self.get_node_mut(id)?.error = true;
+        Ok(id)
+    }
+
+    /// The input a node was parsed from, see [`Node::span`].
+    pub fn span(&self, node_id: NodeId) -> Result<Option<Span>, TreeError> {
+        Ok(self.get_node(node_id)?.span)
+    }
+
+    pub fn set_span(&mut self, node_id: NodeId, span: Span) -> Result<(), TreeError> {
+        self.get_node_mut(node_id)?.span = Some(span);
+        Ok(())
+    }
+
     pub fn get_descendants(&self, node_id: NodeId) -> Vec<NodeId> {
         let mut descendants = vec![];
         let children = self.get_children(node_id);
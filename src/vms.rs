@@ -147,7 +147,8 @@ pub trait VM {
     /// # Arguments
     ///
     /// * `tree` - The parse tree, which can be used to access parsed data
-    /// * `cur_node` - The current node in the parse tree
+    /// * `cur_node` - The current node in the parse tree. `tree.span(cur_node)` tells where in
+    ///   the input it was parsed from, down to line and column, e.g. for runtime errors
     /// * `instruction` - The instruction to execute
     /// * `state` - The VM state to modify
     fn execute_instruction(